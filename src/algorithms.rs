@@ -1,6 +1,10 @@
 pub mod gzip;
 pub mod bzip2;
 pub mod xz2;
+pub mod zstd;
+pub mod lz4;
+pub mod brotli;
+pub mod stored;
 #[cfg(feature = "image")]
 pub mod png;
 #[cfg(feature = "image")]
@@ -9,31 +13,64 @@ pub mod felics;
 pub mod jpegxl;
 #[cfg(feature = "image")]
 pub mod losslessjpeg;
+#[cfg(test)]
+pub(crate) mod mock;
 
 use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::fs::File;
+use std::io;
+use std::io::Write;
 use std::time::Duration;
+use crate::container::AlgorithmId;
 use crate::convex_hull::Point;
+use crate::error::MixError;
+use crate::timing::Timing;
 use crate::workload::{FolderWorkload, Workload};
 
 pub type ByteSize = u64;
 
-/// Defines compression algorithms
-pub trait Algorithm: Debug {
+/// Defines compression algorithms.
+/// Algorithms are shared between threads, to run the halves of a mix concurrently.
+pub trait Algorithm: Debug + Send + Sync {
     fn name(&self) -> String;
-    /// Estimates the compressed size obtained by running this algorithm on workload w.
-    fn compressed_size(&self) -> ByteSize;
-    /// Estimates the time budget required to execute this algorithm on workload w.
-    fn time_required(&self) -> Duration;
     /// Runs the compression algorithm on some workload.
-    fn execute(&self, w: &mut Workload);
-    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> File;
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError>;
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError>;
 
     /// Runs the compression algorithm on some workload, by writing on a cursor target to optimize memory writes.
-    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool);
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError>;
 
-    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> u64;
+    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> Result<u64, MixError>;
+
+    /// Compresses a buffer into a [CountingSink] and returns the compressed size, to time the compression alone (see [crate::timing::TimingMode::InMemory]).
+    /// Setups that don't support it are timed with [Algorithm::execute_on_tmp] instead.
+    fn compress_in_memory(&self, _data: &[u8]) -> Result<ByteSize, MixError> {
+        Err(MixError::UnsupportedMode(format!("{} doesn't support in memory compression", self.name())))
+    }
+
+    /// The algorithm of the segments written by [Algorithm::execute_with_target], whose decoder also reads the output of [Algorithm::execute_on_tmp].
+    /// None for setups that can't be decoded.
+    fn segment_algorithm(&self) -> Option<AlgorithmId> {
+        None
+    }
+}
+
+/// Discards the compressed data written to it, only counting its bytes.
+#[derive(Debug, Default)]
+pub struct CountingSink {
+    pub bytes: ByteSize,
+}
+
+impl Write for CountingSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes += buf.len() as ByteSize;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 
@@ -43,16 +80,49 @@ pub struct AlgorithmMetrics {
     pub compressed_size: ByteSize,
     pub time_required: Duration,
     pub algorithm: Box<dyn Algorithm>,
+    /// Uncertainty of estimated metrics, none if they were measured or supplied by the user
+    pub confidence_interval: Option<ConfidenceInterval>,
+    /// How the time required was measured, none if the metrics were supplied by the user or predicted
+    pub timing: Option<Timing>,
+    /// Time required to decompress the whole result, none if it wasn't measured (or supplied) or if the setup can't be decoded
+    pub decompression_time: Option<Duration>,
 }
 
 impl AlgorithmMetrics {
-    pub fn new(algorithm: Box<dyn Algorithm>) -> AlgorithmMetrics {
+    pub fn new(algorithm: Box<dyn Algorithm>, compressed_size: ByteSize, time_required: Duration) -> AlgorithmMetrics {
         AlgorithmMetrics {
-            compressed_size: algorithm.compressed_size(),
-            time_required: algorithm.time_required(),
+            compressed_size,
+            time_required,
             algorithm,
+            confidence_interval: None,
+            timing: None,
+            decompression_time: None,
         }
     }
+
+    pub fn with_confidence_interval(mut self, confidence_interval: Option<ConfidenceInterval>) -> AlgorithmMetrics {
+        self.confidence_interval = confidence_interval;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: Option<Timing>) -> AlgorithmMetrics {
+        self.timing = timing;
+        self
+    }
+
+    pub fn with_decompression_time(mut self, decompression_time: Option<Duration>) -> AlgorithmMetrics {
+        self.decompression_time = decompression_time;
+        self
+    }
+}
+
+/// Half widths of the 95% confidence intervals of estimated metrics, along with the number of blocks the estimate is based on.
+/// The actual metrics are expected to be within `compressed_size ± self.compressed_size` and `time_required ± self.time_required`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ConfidenceInterval {
+    pub compressed_size: ByteSize,
+    pub time_required: Duration,
+    pub samples: u64,
 }
 
 impl PartialOrd for AlgorithmMetrics {
@@ -89,12 +159,23 @@ impl Point for AlgorithmMetrics {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct EstimateMetadata {
     pub block_number: u64,
     pub block_ratio: f64,
 }
 
+/// Estimation that keeps drawing blocks until the relative half width of the confidence intervals of both size and time is within the tolerance.
+/// Sampling also stops after `max_blocks` blocks, or once `time_cap` has been spent on the estimation of a setup.
+#[derive(Debug, Copy, Clone)]
+pub struct AdaptiveEstimateMetadata {
+    pub block_ratio: f64,
+    pub tolerance: f64,
+    pub max_blocks: u64,
+    pub time_cap: Option<Duration>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct BlockInfo {
     pub block_size: u64,
     pub block_end_index: u64,
@@ -0,0 +1,148 @@
+use std::cmp::min;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Instant;
+use tempfile::tempfile;
+use brotli::CompressorWriter;
+use crate::algorithms::{Algorithm, BlockInfo, ByteSize, CountingSink};
+use crate::container::{AlgorithmId, SegmentWriter};
+use crate::error::MixError;
+use crate::workload::{FolderWorkload, Workload};
+
+/// Window size (log2) used by the `brotli` program by default.
+pub const DEFAULT_WINDOW_SIZE: u32 = 22;
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// Brotli quality, from 0 to 11.
+#[derive(Debug)]
+pub struct BrotliCompressionLevel(pub u32);
+
+/// Brotli setup, the most expensive end of the hull for text data.
+/// The window size (log2, from 10 to 24) changes the speed/ratio trade-off of each quality, so every window size is a distinct setup.
+///
+/// Brotli streams can't be concatenated: two-part mixes can only be decoded through the mixed container.
+#[derive(Debug)]
+pub struct Brotli {
+    compression_level: BrotliCompressionLevel,
+    window_size: u32,
+}
+
+impl Brotli {
+    pub fn new(compression_level: BrotliCompressionLevel, window_size: u32) -> Brotli {
+        Brotli {
+            compression_level,
+            window_size,
+        }
+    }
+
+    /// The stream is closed by `into_inner`, which ignores write errors: flush it first to surface them.
+    fn encoder<W: Write>(&self, target: W) -> CompressorWriter<W> {
+        CompressorWriter::new(target, BUFFER_SIZE, self.compression_level.0, self.window_size)
+    }
+}
+
+impl Algorithm for Brotli {
+    fn name(&self) -> String {
+        format!("Brotli_{}_w{}", self.compression_level.0, self.window_size)
+    }
+
+    fn segment_algorithm(&self) -> Option<AlgorithmId> {
+        Some(AlgorithmId::Brotli)
+    }
+
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute: init {:?}", instant.elapsed());
+        let mut e = self.encoder(&mut w.result_file);
+        log::debug!("Execute: encoder created {:?}", instant.elapsed());
+        let mut pos = 0usize;
+        let data_len = w.data.metadata()?.len() as usize;
+        while pos < data_len {
+            let buffer_len = min(10_000_000, data_len - pos);
+            let mut buffer: Vec<u8> = vec![0; buffer_len];
+            w.data.read_exact(&mut buffer)?;
+            e.write_all(&buffer)?;
+            pos += buffer_len;
+            log::debug!("Execute: written {} bytes so far (time: {:?})", pos, instant.elapsed());
+        }
+        log::debug!("Execute: write_all done {:?}", instant.elapsed());
+        e.flush()?;
+        e.into_inner();
+        log::debug!("Execute: finished {:?}", instant.elapsed());
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute on tmp: init {:?}", instant.elapsed());
+        let tmpfile = tempfile()?;
+        let mut e = self.encoder(&tmpfile);
+        log::debug!("Execute on tmp: encoder created {:?}", instant.elapsed());
+        let block_info = block_info.unwrap_or(BlockInfo{block_size: w.data.metadata()?.len(), block_end_index: w.data.metadata()?.len()});
+        let mut start = block_info.block_end_index - block_info.block_size;
+        let data_len = block_info.block_end_index;
+
+        w.data.seek(SeekFrom::Start(start))?;
+        while start < data_len {
+            let buffer_len = min(10_000_000, data_len - start);
+            log::debug!("Execute on tmp: starting cycle (start: {start}, data_len: {data_len}, buffer_len: {buffer_len})");
+            let mut buffer: Vec<u8> = vec![0; buffer_len as usize];
+            w.data.read_exact(&mut buffer)?;
+            e.write_all(&buffer)?;
+            start += buffer_len;
+            log::debug!("Execute on tmp: written {} bytes so far (time: {:?})", start, instant.elapsed());
+        }
+        log::debug!("Execute on tmp: write_all done {:?}", instant.elapsed());
+        e.flush()?;
+        e.into_inner();
+        log::debug!("Execute on tmp: finished {:?}", instant.elapsed());
+        w.data.rewind()?;
+        Ok(tmpfile)
+    }
+
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute with target: init {:?}", instant.elapsed());
+        let mut segment = SegmentWriter::begin(&w.result_file, AlgorithmId::Brotli, self.compression_level.0)?;
+        let mut e = self.encoder(&w.result_file);
+        log::debug!("Execute with target: encoder created {:?}", instant.elapsed());
+        let (mut pos, data_len) = if first_half {
+            (0usize, partition)
+        } else {
+            (partition, w.data.metadata()?.len() as usize)
+        };
+        if !first_half {
+            w.data.seek(SeekFrom::Start(partition as u64))?;
+        }
+        while pos < data_len {
+            let buffer_len = min(1_000_000_000, data_len - pos);
+            let mut buffer: Vec<u8> = vec![0; buffer_len];
+            w.data.read_exact(&mut buffer)?;
+            segment.update(&buffer);
+            e.write_all(&buffer)?;
+            pos += buffer_len;
+            log::debug!("Execute with target: written {} bytes so far (time: {:?})", pos, instant.elapsed());
+        }
+        log::debug!("Execute with target: write_all done {:?}", instant.elapsed());
+        e.flush()?;
+        e.into_inner();
+        segment.finish()?;
+        log::debug!("Execute with target: finished {:?}", instant.elapsed());
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_folder(&self, _w: &mut FolderWorkload, _write_to_tmp: bool, _max_size: Option<u64>, _first_half: bool) -> Result<u64, MixError> {
+        Err(MixError::UnsupportedMode(format!("{} doesn't support folder workloads", self.name())))
+    }
+
+    fn compress_in_memory(&self, data: &[u8]) -> Result<ByteSize, MixError> {
+        let mut sink = CountingSink::default();
+        let mut e = self.encoder(&mut sink);
+        e.write_all(data)?;
+        e.flush()?;
+        e.into_inner();
+        Ok(sink.bytes)
+    }
+}
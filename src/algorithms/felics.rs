@@ -1,74 +1,31 @@
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use felics::compression::{ColorType, CompressDecompress, CompressedImage, PixelDepth};
 
 use image::{DynamicImage, ImageDecoder, ImageEncoder};
 use image::codecs::png::{PngDecoder, PngEncoder};
 pub use image::codecs::png::CompressionType as PNGCompressionType;
 pub use image::codecs::png::FilterType as PNGFilterType;
-use rand::Rng;
 use tempfile::tempfile;
 
-use crate::algorithms::{Algorithm, BlockInfo, ByteSize, EstimateMetadata};
+use crate::algorithms::{Algorithm, BlockInfo};
+use crate::algorithms::png::partition_rows;
+use crate::container::{AlgorithmId, SegmentWriter};
+use crate::error::MixError;
 use crate::workload::{FolderWorkload, Workload};
 
-#[derive(Debug)]
-pub struct FELICS {
-    compressed_size: Option<ByteSize>,
-    time_required: Option<Duration>,
-}
+#[derive(Debug, Default)]
+pub struct FELICS;
 
 impl FELICS {
-    pub fn new_folder_workload(workload: &mut FolderWorkload, estimate_metadata: Option<EstimateMetadata>) -> FELICS {
-        let mut felics = FELICS {
-            compressed_size: None,
-            time_required: None,
-        };
-        felics.calculate_metrics_folder(workload, estimate_metadata);
-        felics
-    }
-
-    // in this case EstimateMetadata block_ratio indicates the % of files from the folder to use, and block_number how many repetitions with different files
-    fn calculate_metrics_folder(&mut self, workload: &mut FolderWorkload, estimate_metadata: Option<EstimateMetadata>) {
-        log::info!("Calculating compressed size and time required for algorithm {:?} (workload \"{}\") (estimating: {})", self, workload.name, estimate_metadata.is_some());
-        let (compressed_size, time_required) = match estimate_metadata {
-            Some(_) => {
-                unimplemented!("Estimating time required and compressed size for folder workloads is currently not supported.")
-            }
-            None => {
-                let current_unix = Instant::now();
-                let result = self.execute_on_folder(workload, true, None, false);
-                (result, current_unix.elapsed())
-            }
-        };
-        log::info!("Compressed size and time required calculated for algorithm {:?}:\nCompressed size: {:?};\nTime required: {:?}", self, compressed_size as ByteSize, time_required);
-        self.compressed_size = Some(compressed_size as ByteSize);
-        self.time_required = Some(time_required);
-    }
-}
-
-impl Algorithm for FELICS {
-    fn name(&self) -> String {
-        "FELICS".to_string()
+    pub fn new() -> FELICS {
+        FELICS
     }
 
-    fn compressed_size(&self) -> ByteSize {
-        self.compressed_size.unwrap()
-    }
-
-    fn time_required(&self) -> Duration {
-        self.time_required.unwrap()
-    }
-
-    fn execute(&self, w: &mut Workload) {
-        let instant = Instant::now();
-        log::debug!("Execute: init {:?}", instant.elapsed());
-
-        let mut buffer = Vec::new();
-        w.data.read_to_end(&mut buffer).unwrap();
-        let image = image::load_from_memory(&buffer).unwrap();
+    /// Compresses an image with FELICS, serializing the result on the target.
+    fn compress_image(image: DynamicImage, target: &mut impl Write) -> Result<(), MixError> {
         let felics_image = match image {
             DynamicImage::ImageLuma8(image) => {
                 image.compress()
@@ -88,7 +45,7 @@ impl Algorithm for FELICS {
             DynamicImage::ImageRgba32F(_) => {image.to_rgb16().compress()}
             DynamicImage::ImageLumaA8(_) => {image.to_luma8().compress()}
             DynamicImage::ImageLumaA16(_) => {image.to_luma16().compress()}
-            _ => {panic!("Source image format not supported by FELICS!")}
+            _ => {return Err(MixError::UnsupportedColorType(format!("Source image format {:?} not supported by FELICS!", image.color())))}
         };
 
         let color_type_code: u8 = match felics_image.color_type {
@@ -102,125 +59,119 @@ impl Algorithm for FELICS {
         };
 
         // the felics library doesn't implement serde
-        w.result_file.write(&felics_image.width.to_be_bytes()).unwrap();
-        w.result_file.write(&felics_image.height.to_be_bytes()).unwrap();
-        w.result_file.write(&color_type_code.to_be_bytes()).unwrap();
-        w.result_file.write(&pixel_depth_code.to_be_bytes()).unwrap();
+        target.write_all(&felics_image.width.to_be_bytes())?;
+        target.write_all(&felics_image.height.to_be_bytes())?;
+        target.write_all(&color_type_code.to_be_bytes())?;
+        target.write_all(&pixel_depth_code.to_be_bytes())?;
 
-        w.result_file.write(&felics_image.channels.len().to_be_bytes()).unwrap();
+        target.write_all(&felics_image.channels.len().to_be_bytes())?;
         for channel in felics_image.channels {
-            w.result_file.write(&channel.pixel1.to_be_bytes()).unwrap();
-            w.result_file.write(&channel.pixel2.to_be_bytes()).unwrap();
-            w.result_file.write(&channel.data.len().to_be_bytes()).unwrap();
-            w.result_file.write(&channel.data.num_bytes().to_be_bytes()).unwrap();
-            w.result_file.write(&channel.data.as_raw_bytes()).unwrap();
+            target.write_all(&channel.pixel1.to_be_bytes())?;
+            target.write_all(&channel.pixel2.to_be_bytes())?;
+            target.write_all(&channel.data.len().to_be_bytes())?;
+            target.write_all(&channel.data.num_bytes().to_be_bytes())?;
+            target.write_all(&channel.data.as_raw_bytes())?;
         }
+        Ok(())
+    }
+}
+
+impl Algorithm for FELICS {
+    fn name(&self) -> String {
+        "FELICS".to_string()
+    }
+
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute: init {:?}", instant.elapsed());
+
+        let mut buffer = Vec::new();
+        w.data.read_to_end(&mut buffer)?;
+        let image = image::load_from_memory(&buffer)?;
+        FELICS::compress_image(image, &mut w.result_file)?;
 
         log::debug!("Execute: finished {:?}", instant.elapsed());
 
-        w.data.rewind().unwrap();
+        w.data.rewind()?;
+        Ok(())
     }
 
-    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> File {
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
         let instant = Instant::now();
         log::debug!("Execute on tmp: init {:?}", instant.elapsed());
 
-        let mut tmpfile = tempfile().unwrap();
+        let mut tmpfile = tempfile()?;
         let mut buffer = Vec::new();
-        w.data.read_to_end(&mut buffer).unwrap();
-        let image = image::load_from_memory(&buffer).unwrap();
-        let felics_image = match image {
-            DynamicImage::ImageLuma8(image) => {
-                image.compress()
-            }
-            DynamicImage::ImageLuma16(image) => {
-                image.compress()
-            }
-            DynamicImage::ImageRgb8(image) => {
-                image.compress()
-            }
-            DynamicImage::ImageRgb16(image) => {
-                image.compress()
-            },
-            DynamicImage::ImageRgba8(_) => {image.to_rgb8().compress()}
-            DynamicImage::ImageRgba16(_) => {image.to_rgb16().compress()}
-            DynamicImage::ImageRgb32F(_) => {image.to_rgb16().compress()}
-            DynamicImage::ImageRgba32F(_) => {image.to_rgb16().compress()}
-            DynamicImage::ImageLumaA8(_) => {image.to_luma8().compress()}
-            DynamicImage::ImageLumaA16(_) => {image.to_luma16().compress()}
-            _ => {panic!("Source image format not supported by FELICS!")}
-        };
+        w.data.read_to_end(&mut buffer)?;
+        let image = image::load_from_memory(&buffer)?;
+        FELICS::compress_image(image, &mut tmpfile)?;
 
-        let color_type_code: u8 = match felics_image.color_type {
-            ColorType::Gray => 0,
-            ColorType::Rgb => 1,
-        };
+        log::debug!("Execute: finished {:?}", instant.elapsed());
 
-        let pixel_depth_code: u8 = match felics_image.pixel_depth {
-            PixelDepth::Eight => 0,
-            PixelDepth::Sixteen => 1,
-        };
+        w.data.rewind()?;
+        Ok(tmpfile)
+    }
 
-        // the felics library doesn't implement serde
-        tmpfile.write(&felics_image.width.to_be_bytes()).unwrap();
-        tmpfile.write(&felics_image.height.to_be_bytes()).unwrap();
-        tmpfile.write(&color_type_code.to_be_bytes()).unwrap();
-        tmpfile.write(&pixel_depth_code.to_be_bytes()).unwrap();
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute with target: init {:?}", instant.elapsed());
 
-        tmpfile.write(&felics_image.channels.len().to_be_bytes()).unwrap();
-        for channel in felics_image.channels {
-            tmpfile.write(&channel.pixel1.to_be_bytes()).unwrap();
-            tmpfile.write(&channel.pixel2.to_be_bytes()).unwrap();
-            tmpfile.write(&channel.data.len().to_be_bytes()).unwrap();
-            tmpfile.write(&channel.data.num_bytes().to_be_bytes()).unwrap();
-            tmpfile.write(&channel.data.as_raw_bytes()).unwrap();
+        let mut buffer = Vec::new();
+        w.data.read_to_end(&mut buffer)?;
+        let image = image::load_from_memory(&buffer)?;
+        let (start_row, end_row) = partition_rows(image.height(), partition, w.data.metadata()?.len(), first_half);
+        if start_row == end_row {
+            log::debug!("Execute with target: empty partition, nothing to write");
+            w.data.rewind()?;
+            return Ok(());
         }
+        let band = image.crop_imm(0, start_row, image.width(), end_row - start_row);
 
-        log::debug!("Execute: finished {:?}", instant.elapsed());
-
-        w.data.rewind().unwrap();
-        tmpfile
-    }
+        let mut segment = SegmentWriter::begin(&w.result_file, AlgorithmId::Felics, 0)?;
+        segment.update(band.as_bytes());
+        FELICS::compress_image(band, &mut &w.result_file)?;
+        segment.finish()?;
 
-    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) {
-        unimplemented!()
+        log::debug!("Execute with target: finished {:?} - rows {}..{}", instant.elapsed(), start_row, end_row);
+        w.data.rewind()?;
+        Ok(())
     }
 
-    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> u64 {
+    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> Result<u64, MixError> {
         let mut size = 0;
         // read_dir doesn't guarantee any consistent order - sort files by size
         let mut files = Vec::new();
-        for path in w.get_data_folder() {
-            files.push(path.unwrap());
+        for path in w.get_data_folder()? {
+            let path = path?;
+            files.push((path.metadata()?.len(), path));
         }
-        files.sort_by_key(|a| a.metadata().unwrap().len());
+        files.sort_by_key(|(len, _)| *len);
         // If partially compressing the folder, partition the directory now
         if let Some(max_size) = max_size {
             let mut actual_files = Vec::new();
             let mut data_size = 0;
-            for path in files {
-                let len = path.metadata().unwrap().len();
+            for (len, path) in files {
                 if data_size < max_size && first_half || data_size > max_size && !first_half {
-                    actual_files.push(path);
+                    actual_files.push((len, path));
                 }
                 data_size += len;
             }
             files = actual_files;
         }
 
-        for direntry in files {
+        for (_, direntry) in files {
             let mut file_workload = Workload::new(
                 format!("{}-{:?}", w.name, direntry.file_name()),
-                File::open(direntry.path()).unwrap(),
+                File::open(direntry.path())?,
                 w.time_budget,
-                Some(File::create(Path::new("results").join(&w.name).join(direntry.file_name())).unwrap())
-            );
-            let result = if write_to_tmp { self.execute_on_tmp(&mut file_workload, None) } else {
-                self.execute(&mut file_workload);
+                Some(File::create(Path::new("results").join(&w.name).join(direntry.file_name()))?)
+            )?;
+            let result = if write_to_tmp { self.execute_on_tmp(&mut file_workload, None)? } else {
+                self.execute(&mut file_workload)?;
                 file_workload.result_file
             };
-            size += result.metadata().unwrap().len();
+            size += result.metadata()?.len();
         }
-        size
+        Ok(size)
     }
 }
\ No newline at end of file
@@ -1,12 +1,13 @@
 use std::cmp::min;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use flate2::Compression;
 use flate2::write::GzEncoder;
-use rand::Rng;
 use tempfile::tempfile;
-use crate::algorithms::{Algorithm, BlockInfo, ByteSize, EstimateMetadata};
+use crate::algorithms::{Algorithm, BlockInfo, ByteSize, CountingSink};
+use crate::container::{AlgorithmId, SegmentWriter};
+use crate::error::MixError;
 use crate::workload::{FolderWorkload, Workload};
 
 #[derive(Debug)]
@@ -14,54 +15,13 @@ pub struct GzipCompressionLevel(pub u32);
 #[derive(Debug)]
 pub struct Gzip {
     compression_level: GzipCompressionLevel,
-    compressed_size: Option<ByteSize>,
-    time_required: Option<Duration>
 }
 
 impl Gzip {
-    pub fn new(workload: &mut Workload, compression_level: GzipCompressionLevel, estimate_metadata: Option<EstimateMetadata>) -> Gzip {
-
-        let mut gzip = Gzip {
+    pub fn new(compression_level: GzipCompressionLevel) -> Gzip {
+        Gzip {
             compression_level,
-            compressed_size: None,
-            time_required: None
-        };
-        gzip.calculate_metrics(workload, estimate_metadata);
-        gzip
-    }
-
-    fn calculate_metrics(&mut self, workload: &mut Workload, estimate_metadata: Option<EstimateMetadata>) {
-        log::info!("Calculating compressed size and time required for algorithm {:?} (workload \"{}\") (estimating: {})", self, workload.name, estimate_metadata.is_some());
-        let (compressed_size, time_required) = match estimate_metadata {
-            Some(metadata) => {
-                let mut average_compressed_size = 0;
-                let mut average_time_required = 0.;
-                let current_unix = Instant::now();
-                log::debug!("Estimating metrics by using {} blocks of ratio {}", metadata.block_number, metadata.block_ratio);
-                for _ in 0..metadata.block_number {
-                    let workload_size = workload.data.metadata().unwrap().len();
-                    let block_size = (workload_size as f64 * metadata.block_ratio).round() as u64;
-                    let block_end_index = rand::thread_rng().gen_range(block_size..workload_size);
-                    let current_unix = Instant::now();
-                    let block_compressed_size = self.execute_on_tmp(workload, Some(BlockInfo{ block_size, block_end_index })).metadata().unwrap().len();
-                    let time = current_unix.elapsed().as_secs_f64();
-                    average_time_required += time;
-                    average_compressed_size += block_compressed_size;
-                }
-                average_compressed_size = ((average_compressed_size as f64 / metadata.block_number as f64) * (1./metadata.block_ratio).round()) as u64;
-                average_time_required = (average_time_required / metadata.block_number as f64) * (1./metadata.block_ratio);
-                log::debug!("Final metrics:\nCompressed size: {}\nTime required: {}\nTime taken for estimation: {:?}", average_compressed_size, average_time_required, current_unix.elapsed());
-                (average_compressed_size, Duration::from_secs_f64(average_time_required))
-            },
-            None => {
-                let current_unix = Instant::now();
-                let result = self.execute_on_tmp(workload, None).metadata().unwrap().len();
-                (result, current_unix.elapsed())
-            }
-        };
-        log::info!("Compressed size and time required calculated for algorithm {:?}:\nCompressed size: {:?};\nTime required: {:?}", self, compressed_size as ByteSize, time_required);
-        self.compressed_size = Some(compressed_size as ByteSize);
-        self.time_required = Some(time_required);
+        }
     }
 }
 impl Algorithm for Gzip {
@@ -69,90 +29,99 @@ impl Algorithm for Gzip {
         format!("Gzip_{}", self.compression_level.0)
     }
 
-    fn compressed_size(&self) -> ByteSize {
-        self.compressed_size.unwrap()
-    }
-
-    fn time_required(&self) -> Duration {
-        self.time_required.unwrap()
+    fn segment_algorithm(&self) -> Option<AlgorithmId> {
+        Some(AlgorithmId::Gzip)
     }
 
-    fn execute(&self, w: &mut Workload) {
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError> {
         let instant = Instant::now();
         log::debug!("Execute: init {:?}", instant.elapsed());
         let mut e = GzEncoder::new(&mut w.result_file, Compression::new(self.compression_level.0));
         log::debug!("Execute: encoder created {:?}", instant.elapsed());
         let mut pos = 0usize;
-        let data_len = w.data.metadata().unwrap().len() as usize;
+        let data_len = w.data.metadata()?.len() as usize;
         while pos < data_len {
             let buffer_len = min(10_000_000, data_len - pos);
             let mut buffer: Vec<u8> = vec![0; buffer_len];
-            w.data.read_exact(&mut buffer).expect(&*format!("Something went wrong while compressing data for workload \"{}\"", w.name));
-            e.write_all(&*buffer).expect(&*format!("Something went wrong while writing compressed data for workload \"{}\"", w.name));
+            w.data.read_exact(&mut buffer)?;
+            e.write_all(&*buffer)?;
             pos += buffer_len;
             log::debug!("Execute: written {} bytes so far (time: {:?})", pos, instant.elapsed());
         }
         log::debug!("Execute: write_all done {:?}", instant.elapsed());
-        e.finish().unwrap();
+        e.finish()?;
         log::debug!("Execute: finished {:?}", instant.elapsed());
-        w.data.rewind().unwrap();
+        w.data.rewind()?;
+        Ok(())
     }
 
-    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> File {
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
         let instant = Instant::now();
         log::debug!("Execute on tmp: init {:?}", instant.elapsed());
-        let tmpfile = tempfile().unwrap();
+        let tmpfile = tempfile()?;
         let mut e = GzEncoder::new(&tmpfile, Compression::new(self.compression_level.0));
         log::debug!("Execute on tmp: encoder created {:?}", instant.elapsed());
-        let block_info = block_info.unwrap_or(BlockInfo{block_size: w.data.metadata().unwrap().len(), block_end_index: w.data.metadata().unwrap().len()});
+        let block_info = block_info.unwrap_or(BlockInfo{block_size: w.data.metadata()?.len(), block_end_index: w.data.metadata()?.len()});
         let mut start = block_info.block_end_index - block_info.block_size;
         let data_len = block_info.block_end_index;
 
-        w.data.seek(SeekFrom::Start(start)).unwrap();
+        w.data.seek(SeekFrom::Start(start))?;
         while start < data_len {
             let buffer_len = min(10_000_000, data_len - start);
             let mut buffer: Vec<u8> = vec![0; buffer_len as usize];
-            w.data.read_exact(&mut buffer).expect(&*format!("Something went wrong while compressing data for workload \"{}\"", w.name));
-            e.write_all(&*buffer).expect(&*format!("Something went wrong while writing compressed data for workload \"{}\"", w.name));
+            w.data.read_exact(&mut buffer)?;
+            e.write_all(&*buffer)?;
             start += buffer_len;
             log::debug!("Execute on tmp: written {} bytes so far (time: {:?})", start, instant.elapsed());
         }
         log::debug!("Execute on tmp: write_all done {:?}", instant.elapsed());
-        e.finish().unwrap();
+        e.finish()?;
         log::debug!("Execute on tmp: finished {:?}", instant.elapsed());
-        w.data.rewind().unwrap();
-        tmpfile
+        w.data.rewind()?;
+        Ok(tmpfile)
     }
 
-    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) {
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError> {
         let instant = Instant::now();
         log::debug!("Execute with target: init {:?}", instant.elapsed());
+        let mut segment = SegmentWriter::begin(&w.result_file, AlgorithmId::Gzip, self.compression_level.0)?;
         let mut e = GzEncoder::new(&w.result_file, Compression::new(self.compression_level.0));
         log::debug!("Execute with target: encoder created {:?}", instant.elapsed());
         let (mut pos, data_len) = if first_half {
             (0usize, partition)
         } else {
-            (partition, w.data.metadata().unwrap().len() as usize)
+            (partition, w.data.metadata()?.len() as usize)
         };
         if !first_half {
-            w.data.seek(SeekFrom::Start(partition as u64)).expect("Partition is wrong");
+            w.data.seek(SeekFrom::Start(partition as u64))?;
         }
         while pos < data_len {
             let buffer_len = min(1_000_000_000, data_len - pos);
             let mut buffer: Vec<u8> = vec![0; buffer_len];
-            w.data.read_exact(&mut *buffer).expect(&*format!("Something went wrong while compressing data for workload \"{}\"", w.name));
-            e.write_all(&*buffer).expect(&*format!("Something went wrong while writing compressed data for workload \"{}\"", w.name));
+            w.data.read_exact(&mut *buffer)?;
+            segment.update(&buffer);
+            e.write_all(&*buffer)?;
             pos += buffer_len;
             log::debug!("Execute with target: written {} bytes so far (time: {:?})", pos, instant.elapsed());
         }
         log::debug!("Execute with target: write_all done {:?}", instant.elapsed());
-        e.finish().unwrap();
+        e.finish()?;
+        segment.finish()?;
         log::debug!("Execute with target: finished {:?}", instant.elapsed());
-        w.data.rewind().unwrap();
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_folder(&self, _w: &mut FolderWorkload, _write_to_tmp: bool, _max_size: Option<u64>, _first_half: bool) -> Result<u64, MixError> {
+        Err(MixError::UnsupportedMode(format!("{} doesn't support folder workloads", self.name())))
     }
 
-    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> u64 {
-        unimplemented!()
+    fn compress_in_memory(&self, data: &[u8]) -> Result<ByteSize, MixError> {
+        let mut sink = CountingSink::default();
+        let mut e = GzEncoder::new(&mut sink, Compression::new(self.compression_level.0));
+        e.write_all(data)?;
+        e.finish()?;
+        Ok(sink.bytes)
     }
 }
 #[cfg(test)]
@@ -160,8 +129,8 @@ mod tests {
     use std::io::{Seek, Write};
     use std::time::Duration;
     use tempfile::tempfile;
-    use crate::algorithms::Algorithm;
     use crate::algorithms::gzip::{GzipCompressionLevel, Gzip};
+    use crate::profiler::{MetricsSource, Profiler};
     use crate::workload::Workload;
 
     const MOCK_WORKLOAD_DATA: &str = r#"
@@ -352,11 +321,11 @@ Allor si mosse, e io li tenni dietro."#;
         let mut tmp = tempfile().unwrap();
         tmp.write_all(MOCK_WORKLOAD_DATA.as_bytes()).unwrap();
         tmp.rewind().unwrap();
-        let mut workload = Workload::new(String::from("test"), tmp, Duration::from_secs(1), None);
-        let alg = Gzip::new(&mut workload, GzipCompressionLevel(9), None);
-        alg.execute(&mut workload);
-        println!("Time: {:?}", alg.time_required());
+        let mut workload = Workload::new(String::from("test"), tmp, Duration::from_secs(1), None).unwrap();
+        let metrics = Profiler::new(MetricsSource::Measure).profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(9)))]).unwrap();
+        metrics[0].algorithm.execute(&mut workload).unwrap();
+        println!("Time: {:?}", metrics[0].time_required);
         assert_eq!(workload.data.metadata().unwrap().len(), 5265);
-        assert_eq!(alg.compressed_size(), 2529);
+        assert_eq!(metrics[0].compressed_size, 2529);
     }
 }
\ No newline at end of file
@@ -1,55 +1,71 @@
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{Read, Seek, Write};
 use std::path::Path;
-use std::time::{Duration, Instant};
-use felics::compression::{ColorType, CompressDecompress, CompressedImage, PixelDepth};
-
-use image::{DynamicImage, ImageDecoder, ImageEncoder};
-use image::codecs::png::{PngDecoder, PngEncoder};
-pub use image::codecs::png::CompressionType as PNGCompressionType;
-pub use image::codecs::png::FilterType as PNGFilterType;
-use rand::Rng;
+use std::time::Instant;
+
+use image::DynamicImage;
+use jxl_oxide::JxlImage;
 use tempfile::tempfile;
 use zune_core::bit_depth::BitDepth;
 use zune_core::colorspace::ColorSpace;
 use zune_jpegxl::JxlSimpleEncoder;
 use zune_core::options::EncoderOptions;
 
-use crate::algorithms::{Algorithm, BlockInfo, ByteSize, EstimateMetadata};
+use crate::algorithms::{Algorithm, BlockInfo};
+use crate::algorithms::png::{integer_color_type, partition_rows};
+use crate::container::{AlgorithmId, SegmentWriter};
+use crate::error::MixError;
 use crate::workload::{FolderWorkload, Workload};
 
-#[derive(Debug)]
-pub struct JPEGXL {
-    compressed_size: Option<ByteSize>,
-    time_required: Option<Duration>,
-}
+#[derive(Debug, Default)]
+pub struct JPEGXL;
 
 impl JPEGXL {
-    pub fn new_folder_workload(workload: &mut FolderWorkload, estimate_metadata: Option<EstimateMetadata>) -> JPEGXL {
-        let mut jpegxl = JPEGXL {
-            compressed_size: None,
-            time_required: None,
+    pub fn new() -> JPEGXL {
+        JPEGXL
+    }
+
+    fn encode_image(image: &DynamicImage) -> Result<Vec<u8>, MixError> {
+        let (color_space, bit_depth) = match image.color() {
+            image::ColorType::L8 => {(ColorSpace::Luma, BitDepth::Eight)}
+            image::ColorType::La8 => {(ColorSpace::LumaA, BitDepth::Eight)}
+            image::ColorType::Rgb8 => {(ColorSpace::RGB, BitDepth::Eight)}
+            image::ColorType::Rgba8 => {(ColorSpace::RGBA, BitDepth::Eight)}
+            image::ColorType::L16 => {(ColorSpace::Luma, BitDepth::Sixteen)}
+            image::ColorType::La16 => {(ColorSpace::LumaA, BitDepth::Sixteen)}
+            image::ColorType::Rgb16 => {(ColorSpace::RGB, BitDepth::Sixteen)}
+            image::ColorType::Rgba16 => {(ColorSpace::RGBA, BitDepth::Sixteen)}
+            color_type => {return Err(MixError::UnsupportedColorType(format!("Unknown color type {:?}!", color_type)))}
         };
-        jpegxl.calculate_metrics_folder(workload, estimate_metadata);
-        jpegxl
+        let mut encoder = JxlSimpleEncoder::new(image.as_bytes(), EncoderOptions::new(image.width() as usize, image.height() as usize, color_space, bit_depth));
+        encoder.encode().map_err(|e| MixError::UnsupportedMode(format!("JPEG XL encoding failed: {:?}", e)))
     }
 
-    // in this case EstimateMetadata block_ratio indicates the % of files from the folder to use, and block_number how many repetitions with different files
-    fn calculate_metrics_folder(&mut self, workload: &mut FolderWorkload, estimate_metadata: Option<EstimateMetadata>) {
-        log::info!("Calculating compressed size and time required for algorithm {:?} (workload \"{}\") (estimating: {})", self, workload.name, estimate_metadata.is_some());
-        let (compressed_size, time_required) = match estimate_metadata {
-            Some(_) => {
-                unimplemented!("Estimating time required and compressed size for folder workloads is currently not supported.")
-            }
-            None => {
-                let current_unix = Instant::now();
-                let result = self.execute_on_folder(workload, true, None, false);
-                (result, current_unix.elapsed())
-            }
+    /// Decodes a JPEG XL payload into the raw pixels, width, height and color type of the image.
+    /// The decoder renders samples in [0, 1], scaled back to the integer samples of the bit depth of the image.
+    /// Images whose raw pixels would exceed `max_len` bytes are rejected before they are rendered.
+    pub(crate) fn decode_image<R: Read>(payload: R, max_len: u64) -> Result<(Vec<u8>, u32, u32, image::ColorType), MixError> {
+        let image = JxlImage::builder().read(payload).map_err(|e| MixError::Decode(format!("JPEG XL decoding failed: {}", e)))?;
+        let bits_per_sample = image.image_header().metadata.bit_depth.bits_per_sample();
+        let bytes_per_sample = if bits_per_sample <= 8 { 1 } else { 2 };
+        let raw_len = (image.width() as u64 * image.height() as u64).checked_mul(image.pixel_format().channels() as u64 * bytes_per_sample as u64);
+        if raw_len.is_none_or(|raw_len| raw_len > max_len) {
+            return Err(MixError::Decode(format!("JPEG XL image of {}x{} pixels is larger than its segment", image.width(), image.height())));
+        }
+        let render = image.render_frame(0).map_err(|e| MixError::Decode(format!("JPEG XL rendering failed: {}", e)))?;
+        let mut stream = render.stream();
+        let (width, height, channels) = (stream.width(), stream.height(), stream.channels());
+        let mut samples = vec![0f32; width as usize * height as usize * channels as usize];
+        stream.write_to_buffer(&mut samples);
+
+        let color_type = integer_color_type(channels as u8, bytes_per_sample)
+            .ok_or_else(|| MixError::Decode(format!("JPEG XL image with {} channels of {} bits", channels, bits_per_sample)))?;
+        let max = ((1u32 << bits_per_sample) - 1) as f32;
+        let pixels = match bytes_per_sample {
+            1 => samples.iter().map(|sample| (sample.clamp(0., 1.) * max).round() as u8).collect(),
+            _ => samples.iter().flat_map(|sample| ((sample.clamp(0., 1.) * max).round() as u16).to_ne_bytes()).collect(),
         };
-        log::info!("Compressed size and time required calculated for algorithm {:?}:\nCompressed size: {:?};\nTime required: {:?}", self, compressed_size as ByteSize, time_required);
-        self.compressed_size = Some(compressed_size as ByteSize);
-        self.time_required = Some(time_required);
+        Ok((pixels, width, height, color_type))
     }
 }
 
@@ -58,117 +74,105 @@ impl Algorithm for JPEGXL {
         "JPEGXL".to_string()
     }
 
-    fn compressed_size(&self) -> ByteSize {
-        self.compressed_size.unwrap()
-    }
-
-    fn time_required(&self) -> Duration {
-        self.time_required.unwrap()
+    fn segment_algorithm(&self) -> Option<AlgorithmId> {
+        Some(AlgorithmId::JpegXl)
     }
 
-    fn execute(&self, w: &mut Workload) {
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError> {
         let instant = Instant::now();
         log::debug!("Execute: init {:?}", instant.elapsed());
 
         let mut buffer = Vec::new();
-        w.data.read_to_end(&mut buffer).unwrap();
-        let image = image::load_from_memory(&buffer).unwrap();
-
-        let (color_space, bit_depth) = match image.color() {
-            image::ColorType::L8 => {(ColorSpace::Luma, BitDepth::Eight)}
-            image::ColorType::La8 => {(ColorSpace::LumaA, BitDepth::Eight)}
-            image::ColorType::Rgb8 => {(ColorSpace::RGB, BitDepth::Eight)}
-            image::ColorType::Rgba8 => {(ColorSpace::RGBA, BitDepth::Eight)}
-            image::ColorType::L16 => {(ColorSpace::Luma, BitDepth::Sixteen)}
-            image::ColorType::La16 => {(ColorSpace::LumaA, BitDepth::Sixteen)}
-            image::ColorType::Rgb16 => {(ColorSpace::RGB, BitDepth::Sixteen)}
-            image::ColorType::Rgba16 => {(ColorSpace::RGBA, BitDepth::Sixteen)}
-            image::ColorType::Rgb32F => {(ColorSpace::RGB, BitDepth::Float32)}
-            image::ColorType::Rgba32F => {(ColorSpace::RGBA, BitDepth::Float32)}
-            _ => {panic!("Unknown color type!")}
-        };
-        let mut encoder = JxlSimpleEncoder::new(image.as_bytes(), EncoderOptions::new(image.width() as usize, image.height() as usize, color_space, bit_depth));
-        let result = encoder.encode().unwrap();
-
+        w.data.read_to_end(&mut buffer)?;
+        let image = image::load_from_memory(&buffer)?;
+        let result = JPEGXL::encode_image(&image)?;
 
-        w.result_file.write(&result).unwrap();
+        w.result_file.write_all(&result)?;
 
         log::debug!("Execute: finished {:?}", instant.elapsed());
 
-        w.data.rewind().unwrap();
+        w.data.rewind()?;
+        Ok(())
     }
 
-    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> File {
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
         let instant = Instant::now();
         log::debug!("Execute on tmp: init {:?}", instant.elapsed());
 
-        let mut tmpfile = tempfile().unwrap();
+        let mut tmpfile = tempfile()?;
         let mut buffer = Vec::new();
-        w.data.read_to_end(&mut buffer).unwrap();
-        let image = image::load_from_memory(&buffer).unwrap();
-
-        let (color_space, bit_depth) = match image.color() {
-            image::ColorType::L8 => {(ColorSpace::Luma, BitDepth::Eight)}
-            image::ColorType::La8 => {(ColorSpace::LumaA, BitDepth::Eight)}
-            image::ColorType::Rgb8 => {(ColorSpace::RGB, BitDepth::Eight)}
-            image::ColorType::Rgba8 => {(ColorSpace::RGBA, BitDepth::Eight)}
-            image::ColorType::L16 => {(ColorSpace::Luma, BitDepth::Sixteen)}
-            image::ColorType::La16 => {(ColorSpace::LumaA, BitDepth::Sixteen)}
-            image::ColorType::Rgb16 => {(ColorSpace::RGB, BitDepth::Sixteen)}
-            image::ColorType::Rgba16 => {(ColorSpace::RGBA, BitDepth::Sixteen)}
-            image::ColorType::Rgb32F => {(ColorSpace::RGB, BitDepth::Float32)}
-            image::ColorType::Rgba32F => {(ColorSpace::RGBA, BitDepth::Float32)}
-            _ => {panic!("Unknown color type!")}
-        };
-        let mut encoder = JxlSimpleEncoder::new(image.as_bytes(), EncoderOptions::new(image.width() as usize, image.height() as usize, color_space, bit_depth));
-        let result = encoder.encode().unwrap();
-        tmpfile.write(&result).unwrap();
+        w.data.read_to_end(&mut buffer)?;
+        let image = image::load_from_memory(&buffer)?;
+        let result = JPEGXL::encode_image(&image)?;
+        tmpfile.write_all(&result)?;
 
         log::debug!("Execute: finished {:?}", instant.elapsed());
 
-        w.data.rewind().unwrap();
-        tmpfile
+        w.data.rewind()?;
+        Ok(tmpfile)
     }
 
-    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) {
-        unimplemented!()
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute with target: init {:?}", instant.elapsed());
+
+        let mut buffer = Vec::new();
+        w.data.read_to_end(&mut buffer)?;
+        let image = image::load_from_memory(&buffer)?;
+        let (start_row, end_row) = partition_rows(image.height(), partition, w.data.metadata()?.len(), first_half);
+        if start_row == end_row {
+            log::debug!("Execute with target: empty partition, nothing to write");
+            w.data.rewind()?;
+            return Ok(());
+        }
+        let band = image.crop_imm(0, start_row, image.width(), end_row - start_row);
+
+        let mut segment = SegmentWriter::begin(&w.result_file, AlgorithmId::JpegXl, 0)?;
+        segment.update(band.as_bytes());
+        let result = JPEGXL::encode_image(&band)?;
+        (&w.result_file).write_all(&result)?;
+        segment.finish()?;
+
+        log::debug!("Execute with target: finished {:?} - rows {}..{}", instant.elapsed(), start_row, end_row);
+        w.data.rewind()?;
+        Ok(())
     }
 
-    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> u64 {
+    fn execute_on_folder(&self, w: &mut FolderWorkload, write_to_tmp: bool, max_size: Option<u64>, first_half: bool) -> Result<u64, MixError> {
         let mut size = 0;
         // read_dir doesn't guarantee any consistent order - sort files by size
         let mut files = Vec::new();
-        for path in w.get_data_folder() {
-            files.push(path.unwrap());
+        for path in w.get_data_folder()? {
+            let path = path?;
+            files.push((path.metadata()?.len(), path));
         }
-        files.sort_by_key(|a| a.metadata().unwrap().len());
+        files.sort_by_key(|(len, _)| *len);
         // If partially compressing the folder, partition the directory now
         if let Some(max_size) = max_size {
             let mut actual_files = Vec::new();
             let mut data_size = 0;
-            for path in files {
-                let len = path.metadata().unwrap().len();
+            for (len, path) in files {
                 if data_size < max_size && first_half || data_size > max_size && !first_half {
-                    actual_files.push(path);
+                    actual_files.push((len, path));
                 }
                 data_size += len;
             }
             files = actual_files;
         }
 
-        for direntry in files {
+        for (_, direntry) in files {
             let mut file_workload = Workload::new(
                 format!("{}-{:?}", w.name, direntry.file_name()),
-                File::open(direntry.path()).unwrap(),
+                File::open(direntry.path())?,
                 w.time_budget,
-                Some(File::create(Path::new("results").join(&w.name).join(direntry.file_name())).unwrap())
-            );
-            let result = if write_to_tmp { self.execute_on_tmp(&mut file_workload, None) } else {
-                self.execute(&mut file_workload);
+                Some(File::create(Path::new("results").join(&w.name).join(direntry.file_name()))?)
+            )?;
+            let result = if write_to_tmp { self.execute_on_tmp(&mut file_workload, None)? } else {
+                self.execute(&mut file_workload)?;
                 file_workload.result_file
             };
-            size += result.metadata().unwrap().len();
+            size += result.metadata()?.len();
         }
-        size
+        Ok(size)
     }
 }
\ No newline at end of file
@@ -0,0 +1,143 @@
+use std::cmp::min;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Instant;
+use tempfile::tempfile;
+use lz4::{Encoder, EncoderBuilder};
+use crate::algorithms::{Algorithm, BlockInfo, ByteSize, CountingSink};
+use crate::container::{AlgorithmId, SegmentWriter};
+use crate::error::MixError;
+use crate::workload::{FolderWorkload, Workload};
+
+/// Lz4 frame compression level, following the `lz4` program conventions:
+/// negative values use the fast mode with the given acceleration (same as `lz4 --fast=N`), 1 and 2 are the default fast mode and 3 to 12 use the high compression (HC) mode.
+#[derive(Debug)]
+pub struct Lz4CompressionLevel(pub i32);
+
+/// Lz4 setup, the cheapest end of the hull for text data.
+///
+/// Every setup produces regular lz4 frames, so two-part mixes are two concatenated frames that the stock `lz4` program decodes as a whole.
+#[derive(Debug)]
+pub struct Lz4 {
+    compression_level: Lz4CompressionLevel,
+}
+
+impl Lz4 {
+    pub fn new(compression_level: Lz4CompressionLevel) -> Lz4 {
+        Lz4 {
+            compression_level,
+        }
+    }
+
+    fn encoder<W: Write>(&self, target: W) -> io::Result<Encoder<W>> {
+        // The frame preferences declare the level as unsigned, while liblz4 reads it as a signed int: negative levels are passed as their two's complement
+        EncoderBuilder::new().level(self.compression_level.0 as u32).build(target)
+    }
+}
+
+impl Algorithm for Lz4 {
+    fn name(&self) -> String {
+        if self.compression_level.0 < 0 {
+            format!("Lz4_fast_{}", -self.compression_level.0)
+        } else {
+            format!("Lz4_{}", self.compression_level.0)
+        }
+    }
+
+    fn segment_algorithm(&self) -> Option<AlgorithmId> {
+        Some(AlgorithmId::Lz4)
+    }
+
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute: init {:?}", instant.elapsed());
+        let mut e = self.encoder(&mut w.result_file)?;
+        log::debug!("Execute: encoder created {:?}", instant.elapsed());
+        let mut pos = 0usize;
+        let data_len = w.data.metadata()?.len() as usize;
+        while pos < data_len {
+            let buffer_len = min(10_000_000, data_len - pos);
+            let mut buffer: Vec<u8> = vec![0; buffer_len];
+            w.data.read_exact(&mut buffer)?;
+            e.write_all(&buffer)?;
+            pos += buffer_len;
+            log::debug!("Execute: written {} bytes so far (time: {:?})", pos, instant.elapsed());
+        }
+        log::debug!("Execute: write_all done {:?}", instant.elapsed());
+        e.finish().1?;
+        log::debug!("Execute: finished {:?}", instant.elapsed());
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute on tmp: init {:?}", instant.elapsed());
+        let tmpfile = tempfile()?;
+        let mut e = self.encoder(&tmpfile)?;
+        log::debug!("Execute on tmp: encoder created {:?}", instant.elapsed());
+        let block_info = block_info.unwrap_or(BlockInfo{block_size: w.data.metadata()?.len(), block_end_index: w.data.metadata()?.len()});
+        let mut start = block_info.block_end_index - block_info.block_size;
+        let data_len = block_info.block_end_index;
+
+        w.data.seek(SeekFrom::Start(start))?;
+        while start < data_len {
+            let buffer_len = min(10_000_000, data_len - start);
+            log::debug!("Execute on tmp: starting cycle (start: {start}, data_len: {data_len}, buffer_len: {buffer_len})");
+            let mut buffer: Vec<u8> = vec![0; buffer_len as usize];
+            w.data.read_exact(&mut buffer)?;
+            e.write_all(&buffer)?;
+            start += buffer_len;
+            log::debug!("Execute on tmp: written {} bytes so far (time: {:?})", start, instant.elapsed());
+        }
+        log::debug!("Execute on tmp: write_all done {:?}", instant.elapsed());
+        e.finish().1?;
+        log::debug!("Execute on tmp: finished {:?}", instant.elapsed());
+        w.data.rewind()?;
+        Ok(tmpfile)
+    }
+
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError> {
+        let instant = Instant::now();
+        log::debug!("Execute with target: init {:?}", instant.elapsed());
+        let mut segment = SegmentWriter::begin(&w.result_file, AlgorithmId::Lz4, self.compression_level.0 as u32)?;
+        let mut e = self.encoder(&w.result_file)?;
+        log::debug!("Execute with target: encoder created {:?}", instant.elapsed());
+        let (mut pos, data_len) = if first_half {
+            (0usize, partition)
+        } else {
+            (partition, w.data.metadata()?.len() as usize)
+        };
+        if !first_half {
+            w.data.seek(SeekFrom::Start(partition as u64))?;
+        }
+        while pos < data_len {
+            let buffer_len = min(1_000_000_000, data_len - pos);
+            let mut buffer: Vec<u8> = vec![0; buffer_len];
+            w.data.read_exact(&mut buffer)?;
+            segment.update(&buffer);
+            e.write_all(&buffer)?;
+            pos += buffer_len;
+            log::debug!("Execute with target: written {} bytes so far (time: {:?})", pos, instant.elapsed());
+        }
+        log::debug!("Execute with target: write_all done {:?}", instant.elapsed());
+        e.finish().1?;
+        segment.finish()?;
+        log::debug!("Execute with target: finished {:?}", instant.elapsed());
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_folder(&self, _w: &mut FolderWorkload, _write_to_tmp: bool, _max_size: Option<u64>, _first_half: bool) -> Result<u64, MixError> {
+        Err(MixError::UnsupportedMode(format!("{} doesn't support folder workloads", self.name())))
+    }
+
+    fn compress_in_memory(&self, data: &[u8]) -> Result<ByteSize, MixError> {
+        let mut sink = CountingSink::default();
+        let mut e = self.encoder(&mut sink)?;
+        e.write_all(data)?;
+        e.finish().1?;
+        Ok(sink.bytes)
+    }
+}
@@ -0,0 +1,34 @@
+use std::fs::File;
+use std::time::Duration;
+
+use tempfile::tempfile;
+
+use crate::algorithms::{Algorithm, AlgorithmMetrics, BlockInfo, ByteSize};
+use crate::error::MixError;
+use crate::workload::{FolderWorkload, Workload};
+
+/// A setup that compresses nothing, for the tests of the policies that only look at the metrics of the setups.
+#[derive(Debug)]
+pub(crate) struct MockAlgorithm;
+
+impl Algorithm for MockAlgorithm {
+    fn name(&self) -> String {
+        "Mock".to_string()
+    }
+
+    fn execute(&self, _: &mut Workload) -> Result<(), MixError> { Ok(()) }
+
+    fn execute_on_tmp(&self, _: &mut Workload, _: Option<BlockInfo>) -> Result<File, MixError> { Ok(tempfile()?) }
+
+    fn execute_with_target(&self, _: &mut Workload, _: usize, _: bool) -> Result<(), MixError> { Ok(()) }
+
+    fn execute_on_folder(&self, _: &mut FolderWorkload, _: bool, _: Option<u64>, _: bool) -> Result<u64, MixError> { Ok(0) }
+}
+
+/// Mock setups with their compressed size and compression time (in ms).
+pub(crate) fn metrics(setups: &[(ByteSize, u64)]) -> Vec<AlgorithmMetrics> {
+    setups
+        .iter()
+        .map(|(size, millis)| AlgorithmMetrics::new(Box::new(MockAlgorithm), *size, Duration::from_millis(*millis)))
+        .collect()
+}
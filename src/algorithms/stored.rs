@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Instant;
+use tempfile::tempfile;
+use crate::algorithms::{Algorithm, BlockInfo, ByteSize};
+use crate::container::{AlgorithmId, SegmentWriter};
+use crate::error::MixError;
+use crate::workload::{FolderWorkload, Workload};
+
+/// Copies the data as is. It is not a setup of any hull: only the deadline mode falls back to it, when not even the cheapest setup fits in the time left.
+#[derive(Debug, Default)]
+pub struct Stored;
+
+impl Stored {
+    pub fn new() -> Stored {
+        Stored
+    }
+}
+
+/// Copies `len` bytes from the current position of the data to the target, feeding them to the segment writer if any.
+fn copy_range(data: &mut File, target: &mut impl Write, len: u64, mut segment: Option<&mut SegmentWriter>) -> Result<(), MixError> {
+    let mut buffer = vec![0; 1 << 20];
+    let mut remaining = len;
+    while remaining > 0 {
+        let buffer_len = remaining.min(buffer.len() as u64) as usize;
+        data.read_exact(&mut buffer[..buffer_len])?;
+        if let Some(segment) = segment.as_deref_mut() {
+            segment.update(&buffer[..buffer_len]);
+        }
+        target.write_all(&buffer[..buffer_len])?;
+        remaining -= buffer_len as u64;
+    }
+    Ok(())
+}
+
+impl Algorithm for Stored {
+    fn name(&self) -> String {
+        String::from("Stored")
+    }
+
+    fn segment_algorithm(&self) -> Option<AlgorithmId> {
+        Some(AlgorithmId::Stored)
+    }
+
+    fn execute(&self, w: &mut Workload) -> Result<(), MixError> {
+        let data_len = w.data.metadata()?.len();
+        copy_range(&mut w.data, &mut w.result_file, data_len, None)?;
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_tmp(&self, w: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
+        let mut tmpfile = tempfile()?;
+        let block_info = block_info.unwrap_or(BlockInfo{block_size: w.data.metadata()?.len(), block_end_index: w.data.metadata()?.len()});
+        w.data.seek(SeekFrom::Start(block_info.block_end_index - block_info.block_size))?;
+        copy_range(&mut w.data, &mut tmpfile, block_info.block_size, None)?;
+        w.data.rewind()?;
+        Ok(tmpfile)
+    }
+
+    fn execute_with_target(&self, w: &mut Workload, partition: usize, first_half: bool) -> Result<(), MixError> {
+        let instant = Instant::now();
+        let mut segment = SegmentWriter::begin(&w.result_file, AlgorithmId::Stored, 0)?;
+        let (start, end) = if first_half {
+            (0, partition as u64)
+        } else {
+            (partition as u64, w.data.metadata()?.len())
+        };
+        w.data.seek(SeekFrom::Start(start))?;
+        let mut target = &w.result_file;
+        copy_range(&mut w.data, &mut target, end - start, Some(&mut segment))?;
+        segment.finish()?;
+        log::debug!("Execute with target: stored {} bytes in {:?}", end - start, instant.elapsed());
+        w.data.rewind()?;
+        Ok(())
+    }
+
+    fn execute_on_folder(&self, _w: &mut FolderWorkload, _write_to_tmp: bool, _max_size: Option<u64>, _first_half: bool) -> Result<u64, MixError> {
+        Err(MixError::UnsupportedMode(format!("{} doesn't support folder workloads", self.name())))
+    }
+
+    fn compress_in_memory(&self, data: &[u8]) -> Result<ByteSize, MixError> {
+        Ok(data.len() as ByteSize)
+    }
+}
@@ -0,0 +1,562 @@
+use std::time::Duration;
+use crate::algorithms::{AlgorithmMetrics, ByteSize};
+use crate::mixing_policy::{MetricsWithBenefit, OptimalMix};
+
+/// Relative gap between the total size of an allocation and its dual bound below which the allocation is proven optimal, to absorb floating point errors.
+const OPTIMALITY_TOLERANCE: f64 = 1e-9;
+
+/// Times closer than this (in seconds) are considered the same point of an envelope.
+const TIME_TOLERANCE: f64 = 1e-9;
+
+/// Limits of a single workload, enforced alongside the total time budget.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct WorkloadLimits {
+    /// Maximum time the workload may take
+    pub cap: Option<Duration>,
+    /// Maximum expected compressed size of the workload, e.g. the size of the weakest acceptable setup
+    pub floor: Option<ByteSize>,
+}
+
+/// A constraint the optimal allocation is held back by: relaxing it would make the total size smaller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Binding {
+    TotalBudget,
+    /// The cap of the workload with the given index
+    Cap(usize),
+    /// The floor of the workload with the given index
+    Floor(usize),
+}
+
+/// Proof that an allocation is optimal, from Lagrangian duality.
+///
+/// For any multiplier λ ≥ 0 (bytes per second), no allocation within the total budget B (and within the limits of each workload) compresses to less than
+/// `Σ min(size + λ·time) - λ·B`, where each minimum is over the mixes of a workload within its limits. An allocation whose total size meets this bound can't be improved.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Certificate {
+    /// Multiplier of the budget constraint: the bytes the last second of budget saves
+    pub multiplier: f64,
+    /// Expected total compressed size of the allocation
+    pub total_size: f64,
+    /// Expected total time of the allocation, in seconds
+    pub total_time: f64,
+    /// Lower bound of the total compressed size of any allocation within the budget
+    pub dual_bound: f64,
+}
+
+impl Certificate {
+    pub fn gap(&self) -> f64 {
+        self.total_size - self.dual_bound
+    }
+
+    pub fn is_optimal(&self) -> bool {
+        self.gap() <= OPTIMALITY_TOLERANCE * self.total_size.max(1.)
+    }
+}
+
+/// The mix of each workload minimizing the total compressed size within a total time budget, with its optimality certificate and the constraints holding it back.
+#[derive(Debug)]
+pub struct Allocation<'a> {
+    pub mixes: Vec<OptimalMix<'a>>,
+    pub certificate: Certificate,
+    pub binding: Vec<Binding>,
+}
+
+fn seconds(metrics: &AlgorithmMetrics) -> f64 {
+    metrics.time_required.as_secs_f64()
+}
+
+/// Bytes saved per additional second by moving from the cheap setup to the expensive one.
+fn benefit(cheap: &AlgorithmMetrics, expensive: &AlgorithmMetrics) -> f64 {
+    (cheap.compressed_size as f64 - expensive.compressed_size as f64) / (seconds(expensive) - seconds(cheap))
+}
+
+/// The setups of a workload on its lower convex envelope, by increasing time: each setup is slower and smaller than the previous one, and saves fewer bytes per second.
+/// The hull of a workload is built beforehand, this only guards the solver against hulls that aren't strictly convex (e.g. aligned setups).
+///
+/// Mixes of two consecutive setups make the envelope a convex, decreasing function from time to compressed size.
+struct Envelope<'a> {
+    setups: Vec<&'a AlgorithmMetrics>,
+}
+
+impl<'a> Envelope<'a> {
+    fn new(lower_convex_hull: &[MetricsWithBenefit<'a>]) -> Envelope<'a> {
+        let mut sorted: Vec<_> = lower_convex_hull.iter().map(|metric| metric.0).collect();
+        sorted.sort_by(|a, b| a.time_required.cmp(&b.time_required).then(a.compressed_size.cmp(&b.compressed_size)));
+        let mut setups: Vec<&AlgorithmMetrics> = Vec::with_capacity(sorted.len());
+        for setup in sorted {
+            if setups.last().is_some_and(|last| setup.compressed_size >= last.compressed_size) {
+                continue;
+            }
+            while let [.., previous, last] = setups[..] {
+                if benefit(previous, last) > benefit(last, setup) {
+                    break;
+                }
+                setups.pop();
+            }
+            setups.push(setup);
+        }
+        Envelope { setups }
+    }
+
+    fn time(&self, index: usize) -> f64 {
+        seconds(self.setups[index])
+    }
+
+    /// Index of the segment (from setup `index - 1` to setup `index`) covering the given time, the first one if the time is on a setup.
+    fn segment(&self, time: f64) -> usize {
+        (1..self.setups.len()).find(|&index| time <= self.time(index)).unwrap_or(self.setups.len() - 1).max(1)
+    }
+
+    fn size_at(&self, time: f64) -> f64 {
+        if self.setups.len() == 1 {
+            return self.setups[0].compressed_size as f64;
+        }
+        let index = self.segment(time);
+        self.setups[index - 1].compressed_size as f64 - benefit(self.setups[index - 1], self.setups[index]) * (time - self.time(index - 1))
+    }
+
+    /// The time from which the envelope is within the given size, none if no mix is small enough.
+    fn time_for_size(&self, size: f64) -> Option<f64> {
+        if self.setups[0].compressed_size as f64 <= size {
+            return Some(self.time(0));
+        }
+        let index = (1..self.setups.len()).find(|&index| self.setups[index].compressed_size as f64 <= size)?;
+        Some(self.time(index - 1) + (self.setups[index - 1].compressed_size as f64 - size) / benefit(self.setups[index - 1], self.setups[index]))
+    }
+
+    /// Bytes saved per second by the segments before and after the given time, if any.
+    fn benefits_around(&self, time: f64) -> (Option<f64>, Option<f64>) {
+        let before = (1..self.setups.len()).rev().find(|&index| self.time(index - 1) < time - TIME_TOLERANCE);
+        let after = (1..self.setups.len()).find(|&index| self.time(index) > time + TIME_TOLERANCE);
+        let benefit_of = |index: usize| benefit(self.setups[index - 1], self.setups[index]);
+        (before.map(benefit_of), after.map(benefit_of))
+    }
+
+    /// The mix taking the given time, a single setup if the time is (close to) the time of one.
+    fn mix_at(&self, time: f64) -> OptimalMix<'a> {
+        if let Some(setup) = self.setups.iter().find(|setup| (seconds(setup) - time).abs() <= TIME_TOLERANCE) {
+            return OptimalMix::Single(setup);
+        }
+        let index = self.segment(time);
+        let (cheap, expensive) = (self.setups[index - 1], self.setups[index]);
+        OptimalMix::Normal((expensive, cheap), (time - seconds(cheap)) / (seconds(expensive) - seconds(cheap)))
+    }
+
+    /// The times the workload may take within its limits, none if they can't be met.
+    fn time_range(&self, limits: &WorkloadLimits) -> Option<(f64, f64)> {
+        let start = match limits.floor {
+            Some(floor) => self.time_for_size(floor as f64)?,
+            None => self.time(0),
+        };
+        let end = limits.cap.map_or(f64::INFINITY, |cap| cap.as_secs_f64()).min(self.time(self.setups.len() - 1));
+        (start <= end).then_some((start, end))
+    }
+}
+
+/// The minimum time of each workload within its limits, none for the workloads whose limits can't be met (a floor unreachable by any mix, or a cap below the floor).
+pub fn minimum_times(lower_convex_hull_per_workload: &[Vec<MetricsWithBenefit>], limits: &[WorkloadLimits]) -> Vec<Option<Duration>> {
+    lower_convex_hull_per_workload
+        .iter()
+        .enumerate()
+        .map(|(workload, lch)| {
+            let envelope = Envelope::new(lch);
+            if envelope.setups.is_empty() {
+                return None;
+            }
+            envelope.time_range(&limits.get(workload).copied().unwrap_or_default()).map(|(start, _)| Duration::from_secs_f64(start))
+        })
+        .collect()
+}
+
+/// The envelope of each workload, and the times (from, to) it may take within its limits.
+type LimitedEnvelopes<'a> = (Vec<Envelope<'a>>, Vec<(f64, f64)>);
+
+/// The envelope of each workload, with the times it may take within its limits (missing limits don't constrain their workload). None if the limits of a workload can't be met.
+fn limited_envelopes<'a>(lower_convex_hull_per_workload: &[Vec<MetricsWithBenefit<'a>>], limits: &[WorkloadLimits]) -> Option<LimitedEnvelopes<'a>> {
+    let envelopes: Vec<_> = lower_convex_hull_per_workload.iter().map(|lch| Envelope::new(lch)).collect();
+    if envelopes.iter().any(|envelope| envelope.setups.is_empty()) {
+        return None;
+    }
+    let ranges = envelopes
+        .iter()
+        .enumerate()
+        .map(|(workload, envelope)| envelope.time_range(&limits.get(workload).copied().unwrap_or_default()))
+        .collect::<Option<Vec<_>>>()?;
+    Some((envelopes, ranges))
+}
+
+/// The segments of each envelope within the range of its workload, as (workload, end time, benefit), by decreasing benefit.
+/// Benefits decrease along each envelope, so the segments of a workload stay in order.
+fn upgrades(envelopes: &[Envelope], ranges: &[(f64, f64)]) -> Vec<(usize, f64, f64)> {
+    let mut upgrades = Vec::new();
+    for (workload, (envelope, &(start, end))) in envelopes.iter().zip(ranges).enumerate() {
+        for index in 1..envelope.setups.len() {
+            let (from, to) = (envelope.time(index - 1).max(start), envelope.time(index).min(end));
+            if to > from {
+                upgrades.push((workload, to, benefit(envelope.setups[index - 1], envelope.setups[index])));
+            }
+        }
+    }
+    upgrades.sort_by(|a, b| b.2.total_cmp(&a.2));
+    upgrades
+}
+
+/// Chooses the mix of each workload that minimizes the total compressed size within the total time budget and the limits of each workload, by water-filling.
+///
+/// Every workload starts from the cheapest mix within its floor, and the envelope of each workload is then followed up to its cap, taking the segments of all the envelopes by decreasing benefit while they fit in the budget.
+/// The first segment that doesn't fit is taken partially, so at most one workload is mixed because of the total budget; the workloads whose floor or cap falls between two setups are mixed as well.
+/// The benefit of that last segment is the multiplier of the budget constraint, whose dual bound certifies the allocation, see [Certificate].
+/// Missing limits (e.g. an empty slice) don't constrain their workload.
+///
+/// Can result in a none if the total time budget doesn't allow even for the cheapest setups, or if the limits of a workload can't be met, see [minimum_times].
+pub fn optimal_allocation<'a>(lower_convex_hull_per_workload: &[Vec<MetricsWithBenefit<'a>>], total_time_budget: Duration, limits: &[WorkloadLimits]) -> Option<Allocation<'a>> {
+    let (envelopes, ranges) = limited_envelopes(lower_convex_hull_per_workload, limits)?;
+    let limits: Vec<_> = (0..envelopes.len()).map(|workload| limits.get(workload).copied().unwrap_or_default()).collect();
+    let budget = total_time_budget.as_secs_f64();
+    let mut positions: Vec<f64> = ranges.iter().map(|(start, _)| *start).collect();
+    let mut slack = budget - positions.iter().sum::<f64>();
+    if slack < -TIME_TOLERANCE {
+        return None;
+    }
+    slack = slack.max(0.);
+
+    let mut multiplier = 0.;
+    for (workload, to, benefit) in upgrades(&envelopes, &ranges) {
+        let additional_time = to - positions[workload];
+        if additional_time <= slack {
+            slack -= additional_time;
+            positions[workload] = to;
+            continue;
+        }
+        multiplier = benefit;
+        positions[workload] += slack;
+        break;
+    }
+
+    let mixes = envelopes.iter().zip(&positions).map(|(envelope, &time)| envelope.mix_at(time)).collect();
+    let total_size = envelopes.iter().zip(&positions).map(|(envelope, &time)| envelope.size_at(time)).sum();
+    let total_time = positions.iter().sum();
+    // The minimum of a convex function over a range is at one of its ends, or at a setup in between
+    let dual_bound = envelopes
+        .iter()
+        .zip(&ranges)
+        .map(|(envelope, &(start, end))| {
+            envelope.setups
+                .iter()
+                .map(|setup| seconds(setup))
+                .filter(|time| (start..=end).contains(time))
+                .chain([start, end])
+                .map(|time| envelope.size_at(time) + multiplier * time)
+                .fold(f64::INFINITY, f64::min)
+        })
+        .sum::<f64>()
+        - multiplier * budget;
+    let certificate = Certificate { multiplier, total_size, total_time, dual_bound };
+
+    // A limit binds when the workload sits on it and would move past it at the price of the budget
+    let mut binding = Vec::new();
+    if multiplier > 0. {
+        binding.push(Binding::TotalBudget);
+    }
+    for (workload, (envelope, &time)) in envelopes.iter().zip(&positions).enumerate() {
+        let (start, end) = ranges[workload];
+        let (before, after) = envelope.benefits_around(time);
+        if limits[workload].cap.is_some_and(|cap| (time - cap.as_secs_f64()).abs() <= TIME_TOLERANCE) && after.is_some_and(|benefit| benefit > multiplier) && (time - end).abs() <= TIME_TOLERANCE {
+            binding.push(Binding::Cap(workload));
+        }
+        if limits[workload].floor.is_some() && (time - start).abs() <= TIME_TOLERANCE && before.is_some_and(|benefit| benefit < multiplier) {
+            binding.push(Binding::Floor(workload));
+        }
+    }
+    Some(Allocation { mixes, certificate, binding })
+}
+
+/// The smallest total time budget whose optimal allocation (see [optimal_allocation]) is within the total size budget, none if no allocation within the limits is small enough.
+///
+/// Minimizing the total time for a total size is the dual problem of minimizing the total size for a total time: both follow the envelopes by decreasing benefit,
+/// so the fastest allocation within a size budget is the optimal allocation of the time budget where the total size reaches it.
+pub fn minimum_time_for_size(lower_convex_hull_per_workload: &[Vec<MetricsWithBenefit>], total_size_budget: ByteSize, limits: &[WorkloadLimits]) -> Option<Duration> {
+    let (envelopes, ranges) = limited_envelopes(lower_convex_hull_per_workload, limits)?;
+    let mut positions: Vec<f64> = ranges.iter().map(|(start, _)| *start).collect();
+    let mut time: f64 = positions.iter().sum();
+    let mut excess = envelopes.iter().zip(&positions).map(|(envelope, &time)| envelope.size_at(time)).sum::<f64>() - total_size_budget as f64;
+    for (workload, to, benefit) in upgrades(&envelopes, &ranges) {
+        if excess <= 0. {
+            break;
+        }
+        let additional_time = to - positions[workload];
+        if benefit * additional_time <= excess {
+            excess -= benefit * additional_time;
+            time += additional_time;
+            positions[workload] = to;
+            continue;
+        }
+        time += excess / benefit;
+        excess = 0.;
+    }
+    (excess <= OPTIMALITY_TOLERANCE * (total_size_budget as f64).max(1.)).then(|| Duration::from_secs_f64(time))
+}
+
+/// The total time of the allocation taking every segment of the envelopes (within the limits of its workload) saving more than the given bytes per second, none if the limits of a workload can't be met.
+/// Past this time, every additional second of budget saves fewer bytes than that.
+pub fn time_for_benefit(lower_convex_hull_per_workload: &[Vec<MetricsWithBenefit>], minimum_benefit: f64, limits: &[WorkloadLimits]) -> Option<Duration> {
+    let (envelopes, ranges) = limited_envelopes(lower_convex_hull_per_workload, limits)?;
+    let mut positions: Vec<f64> = ranges.iter().map(|(start, _)| *start).collect();
+    for (workload, to, benefit) in upgrades(&envelopes, &ranges) {
+        if benefit <= minimum_benefit {
+            break;
+        }
+        positions[workload] = to;
+    }
+    Some(Duration::from_secs_f64(positions.iter().sum()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::algorithms::AlgorithmMetrics;
+    use crate::algorithms::mock::metrics;
+    use crate::allocation::{minimum_time_for_size, minimum_times, optimal_allocation, time_for_benefit, Binding, WorkloadLimits};
+    use crate::mixing_policy::{MetricsWithBenefit, MixingPolicy, OptimalMix};
+
+    /// One to three workloads of one to five random setups each.
+    fn random_setups(rng: &mut StdRng) -> Vec<Vec<AlgorithmMetrics>> {
+        (0..rng.gen_range(1..=3))
+            .map(|_| {
+                let setups: Vec<_> = (0..rng.gen_range(1..=5)).map(|_| (rng.gen_range(100..10_000), rng.gen_range(1..5_000))).collect();
+                metrics(&setups)
+            })
+            .collect()
+    }
+
+    /// Two workloads: the second one saves 1000 bytes per second, the first one 200 and then 33.3.
+    fn two_workloads() -> Vec<Vec<AlgorithmMetrics>> {
+        vec![
+            metrics(&[(1000, 1000), (600, 3000), (500, 6000)]),
+            metrics(&[(2000, 2000), (1000, 3000)]),
+        ]
+    }
+
+    fn lower_convex_hulls(setups: &[Vec<AlgorithmMetrics>]) -> Vec<Vec<MetricsWithBenefit<'_>>> {
+        setups.iter().map(|workload| MixingPolicy::new(workload.iter().collect()).lower_convex_hull).collect()
+    }
+
+    /// The smallest total size within the budget, over every setup of every workload.
+    /// An optimal allocation has at most one mixed workload (a single constraint couples the workloads), so it is enough to try every combination of single setups,
+    /// and to spend the slack of each one on a mix of one of the workloads with any other of its setups.
+    fn brute_force(setups: &[Vec<AlgorithmMetrics>], budget: f64) -> Option<f64> {
+        let mut best: Option<f64> = None;
+        let mut combination = vec![0; setups.len()];
+        loop {
+            let time: f64 = combination.iter().zip(setups).map(|(&index, workload)| workload[index].time_required.as_secs_f64()).sum();
+            let size: f64 = combination.iter().zip(setups).map(|(&index, workload)| workload[index].compressed_size as f64).sum();
+            if time <= budget {
+                best = Some(best.map_or(size, |best| best.min(size)));
+                for (&index, workload) in combination.iter().zip(setups) {
+                    let (cheap_time, cheap_size) = (workload[index].time_required.as_secs_f64(), workload[index].compressed_size as f64);
+                    for expensive in workload.iter().filter(|setup| setup.time_required.as_secs_f64() > cheap_time) {
+                        let fraction = ((budget - time) / (expensive.time_required.as_secs_f64() - cheap_time)).min(1.);
+                        best = best.map(|best| best.min(size + fraction * (expensive.compressed_size as f64 - cheap_size)));
+                    }
+                }
+            }
+            // Next combination, as an odometer
+            let Some(workload) = (0..setups.len()).find(|&workload| combination[workload] + 1 < setups[workload].len()) else {
+                return best;
+            };
+            combination[workload] += 1;
+            combination[..workload].fill(0);
+        }
+    }
+
+    /// The (time, size) points of a workload that are the vertices of its mixes within the limits: its setups, and the mixes of any two of them on the cap or on the floor.
+    /// Only the points not dominated by another one are kept, as they are the only ones an optimal allocation may use.
+    fn limited_points(setups: &[AlgorithmMetrics], limits: &WorkloadLimits) -> Vec<(f64, f64)> {
+        let (cap, floor) = (limits.cap.map_or(f64::INFINITY, |cap| cap.as_secs_f64()), limits.floor.map_or(f64::INFINITY, |floor| floor as f64));
+        let raw: Vec<_> = setups.iter().map(|setup| (setup.time_required.as_secs_f64(), setup.compressed_size as f64)).collect();
+        let mut points = raw.clone();
+        for (index, &(a_time, a_size)) in raw.iter().enumerate() {
+            for &(b_time, b_size) in &raw[index + 1..] {
+                for fraction in [(cap - a_time) / (b_time - a_time), (floor - a_size) / (b_size - a_size)] {
+                    if (0. ..=1.).contains(&fraction) {
+                        points.push((a_time + fraction * (b_time - a_time), a_size + fraction * (b_size - a_size)));
+                    }
+                }
+            }
+        }
+        points.retain(|&(time, size)| time <= cap * (1. + 1e-12) && size <= floor * (1. + 1e-12));
+        let dominated = |&(time, size): &(f64, f64), points: &[(f64, f64)]| points.iter().any(|&(other_time, other_size)| other_time <= time && other_size <= size && (other_time, other_size) != (time, size));
+        points.iter().filter(|point| !dominated(point, &points)).copied().collect()
+    }
+
+    /// Same as [brute_force], on the vertices of the mixes of each workload within its limits, and moving one workload towards any other of its vertices.
+    fn brute_force_with_limits(setups: &[Vec<AlgorithmMetrics>], limits: &[WorkloadLimits], budget: f64) -> Option<f64> {
+        let points: Vec<_> = setups.iter().zip(limits).map(|(setups, limits)| limited_points(setups, limits)).collect();
+        if points.iter().any(|points| points.is_empty()) {
+            return None;
+        }
+        let mut best: Option<f64> = None;
+        let mut combination = vec![0; points.len()];
+        loop {
+            let time: f64 = combination.iter().zip(&points).map(|(&index, points)| points[index].0).sum();
+            let size: f64 = combination.iter().zip(&points).map(|(&index, points)| points[index].1).sum();
+            if time <= budget * (1. + 1e-12) {
+                best = Some(best.map_or(size, |best| best.min(size)));
+                for (&index, points) in combination.iter().zip(&points) {
+                    let (from_time, from_size) = points[index];
+                    for &(to_time, to_size) in points.iter().filter(|point| point.0 > from_time) {
+                        let fraction = ((budget - time) / (to_time - from_time)).min(1.);
+                        best = best.map(|best| best.min(size + fraction * (to_size - from_size)));
+                    }
+                }
+            }
+            let Some(workload) = (0..points.len()).find(|&workload| combination[workload] + 1 < points[workload].len()) else {
+                return best;
+            };
+            combination[workload] += 1;
+            combination[..workload].fill(0);
+        }
+    }
+
+    #[test]
+    fn matches_brute_force_on_random_hulls() {
+        let mut rng = StdRng::seed_from_u64(21);
+        for _ in 0..200 {
+            let setups = random_setups(&mut rng);
+            let lchs = lower_convex_hulls(&setups);
+            let budget = Duration::from_millis(rng.gen_range(0..12_000));
+
+            let expected = brute_force(&setups, budget.as_secs_f64());
+            let allocation = optimal_allocation(&lchs, budget, &[]);
+            assert_eq!(allocation.is_some(), expected.is_some(), "feasibility with budget {:?}", budget);
+            if let (Some(allocation), Some(expected)) = (allocation, expected) {
+                let certificate = allocation.certificate;
+                assert!((certificate.total_size - expected).abs() <= 1e-6 * expected, "{} != {} with budget {:?}", certificate.total_size, expected, budget);
+                assert!(certificate.is_optimal(), "{:?}", certificate);
+                assert!(certificate.total_time <= budget.as_secs_f64() + 1e-9);
+                assert!(allocation.mixes.iter().filter(|mix| matches!(mix, OptimalMix::Normal(..))).count() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn spend_the_budget_on_the_highest_benefit_first() {
+        let setups = two_workloads();
+        let lchs = lower_convex_hulls(&setups);
+        // The second workload saves 1000 bytes per second, the first one 200 and then 33
+        let allocation = optimal_allocation(&lchs, Duration::from_secs(5), &[]).unwrap();
+        assert!(matches!(allocation.mixes[1], OptimalMix::Single(metrics) if metrics.compressed_size == 1000));
+        assert!(matches!(allocation.mixes[0], OptimalMix::Normal((expensive, cheap), fraction)
+            if expensive.compressed_size == 600 && cheap.compressed_size == 1000 && (fraction - 0.5).abs() < 1e-9));
+        assert_eq!(allocation.certificate.multiplier, 200.);
+        assert!((allocation.certificate.total_size - 1800.).abs() < 1e-9);
+        assert!(allocation.certificate.is_optimal());
+
+        assert!(optimal_allocation(&lchs, Duration::from_millis(2999), &[]).is_none());
+        let unconstrained = optimal_allocation(&lchs, Duration::from_secs(60), &[]).unwrap();
+        assert_eq!(unconstrained.certificate.multiplier, 0.);
+        assert_eq!(unconstrained.certificate.total_size, 1500.);
+    }
+
+    #[test]
+    fn matches_brute_force_with_random_limits() {
+        let mut rng = StdRng::seed_from_u64(22);
+        for _ in 0..500 {
+            let setups = random_setups(&mut rng);
+            let limits: Vec<_> = setups
+                .iter()
+                .map(|_| WorkloadLimits {
+                    cap: rng.gen_bool(0.5).then(|| Duration::from_millis(rng.gen_range(1_000..6_000))),
+                    floor: rng.gen_bool(0.5).then(|| rng.gen_range(2_000..10_000)),
+                })
+                .collect();
+            let lchs = lower_convex_hulls(&setups);
+            let budget = Duration::from_millis(rng.gen_range(0..12_000));
+
+            let expected = brute_force_with_limits(&setups, &limits, budget.as_secs_f64());
+            let allocation = optimal_allocation(&lchs, budget, &limits);
+            assert_eq!(allocation.is_some(), expected.is_some(), "feasibility with budget {:?} and limits {:?}", budget, limits);
+            if let (Some(allocation), Some(expected)) = (allocation, expected) {
+                let certificate = allocation.certificate;
+                assert!((certificate.total_size - expected).abs() <= 1e-6 * expected, "{} != {} with budget {:?} and limits {:?}", certificate.total_size, expected, budget, limits);
+                assert!(certificate.is_optimal(), "{:?}", certificate);
+                assert!(certificate.total_time <= budget.as_secs_f64() + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn report_the_binding_limits() {
+        let setups = two_workloads();
+        let lchs = lower_convex_hulls(&setups);
+        // The second workload would rather take 3s, the remaining budget goes to the first one
+        let capped = [WorkloadLimits::default(), WorkloadLimits { cap: Some(Duration::from_millis(2500)), floor: None }];
+        let allocation = optimal_allocation(&lchs, Duration::from_secs(5), &capped).unwrap();
+        assert!(matches!(allocation.mixes[1], OptimalMix::Normal(_, fraction) if (fraction - 0.5).abs() < 1e-9));
+        assert!((allocation.certificate.total_size - 2200.).abs() < 1e-9);
+        assert!(allocation.certificate.is_optimal());
+        assert_eq!(allocation.binding, vec![Binding::TotalBudget, Binding::Cap(1)]);
+
+        // Reaching the floor of the first workload takes the time the second one would make better use of
+        let floored = [WorkloadLimits { cap: None, floor: Some(600) }, WorkloadLimits { cap: Some(Duration::from_secs(10)), floor: None }];
+        let allocation = optimal_allocation(&lchs, Duration::from_secs(5), &floored).unwrap();
+        assert!((allocation.certificate.total_size - 2600.).abs() < 1e-9);
+        assert!(allocation.certificate.is_optimal());
+        assert_eq!(allocation.binding, vec![Binding::TotalBudget, Binding::Floor(0)]);
+
+        let unreachable = [WorkloadLimits { cap: None, floor: Some(400) }, WorkloadLimits::default()];
+        assert!(optimal_allocation(&lchs, Duration::from_secs(60), &unreachable).is_none());
+        assert_eq!(minimum_times(&lchs, &unreachable), vec![None, Some(Duration::from_secs(2))]);
+        assert_eq!(minimum_times(&lchs, &floored), vec![Some(Duration::from_secs(3)), Some(Duration::from_secs(2))]);
+    }
+
+    #[test]
+    fn minimum_time_for_size_is_the_dual_allocation() {
+        let setups = two_workloads();
+        let lchs = lower_convex_hulls(&setups);
+        // The second workload saves the most bytes per second, then the first one
+        assert_eq!(minimum_time_for_size(&lchs, 3000, &[]), Some(Duration::from_secs(3)));
+        assert_eq!(minimum_time_for_size(&lchs, 2500, &[]), Some(Duration::from_millis(3500)));
+        assert_eq!(minimum_time_for_size(&lchs, 1800, &[]), Some(Duration::from_secs(5)));
+        assert_eq!(minimum_time_for_size(&lchs, 1500, &[]), Some(Duration::from_secs(9)));
+        assert_eq!(minimum_time_for_size(&lchs, 1499, &[]), None);
+        // Without the second workload moving past 2.5s, the first one has to make up for it
+        let capped = [WorkloadLimits::default(), WorkloadLimits { cap: Some(Duration::from_millis(2500)), floor: None }];
+        assert_eq!(minimum_time_for_size(&lchs, 2200, &capped), Some(Duration::from_secs(5)));
+
+        let mut rng = StdRng::seed_from_u64(23);
+        for _ in 0..100 {
+            let setups = random_setups(&mut rng);
+            let lchs = lower_convex_hulls(&setups);
+            let size_budget = rng.gen_range(100..30_000);
+            let Some(time) = minimum_time_for_size(&lchs, size_budget, &[]) else {
+                let smallest = optimal_allocation(&lchs, Duration::MAX, &[]).unwrap().certificate.total_size;
+                assert!(smallest > size_budget as f64, "{} is within {} bytes", smallest, size_budget);
+                continue;
+            };
+            let allocation = optimal_allocation(&lchs, time, &[]).unwrap();
+            // Up to the rounding of the time to nanoseconds
+            assert!(allocation.certificate.total_size <= size_budget as f64 * (1. + 1e-6), "{} > {} bytes in {:?}", allocation.certificate.total_size, size_budget, time);
+            // Any shorter budget results in a larger total size
+            if let Some(shorter) = time.checked_sub(Duration::from_millis(1)).and_then(|shorter| optimal_allocation(&lchs, shorter, &[])) {
+                assert!(shorter.certificate.total_size > size_budget as f64, "{} bytes within {:?}", shorter.certificate.total_size, time);
+            }
+        }
+    }
+
+    #[test]
+    fn time_for_benefit_stops_at_the_break_even() {
+        let setups = two_workloads();
+        let lchs = lower_convex_hulls(&setups);
+        // Benefits are 1000, 200 and 33.3 bytes per second
+        assert_eq!(time_for_benefit(&lchs, 2000., &[]), Some(Duration::from_secs(3)));
+        assert_eq!(time_for_benefit(&lchs, 500., &[]), Some(Duration::from_secs(4)));
+        assert_eq!(time_for_benefit(&lchs, 200., &[]), Some(Duration::from_secs(4)));
+        assert_eq!(time_for_benefit(&lchs, 100., &[]), Some(Duration::from_secs(6)));
+        assert_eq!(time_for_benefit(&lchs, 0., &[]), Some(Duration::from_secs(9)));
+        let capped = [WorkloadLimits { cap: Some(Duration::from_secs(2)), floor: None }, WorkloadLimits::default()];
+        assert_eq!(time_for_benefit(&lchs, 0., &capped), Some(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs::{create_dir_all, read_to_string, write, File};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread::available_parallelism;
+use std::time::Duration;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+use crate::algorithms::{ByteSize, ConfidenceInterval};
+use crate::error::MixError;
+use crate::workload::FolderWorkload;
+
+/// Default location of the metrics cache.
+pub const DEFAULT_CACHE_FOLDER: &str = "results/.cache";
+
+/// Persistent cache of the metrics of each setup, so that rerunning a job on the same data (e.g. with a different budget) doesn't profile every setup again.
+///
+/// Entries are keyed by the content hash of the workload, the algorithm name, the estimation parameters and a fingerprint of the machine, since timings measured elsewhere aren't meaningful.
+/// Each entry is stored as a small text file named after the hash of its key.
+#[derive(Debug)]
+pub struct MetricsCache {
+    pub folder: PathBuf,
+    pub fingerprint: u64,
+}
+
+impl MetricsCache {
+    pub fn new(folder: impl Into<PathBuf>) -> Result<MetricsCache, MixError> {
+        let folder = folder.into();
+        create_dir_all(&folder)?;
+        Ok(MetricsCache { folder, fingerprint: machine_fingerprint() })
+    }
+
+    /// The key of a setup, as a human readable string.
+    /// The parameters describe how the metrics are profiled, see [crate::profiler::MetricsSource::cache_parameters].
+    pub fn key(&self, content_hash: u64, algorithm_name: &str, parameters: &str) -> String {
+        self.key_on_machine(content_hash, algorithm_name, parameters, self.fingerprint)
+    }
+
+    /// The key of a setup profiled on another machine, e.g. in a cache folder shared by several machines.
+    pub fn key_on_machine(&self, content_hash: u64, algorithm_name: &str, parameters: &str, fingerprint: u64) -> String {
+        format!("{:016x};{};{};{:016x}", content_hash, algorithm_name, parameters, fingerprint)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.folder.join(format!("{:016x}", xxh3_64(key.as_bytes())))
+    }
+
+    /// Returns the cached compressed size, time required and confidence interval (for estimates) for a key, if any.
+    /// Unreadable entries are treated as missing, so that they are simply overwritten.
+    pub fn get(&self, key: &str) -> Option<(ByteSize, Duration, Option<ConfidenceInterval>)> {
+        let entry = read_to_string(self.entry_path(key)).ok()?;
+        let mut lines = entry.lines();
+        if lines.next()? != key {
+            log::warn!("Metrics cache collision for key {}", key);
+            return None;
+        }
+        let compressed_size = lines.next()?.parse().ok()?;
+        let time_required = Duration::from_nanos(lines.next()?.parse().ok()?);
+        let confidence_interval = match lines.next() {
+            Some(line) => {
+                let fields: Vec<_> = line.split(' ').collect();
+                if fields.len() != 3 {
+                    return None;
+                }
+                Some(ConfidenceInterval {
+                    compressed_size: fields[0].parse().ok()?,
+                    time_required: Duration::from_nanos(fields[1].parse().ok()?),
+                    samples: fields[2].parse().ok()?,
+                })
+            }
+            None => None,
+        };
+        Some((compressed_size, time_required, confidence_interval))
+    }
+
+    /// Stores the metrics of a key, with the confidence interval as an optional fourth line.
+    pub fn insert(&self, key: &str, compressed_size: ByteSize, time_required: Duration, confidence_interval: Option<ConfidenceInterval>) -> Result<(), MixError> {
+        let mut entry = format!("{}\n{}\n{}\n", key, compressed_size, time_required.as_nanos());
+        if let Some(confidence_interval) = confidence_interval {
+            entry.push_str(&format!("{} {} {}\n", confidence_interval.compressed_size, confidence_interval.time_required.as_nanos(), confidence_interval.samples));
+        }
+        write(self.entry_path(key), entry)?;
+        Ok(())
+    }
+
+    /// Returns the cached decompression time for a key, if any. Setups that can't be decoded are cached as well, with no time.
+    pub fn get_decompression_time(&self, key: &str) -> Option<Option<Duration>> {
+        let key = format!("{};decompression", key);
+        let entry = read_to_string(self.entry_path(&key)).ok()?;
+        let mut lines = entry.lines();
+        if lines.next()? != key {
+            log::warn!("Metrics cache collision for key {}", key);
+            return None;
+        }
+        match lines.next()? {
+            "none" => Some(None),
+            nanos => Some(Some(Duration::from_nanos(nanos.parse().ok()?))),
+        }
+    }
+
+    /// Stores the decompression time of a key, next to its metrics.
+    pub fn insert_decompression_time(&self, key: &str, decompression_time: Option<Duration>) -> Result<(), MixError> {
+        let key = format!("{};decompression", key);
+        let decompression_time = decompression_time.map_or_else(|| String::from("none"), |decompression_time| decompression_time.as_nanos().to_string());
+        write(self.entry_path(&key), format!("{}\n{}\n", key, decompression_time))?;
+        Ok(())
+    }
+}
+
+/// Hashes the content of a file, restoring the cursor at the start of the file.
+pub fn content_hash(mut file: &File) -> io::Result<u64> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0; 1_000_000];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    file.seek(SeekFrom::Start(0))?;
+    Ok(hasher.digest())
+}
+
+/// Hashes the names and content of every file of a folder workload, in name order.
+pub fn folder_content_hash(workload: &FolderWorkload) -> io::Result<u64> {
+    let mut paths = Vec::new();
+    for path in workload.get_data_folder()? {
+        paths.push(path?.path());
+    }
+    paths.sort();
+    let mut hasher = Xxh3::new();
+    for path in paths {
+        hasher.update(path.file_name().unwrap_or_default().to_string_lossy().as_bytes());
+        hasher.update(&content_hash(&File::open(&path)?)?.to_be_bytes());
+    }
+    Ok(hasher.digest())
+}
+
+/// Identifies the machine the metrics are measured on, from its cpu model, number of available cores and hostname.
+pub fn machine_fingerprint() -> u64 {
+    let cpu_model = read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|cpuinfo| {
+            cpuinfo
+                .lines()
+                .find(|line| line.starts_with("model name"))
+                .and_then(|line| line.split(':').nth(1))
+                .map(|model| model.trim().to_string())
+        })
+        .unwrap_or_default();
+    let cores = available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+    let hostname = read_to_string(Path::new("/proc/sys/kernel/hostname"))
+        .or_else(|_| read_to_string(Path::new("/etc/hostname")))
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_default();
+    log::debug!("Machine fingerprint: cpu \"{}\", {} cores, hostname \"{}\"", cpu_model, cores, hostname);
+    xxh3_64(format!("{};{};{}", cpu_model, cores, hostname).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use crate::algorithms::{ConfidenceInterval, EstimateMetadata};
+    use crate::cache::MetricsCache;
+    use crate::profiler::MetricsSource;
+
+    #[test]
+    fn entries_are_keyed_by_every_parameter() {
+        let folder = tempdir().unwrap();
+        let cache = MetricsCache::new(folder.path()).unwrap();
+        let measure = MetricsSource::Measure.cache_parameters();
+        let key = cache.key(42, "Gzip_6", &measure);
+        assert_eq!(cache.get(&key), None);
+        cache.insert(&key, 1234, Duration::from_millis(56), None).unwrap();
+        assert_eq!(cache.get(&key), Some((1234, Duration::from_millis(56), None)));
+
+        assert_eq!(cache.get(&cache.key(43, "Gzip_6", &measure)), None);
+        assert_eq!(cache.get(&cache.key(42, "Gzip_7", &measure)), None);
+        let estimate = MetricsSource::Estimate(EstimateMetadata { block_number: 10, block_ratio: 0.01 }).cache_parameters();
+        assert_eq!(cache.get(&cache.key(42, "Gzip_6", &estimate)), None);
+        let other_machine = MetricsCache { folder: folder.path().to_path_buf(), fingerprint: cache.fingerprint + 1 };
+        assert_eq!(other_machine.get(&other_machine.key(42, "Gzip_6", &measure)), None);
+    }
+
+    #[test]
+    fn confidence_intervals_are_stored() {
+        let folder = tempdir().unwrap();
+        let cache = MetricsCache::new(folder.path()).unwrap();
+        let key = cache.key(42, "Gzip_6", "estimate_10_0.01");
+        let confidence_interval = ConfidenceInterval { compressed_size: 12, time_required: Duration::from_micros(3), samples: 10 };
+        cache.insert(&key, 1234, Duration::from_millis(56), Some(confidence_interval)).unwrap();
+        assert_eq!(cache.get(&key), Some((1234, Duration::from_millis(56), Some(confidence_interval))));
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{create_dir_all, read_dir, read_to_string, write};
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tempfile::tempfile;
+use crate::algorithms::{Algorithm, AlgorithmMetrics};
+use crate::cache::machine_fingerprint;
+use crate::error::MixError;
+use crate::workload::Workload;
+
+/// Default location of the machine profiles, one file per machine named after its fingerprint.
+pub const DEFAULT_CALIBRATION_FOLDER: &str = "results/.calibration";
+
+/// Size of the benchmark corpus.
+const CORPUS_SIZE: usize = 2 << 20;
+
+/// Words of the text part of the benchmark corpus.
+const VOCABULARY_SIZE: usize = 2000;
+
+/// First line of a machine profile.
+const PROFILE_HEADER: &str = "mix_compression machine v1";
+
+/// The standard corpus compressed by every setup during calibration, the same on every machine: text made of words drawn with a skewed distribution,
+/// csv-like records and a tail of random bytes, so that both match finders and entropy coders are exercised.
+pub fn benchmark_corpus() -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let vocabulary: Vec<Vec<u8>> = (0..VOCABULARY_SIZE)
+        .map(|_| (0..rng.gen_range(2..10)).map(|_| rng.gen_range(b'a'..=b'z')).collect())
+        .collect();
+    let mut corpus = Vec::with_capacity(CORPUS_SIZE);
+    while corpus.len() < CORPUS_SIZE * 6 / 10 {
+        // Cubing a uniform draw favours the first words, as in natural language
+        let word = &vocabulary[(rng.gen::<f64>().powi(3) * VOCABULARY_SIZE as f64) as usize];
+        corpus.extend_from_slice(word);
+        corpus.push(if rng.gen_ratio(1, 12) { b'\n' } else { b' ' });
+    }
+    while corpus.len() < CORPUS_SIZE * 9 / 10 {
+        let record = format!("{},{},{:.3},{}\n", rng.gen_range(0..1_000_000), rng.gen_range(1..32), rng.gen::<f64>() * 100., rng.gen_bool(0.3));
+        corpus.extend_from_slice(record.as_bytes());
+    }
+    while corpus.len() < CORPUS_SIZE {
+        corpus.push(rng.gen());
+    }
+    corpus
+}
+
+/// Throughput of each setup on a machine, measured on the [benchmark_corpus].
+///
+/// Times measured on a machine are moved to another one by the ratio of the throughputs of the setup on both, see [MachineProfile::rescale].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineProfile {
+    pub fingerprint: u64,
+    /// Uncompressed bytes per second of each setup, by algorithm name
+    pub throughputs: HashMap<String, f64>,
+}
+
+impl MachineProfile {
+    /// Compresses the benchmark corpus with every setup on this machine.
+    /// Setups that can't compress the corpus (e.g. image codecs) are skipped, and rescaled as the median setup.
+    pub fn calibrate(algorithms: &[Box<dyn Algorithm>]) -> Result<MachineProfile, MixError> {
+        let corpus = benchmark_corpus();
+        let mut data = tempfile()?;
+        data.write_all(&corpus)?;
+        data.rewind()?;
+        let mut workload = Workload::new(String::from("calibration"), data, Duration::ZERO, Some(tempfile()?))?;
+        let mut throughputs = HashMap::new();
+        for algorithm in algorithms {
+            let current_unix = Instant::now();
+            match algorithm.execute_on_tmp(&mut workload, None) {
+                Ok(_) => {
+                    let throughput = corpus.len() as f64 / current_unix.elapsed().as_secs_f64();
+                    log::info!("Calibrated algorithm {}: {:.0} bytes/s", algorithm.name(), throughput);
+                    throughputs.insert(algorithm.name(), throughput);
+                }
+                Err(e) => log::warn!("Skipping the calibration of algorithm {}: {}", algorithm.name(), e),
+            }
+        }
+        Ok(MachineProfile { fingerprint: machine_fingerprint(), throughputs })
+    }
+
+    /// Location of the profile of a machine in a calibration folder.
+    pub fn path(folder: &Path, fingerprint: u64) -> PathBuf {
+        folder.join(format!("{:016x}", fingerprint))
+    }
+
+    /// Reads a profile written by [MachineProfile::save].
+    pub fn load(path: &Path) -> Result<MachineProfile, MixError> {
+        let content = read_to_string(path)?;
+        let mut lines = content.lines();
+        let invalid_profile = || MixError::Decode(format!("{} is not a machine profile", path.display()));
+        if lines.next() != Some(PROFILE_HEADER) {
+            return Err(invalid_profile());
+        }
+        let fingerprint = lines.next().and_then(|line| u64::from_str_radix(line, 16).ok()).ok_or_else(invalid_profile)?;
+        let mut throughputs = HashMap::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let (name, throughput) = line.split_once(';').ok_or_else(invalid_profile)?;
+            throughputs.insert(name.to_string(), throughput.parse().map_err(|_| invalid_profile())?);
+        }
+        Ok(MachineProfile { fingerprint, throughputs })
+    }
+
+    /// Reads every profile of a calibration folder (none if the folder doesn't exist), by fingerprint.
+    pub fn load_all(folder: &Path) -> Result<HashMap<u64, MachineProfile>, MixError> {
+        let mut profiles = HashMap::new();
+        if !folder.exists() {
+            return Ok(profiles);
+        }
+        for entry in read_dir(folder)? {
+            let profile = MachineProfile::load(&entry?.path())?;
+            profiles.insert(profile.fingerprint, profile);
+        }
+        Ok(profiles)
+    }
+
+    /// Writes the profile in a calibration folder, with the fingerprint on the second line and one `algorithm;bytes per second` line per setup.
+    pub fn save(&self, folder: &Path) -> Result<PathBuf, MixError> {
+        create_dir_all(folder)?;
+        let mut content = format!("{}\n{:016x}\n", PROFILE_HEADER, self.fingerprint);
+        let mut names: Vec<_> = self.throughputs.keys().collect();
+        names.sort();
+        for name in names {
+            let _ = writeln!(content, "{};{}", name, self.throughputs[name]);
+        }
+        let path = MachineProfile::path(folder, self.fingerprint);
+        write(&path, content)?;
+        Ok(path)
+    }
+
+    /// How many times faster the target machine runs a setup, or the median speedup of the setups calibrated on both machines if this one wasn't.
+    fn speedup(&self, target: &MachineProfile, algorithm_name: &str) -> Option<f64> {
+        match (self.throughputs.get(algorithm_name), target.throughputs.get(algorithm_name)) {
+            (Some(throughput), Some(target_throughput)) => Some(target_throughput / throughput),
+            _ => {
+                let mut speedups: Vec<_> = self.throughputs
+                    .iter()
+                    .filter_map(|(name, throughput)| target.throughputs.get(name).map(|target_throughput| target_throughput / throughput))
+                    .collect();
+                speedups.sort_by(f64::total_cmp);
+                speedups.get(speedups.len() / 2).copied()
+            }
+        }
+    }
+
+    /// The time a setup measured on this machine would take on the target machine, if both share calibrated setups.
+    pub fn time_on(&self, target: &MachineProfile, algorithm_name: &str, time_required: Duration) -> Option<Duration> {
+        self.speedup(target, algorithm_name).map(|speedup| time_required.div_f64(speedup))
+    }
+
+    /// Moves the times (and their confidence intervals) of metrics profiled on this machine to the target machine.
+    /// Decompression times are moved by the speedup of the compression of the same setup, since only compression is calibrated.
+    /// Metrics are left untouched if the machines share no calibrated setup.
+    pub fn rescale(&self, target: &MachineProfile, metrics: &mut [AlgorithmMetrics]) {
+        if self.fingerprint == target.fingerprint {
+            return;
+        }
+        for metric in metrics {
+            let name = metric.algorithm.name();
+            match self.speedup(target, &name) {
+                Some(speedup) => {
+                    log::debug!("Rescaling the time of algorithm {} from machine {:016x} to {:016x} (speedup {:.3})", name, self.fingerprint, target.fingerprint, speedup);
+                    metric.time_required = metric.time_required.div_f64(speedup);
+                    if let Some(confidence_interval) = &mut metric.confidence_interval {
+                        confidence_interval.time_required = confidence_interval.time_required.div_f64(speedup);
+                    }
+                    metric.decompression_time = metric.decompression_time.map(|decompression_time| decompression_time.div_f64(speedup));
+                }
+                None => log::warn!("Machines {:016x} and {:016x} share no calibrated setup, the time of algorithm {} is left as is", self.fingerprint, target.fingerprint, name),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use crate::algorithms::AlgorithmMetrics;
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::calibration::{benchmark_corpus, MachineProfile};
+
+    fn profile(fingerprint: u64, throughputs: &[(&str, f64)]) -> MachineProfile {
+        MachineProfile { fingerprint, throughputs: throughputs.iter().map(|(name, throughput)| (name.to_string(), *throughput)).collect::<HashMap<_, _>>() }
+    }
+
+    #[test]
+    fn rescale_by_the_throughput_ratio() {
+        let laptop = profile(1, &[("Gzip_1", 100.), ("Gzip_6", 50.), ("Gzip_9", 10.)]);
+        let server = profile(2, &[("Gzip_1", 200.), ("Gzip_6", 200.), ("Gzip_9", 30.)]);
+        let mut metrics = vec![
+            AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(6))), 100, Duration::from_secs(4)).with_decompression_time(Some(Duration::from_secs(2))),
+            // Not calibrated, so it is rescaled by the median speedup (3)
+            AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(5))), 100, Duration::from_secs(3)),
+        ];
+        laptop.rescale(&server, &mut metrics);
+        assert_eq!(metrics[0].time_required, Duration::from_secs(1));
+        assert_eq!(metrics[1].time_required, Duration::from_secs(1));
+        assert_eq!(metrics[0].decompression_time, Some(Duration::from_millis(500)));
+        assert_eq!(metrics[1].decompression_time, None);
+    }
+
+    #[test]
+    fn profiles_round_trip() {
+        assert_eq!(benchmark_corpus(), benchmark_corpus());
+        let folder = tempdir().unwrap();
+        let profile = profile(0xabc, &[("Brotli_5_w22", 1234.5), ("Zstd_3", 1e8)]);
+        let path = profile.save(folder.path()).unwrap();
+        assert_eq!(path, MachineProfile::path(folder.path(), 0xabc));
+        assert_eq!(MachineProfile::load_all(folder.path()).unwrap()[&0xabc], profile);
+    }
+}
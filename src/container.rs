@@ -0,0 +1,750 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use xxhash_rust::xxh3::Xxh3;
+use crate::error::MixError;
+
+/// Signature closing every mixed container, similar to the png signature http://www.libpng.org/pub/png/spec/1.2/PNG-Rationale.html#R.PNG-file-signature
+pub const MAGIC: [u8; 8] = [137u8, 77u8, 73u8, 88u8, 67u8, 77u8, 80u8, 10u8];
+pub const VERSION: u8 = 1;
+/// Zstd skippable frame magic number. The segment table is wrapped in a skippable frame, so that tools that understand them simply ignore it.
+const SKIPPABLE_FRAME_MAGIC: u32 = 0x184D2A5E;
+const SKIPPABLE_FRAME_HEADER_SIZE: u64 = 8;
+/// Algorithm id (u8), level (u32), offset, compressed length, uncompressed length and checksum (u64 each)
+const SEGMENT_ENTRY_SIZE: u64 = 1 + 4 + 8 * 4;
+/// Segment count (u32), table offset (u64), version (u8) and magic
+const FOOTER_SIZE: u64 = 4 + 8 + 1 + MAGIC.len() as u64;
+
+/// Identifies the algorithm that produced a segment, and therefore how to decode it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlgorithmId {
+    Gzip = 1,
+    Bzip2 = 2,
+    Xz2 = 3,
+    Png = 4,
+    Felics = 5,
+    JpegXl = 6,
+    LosslessJpeg = 7,
+    Zstd = 8,
+    Lz4 = 9,
+    Brotli = 10,
+    /// Uncompressed bytes, written by the deadline mode when not even the cheapest setup fits in the time left
+    Stored = 11,
+}
+
+impl AlgorithmId {
+    pub fn from_u8(id: u8) -> Option<AlgorithmId> {
+        match id {
+            1 => Some(AlgorithmId::Gzip),
+            2 => Some(AlgorithmId::Bzip2),
+            3 => Some(AlgorithmId::Xz2),
+            4 => Some(AlgorithmId::Png),
+            5 => Some(AlgorithmId::Felics),
+            6 => Some(AlgorithmId::JpegXl),
+            7 => Some(AlgorithmId::LosslessJpeg),
+            8 => Some(AlgorithmId::Zstd),
+            9 => Some(AlgorithmId::Lz4),
+            10 => Some(AlgorithmId::Brotli),
+            11 => Some(AlgorithmId::Stored),
+            _ => None,
+        }
+    }
+
+    /// Image segments store the raw pixels of a band of rows of the original image instead of a byte range of the original file.
+    pub fn is_image(&self) -> bool {
+        matches!(self, AlgorithmId::Png | AlgorithmId::Felics | AlgorithmId::JpegXl | AlgorithmId::LosslessJpeg)
+    }
+}
+
+/// An entry of the segment table. The checksum is the xxh3 hash of the uncompressed bytes of the segment.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub algorithm: AlgorithmId,
+    pub level: u32,
+    pub offset: u64,
+    pub compressed_len: u64,
+    pub uncompressed_len: u64,
+    pub checksum: u64,
+}
+
+impl Segment {
+    fn write_to(&self, target: &mut impl Write) -> io::Result<()> {
+        target.write_all(&[self.algorithm as u8])?;
+        target.write_all(&self.level.to_be_bytes())?;
+        target.write_all(&self.offset.to_be_bytes())?;
+        target.write_all(&self.compressed_len.to_be_bytes())?;
+        target.write_all(&self.uncompressed_len.to_be_bytes())?;
+        target.write_all(&self.checksum.to_be_bytes())
+    }
+
+    fn read_from(source: &mut impl Read) -> Result<Segment, MixError> {
+        let mut id = [0; 1];
+        let mut level = [0; 4];
+        let mut offset = [0; 8];
+        let mut compressed_len = [0; 8];
+        let mut uncompressed_len = [0; 8];
+        let mut checksum = [0; 8];
+        source.read_exact(&mut id)?;
+        source.read_exact(&mut level)?;
+        source.read_exact(&mut offset)?;
+        source.read_exact(&mut compressed_len)?;
+        source.read_exact(&mut uncompressed_len)?;
+        source.read_exact(&mut checksum)?;
+        let algorithm = AlgorithmId::from_u8(id[0])
+            .ok_or_else(|| MixError::Decode(format!("Unknown algorithm id {} in segment table", id[0])))?;
+        Ok(Segment {
+            algorithm,
+            level: u32::from_be_bytes(level),
+            offset: u64::from_be_bytes(offset),
+            compressed_len: u64::from_be_bytes(compressed_len),
+            uncompressed_len: u64::from_be_bytes(uncompressed_len),
+            checksum: u64::from_be_bytes(checksum),
+        })
+    }
+}
+
+/// Self-describing container for mixed compression results.
+///
+/// Segment payloads are written back to back from the start of the file, followed by a trailer (the segment table and a footer) wrapped in a zstd skippable frame.
+/// Keeping the table at the end means the file still starts with a regular gzip/bzip2/xz member, so mixes of a single algorithm family stay decodable by their usual programs (which will warn about the trailing data).
+/// ```text
+/// [payload 0][payload 1]...[skippable frame header][segment table][segment count][table offset][version][magic]
+/// ```
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Container {
+    pub segments: Vec<Segment>,
+}
+
+impl Container {
+    /// Reads the segment table of a container, leaving the cursor of `source` in an unspecified position.
+    pub fn read(mut source: &File) -> Result<Container, MixError> {
+        let len = source.seek(SeekFrom::End(0))?;
+        if len < FOOTER_SIZE {
+            return Err(MixError::Decode(String::from("File is too small to be a mixed container")));
+        }
+        source.seek(SeekFrom::Start(len - FOOTER_SIZE))?;
+        let mut segment_count = [0; 4];
+        let mut table_offset = [0; 8];
+        let mut version = [0; 1];
+        let mut magic = [0; MAGIC.len()];
+        source.read_exact(&mut segment_count)?;
+        source.read_exact(&mut table_offset)?;
+        source.read_exact(&mut version)?;
+        source.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(MixError::Decode(String::from("Missing mixed container signature")));
+        }
+        if version[0] != VERSION {
+            return Err(MixError::UnsupportedMode(format!("Unsupported mixed container version {}", version[0])));
+        }
+
+        // The table fills the file up to the footer, so a corrupt count or offset is rejected before allocating the segments
+        let table_offset = u64::from_be_bytes(table_offset);
+        let segment_count = u32::from_be_bytes(segment_count);
+        let table_end = (segment_count as u64).checked_mul(SEGMENT_ENTRY_SIZE).and_then(|table_len| table_offset.checked_add(table_len));
+        if table_end != Some(len - FOOTER_SIZE) {
+            return Err(MixError::Decode(format!("Segment table of {} segments at offset {} doesn't end at the footer", segment_count, table_offset)));
+        }
+        source.seek(SeekFrom::Start(table_offset))?;
+        let mut segments = Vec::with_capacity(segment_count as usize);
+        for index in 0..segment_count {
+            let segment = Segment::read_from(&mut source)?;
+            if segment.offset.checked_add(segment.compressed_len).is_none_or(|end| end > table_offset) {
+                return Err(MixError::Decode(format!("Segment #{} overlaps the segment table", index)));
+            }
+            segments.push(segment);
+        }
+        Ok(Container { segments })
+    }
+
+    /// The index where the payload of the next segment should start, i.e. the start of the trailer.
+    fn payload_end(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.offset + segment.compressed_len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn write_trailer(&self, target: &mut impl Write) -> io::Result<()> {
+        let table_offset = self.payload_end() + SKIPPABLE_FRAME_HEADER_SIZE;
+        let frame_size = self.segments.len() as u64 * SEGMENT_ENTRY_SIZE + FOOTER_SIZE;
+        target.write_all(&SKIPPABLE_FRAME_MAGIC.to_le_bytes())?;
+        target.write_all(&(frame_size as u32).to_le_bytes())?;
+        for segment in &self.segments {
+            segment.write_to(target)?;
+        }
+        target.write_all(&(self.segments.len() as u32).to_be_bytes())?;
+        target.write_all(&table_offset.to_be_bytes())?;
+        target.write_all(&[VERSION])?;
+        target.write_all(&MAGIC)
+    }
+}
+
+/// Appends a new segment to a container.
+///
+/// `begin` drops the current trailer and leaves the cursor of the target where the compressed payload must be written.
+/// Every uncompressed byte handled by the encoder must be passed to `update`, and `finish` must be called once the encoder is done to rewrite the trailer.
+pub struct SegmentWriter<'a> {
+    target: &'a File,
+    container: Container,
+    algorithm: AlgorithmId,
+    level: u32,
+    offset: u64,
+    uncompressed_len: u64,
+    hasher: Xxh3,
+}
+
+impl<'a> SegmentWriter<'a> {
+    pub fn begin(mut target: &'a File, algorithm: AlgorithmId, level: u32) -> Result<SegmentWriter<'a>, MixError> {
+        let len = target.seek(SeekFrom::End(0))?;
+        let container = if len == 0 {
+            Container::default()
+        } else {
+            Container::read(target)?
+        };
+        let offset = container.payload_end();
+        target.set_len(offset)?;
+        target.seek(SeekFrom::Start(offset))?;
+        Ok(SegmentWriter {
+            target,
+            container,
+            algorithm,
+            level,
+            offset,
+            uncompressed_len: 0,
+            hasher: Xxh3::new(),
+        })
+    }
+
+    pub fn update(&mut self, uncompressed: &[u8]) {
+        self.hasher.update(uncompressed);
+        self.uncompressed_len += uncompressed.len() as u64;
+    }
+
+    pub fn finish(mut self) -> Result<Segment, MixError> {
+        let end = self.target.seek(SeekFrom::End(0))?;
+        let segment = Segment {
+            algorithm: self.algorithm,
+            level: self.level,
+            offset: self.offset,
+            compressed_len: end - self.offset,
+            uncompressed_len: self.uncompressed_len,
+            checksum: self.hasher.digest(),
+        };
+        log::debug!("Segment written: {:?}", segment);
+        self.container.segments.push(segment);
+        self.container.write_trailer(&mut self.target)?;
+        Ok(segment)
+    }
+}
+
+/// Appends every segment of the `source` container to the `target` container, e.g. to concatenate segments compressed concurrently in separate files.
+/// An empty `source` is left out, since it holds no segment.
+pub fn append(mut target: &File, mut source: &File) -> Result<(), MixError> {
+    if source.metadata()?.len() == 0 {
+        return Ok(());
+    }
+    let source_container = Container::read(source)?;
+    let mut container = if target.seek(SeekFrom::End(0))? == 0 {
+        Container::default()
+    } else {
+        Container::read(target)?
+    };
+    let mut offset = container.payload_end();
+    target.set_len(offset)?;
+    target.seek(SeekFrom::Start(offset))?;
+    for segment in source_container.segments {
+        source.seek(SeekFrom::Start(segment.offset))?;
+        io::copy(&mut source.take(segment.compressed_len), &mut target)?;
+        container.segments.push(Segment { offset, ..segment });
+        offset += segment.compressed_len;
+    }
+    container.write_trailer(&mut target)?;
+    Ok(())
+}
+
+/// Writes everything to the inner writer, while hashing and counting the written bytes.
+struct HashingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: Xxh3,
+    written: u64,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decompresses a mixed container, writing the uncompressed segments in order on `output` and checking their checksums.
+/// Image segments are written as raw pixels, see `decompress_image` to rebuild the original image instead.
+pub fn decompress<W: Write>(mut input: &File, output: &mut W) -> Result<Container, MixError> {
+    let container = Container::read(input)?;
+    for (index, segment) in container.segments.iter().enumerate() {
+        log::debug!("Decompressing segment #{}: {:?}", index, segment);
+        input.seek(SeekFrom::Start(segment.offset))?;
+        let payload = input.take(segment.compressed_len);
+        let mut writer = HashingWriter { inner: output, hasher: Xxh3::new(), written: 0 };
+        decode_segment(segment, payload, &mut writer)?;
+        check_segment(index, segment, writer.written, writer.hasher.digest())?;
+    }
+    Ok(container)
+}
+
+fn check_segment(index: usize, segment: &Segment, uncompressed_len: u64, checksum: u64) -> Result<(), MixError> {
+    if uncompressed_len != segment.uncompressed_len || checksum != segment.checksum {
+        return Err(MixError::Decode(
+            format!("Segment #{} ({:?}) is corrupted: expected {} bytes with checksum {:x}, got {} bytes with checksum {:x}",
+                    index, segment.algorithm, segment.uncompressed_len, segment.checksum, uncompressed_len, checksum)));
+    }
+    Ok(())
+}
+
+/// Decodes a segment read from a file, whose images can't be larger than the uncompressed length it declares.
+fn decode_segment<R: Read, W: Write>(segment: &Segment, payload: R, output: &mut W) -> Result<(), MixError> {
+    decode(segment.algorithm, payload, output, segment.uncompressed_len)
+}
+
+/// Decodes a payload written by an algorithm, such as the output of [crate::algorithms::Algorithm::execute_on_tmp], into its original data.
+pub(crate) fn decode_payload<R: Read, W: Write>(algorithm: AlgorithmId, payload: R, output: &mut W) -> Result<(), MixError> {
+    decode(algorithm, payload, output, u64::MAX)
+}
+
+fn decode<R: Read, W: Write>(algorithm: AlgorithmId, payload: R, output: &mut W, max_image_len: u64) -> Result<(), MixError> {
+    match algorithm {
+        AlgorithmId::Gzip => {
+            io::copy(&mut flate2::read::GzDecoder::new(payload), output)?;
+        }
+        AlgorithmId::Bzip2 => {
+            io::copy(&mut bzip2::read::BzDecoder::new(payload), output)?;
+        }
+        AlgorithmId::Xz2 => {
+            io::copy(&mut xz2::read::XzDecoder::new(payload), output)?;
+        }
+        AlgorithmId::Zstd => {
+            io::copy(&mut zstd::stream::read::Decoder::new(payload)?, output)?;
+        }
+        AlgorithmId::Lz4 => {
+            io::copy(&mut lz4::Decoder::new(payload)?, output)?;
+        }
+        AlgorithmId::Brotli => {
+            io::copy(&mut brotli::Decompressor::new(payload, 1 << 16), output)?;
+        }
+        AlgorithmId::Stored => {
+            io::copy(&mut { payload }, output)?;
+        }
+        #[cfg(feature = "image")]
+        algorithm if algorithm.is_image() => {
+            output.write_all(&decode_image_segment(algorithm, payload, max_image_len)?.0)?;
+        }
+        algorithm => {
+            return Err(MixError::UnsupportedMode(format!("Decoding {:?} segments is not supported", algorithm)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "image")]
+fn decode_png<R: Read>(payload: R, max_len: u64) -> Result<(Vec<u8>, u32, u32, image::ColorType), MixError> {
+    use image::ImageDecoder;
+    let decoder = image::codecs::png::PngDecoder::new(payload)?;
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    if decoder.total_bytes() > max_len {
+        return Err(MixError::Decode(format!("PNG image of {}x{} pixels is larger than its segment", width, height)));
+    }
+    let mut pixels = vec![0; decoder.total_bytes() as usize];
+    decoder.read_image(&mut pixels)?;
+    Ok((pixels, width, height, color_type))
+}
+
+/// Decodes the payload of an image segment into its raw pixels, width, height and color type.
+/// Images whose raw pixels would exceed `max_len` bytes are rejected before they are allocated, since their header may be corrupt.
+#[cfg(feature = "image")]
+fn decode_image_segment<R: Read>(algorithm: AlgorithmId, payload: R, max_len: u64) -> Result<(Vec<u8>, u32, u32, image::ColorType), MixError> {
+    use crate::algorithms::jpegxl::JPEGXL;
+    match algorithm {
+        AlgorithmId::Png => decode_png(payload, max_len),
+        AlgorithmId::JpegXl => JPEGXL::decode_image(payload, max_len),
+        // FELICS payloads are serialized channels of the felics crate, and Lossless JPEG payloads have no decodable header
+        AlgorithmId::Felics | AlgorithmId::LosslessJpeg => Err(MixError::UnsupportedMode(format!("Decoding {:?} segments is not supported", algorithm))),
+        algorithm => Err(MixError::UnsupportedMode(format!("{:?} segments are not image segments", algorithm))),
+    }
+}
+
+/// Decompresses a mixed container made of image segments, returning the raw pixels, width, height and color type of the original image.
+/// Each segment holds a band of rows of the original image, in order.
+#[cfg(feature = "image")]
+pub fn decompress_image(mut input: &File) -> Result<(Vec<u8>, u32, u32, image::ColorType), MixError> {
+    let container = Container::read(input)?;
+    let mut image: Option<(Vec<u8>, u32, u32, image::ColorType)> = None;
+    for (index, segment) in container.segments.iter().enumerate() {
+        if !segment.algorithm.is_image() {
+            return Err(MixError::Decode(format!("Segment #{} ({:?}) is not an image segment", index, segment.algorithm)));
+        }
+        input.seek(SeekFrom::Start(segment.offset))?;
+        let payload = input.take(segment.compressed_len);
+        let (pixels, width, height, color_type) = decode_image_segment(segment.algorithm, payload, segment.uncompressed_len)?;
+        check_segment(index, segment, pixels.len() as u64, xxhash_rust::xxh3::xxh3_64(&pixels))?;
+        image = match image {
+            None => Some((pixels, width, height, color_type)),
+            Some((mut image_pixels, image_width, image_height, image_color_type)) => {
+                if width != image_width || color_type != image_color_type {
+                    return Err(MixError::Decode(format!("Segment #{} doesn't match the width or color type of the previous segments", index)));
+                }
+                image_pixels.extend_from_slice(&pixels);
+                Some((image_pixels, image_width, image_height + height, image_color_type))
+            }
+        };
+    }
+    image.ok_or_else(|| MixError::Decode(String::from("The container has no segments")))
+}
+
+/// Outcome of the verification of a single segment.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SegmentCheck {
+    /// The decoded segment matches the original data
+    Valid,
+    /// The decoded segment differs from the original data, holding the xxh3 hashes of the original and decoded data
+    Mismatch { expected: u64, found: u64 },
+    /// The segment couldn't be decoded at all
+    Undecodable(String),
+}
+
+#[derive(Debug)]
+pub struct SegmentReport {
+    pub index: usize,
+    pub segment: Segment,
+    pub check: SegmentCheck,
+}
+
+/// Result of the verification of a whole container against the original data.
+#[derive(Debug)]
+pub struct Verification {
+    pub reports: Vec<SegmentReport>,
+    /// Uncompressed bytes covered by the segments (pixels for image containers)
+    pub covered_len: u64,
+    /// Length of the original data (pixels for image containers)
+    pub original_len: u64,
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        self.covered_len == self.original_len && self.reports.iter().all(|report| report.check == SegmentCheck::Valid)
+    }
+}
+
+/// Decodes every segment of a container and compares its hash with the hash of the original data it should represent.
+/// For image containers, segments are compared against the pixels of the original image.
+pub fn verify(mut input: &File, mut original: &File) -> Result<Verification, MixError> {
+    let container = Container::read(input)?;
+    let original_pixels = if container.segments.iter().any(|segment| segment.algorithm.is_image()) {
+        Some(read_original_pixels(original)?)
+    } else {
+        None
+    };
+    let original_len = match &original_pixels {
+        Some(pixels) => pixels.len() as u64,
+        None => original.metadata()?.len(),
+    };
+    original.seek(SeekFrom::Start(0))?;
+
+    let mut reports = Vec::with_capacity(container.segments.len());
+    let mut covered_len = 0;
+    for (index, segment) in container.segments.iter().enumerate() {
+        let expected = match &original_pixels {
+            Some(pixels) => {
+                let start = (covered_len as usize).min(pixels.len());
+                let end = ((covered_len + segment.uncompressed_len) as usize).min(pixels.len());
+                xxhash_rust::xxh3::xxh3_64(&pixels[start..end])
+            }
+            None => {
+                let mut writer = HashingWriter { inner: &mut io::sink(), hasher: Xxh3::new(), written: 0 };
+                io::copy(&mut original.take(segment.uncompressed_len), &mut writer)?;
+                writer.hasher.digest()
+            }
+        };
+        covered_len += segment.uncompressed_len;
+
+        input.seek(SeekFrom::Start(segment.offset))?;
+        let mut writer = HashingWriter { inner: &mut io::sink(), hasher: Xxh3::new(), written: 0 };
+        let check = match decode_segment(segment, input.take(segment.compressed_len), &mut writer) {
+            Ok(()) => {
+                let found = writer.hasher.digest();
+                if found == expected && writer.written == segment.uncompressed_len {
+                    SegmentCheck::Valid
+                } else {
+                    SegmentCheck::Mismatch { expected, found }
+                }
+            }
+            Err(e) => SegmentCheck::Undecodable(e.to_string()),
+        };
+        reports.push(SegmentReport { index, segment: *segment, check });
+    }
+    Ok(Verification { reports, covered_len, original_len })
+}
+
+/// Reads the raw pixels of the original image, the same way image algorithms do before partitioning it.
+#[cfg(feature = "image")]
+fn read_original_pixels(original: &File) -> Result<Vec<u8>, MixError> {
+    let mut original = original;
+    original.seek(SeekFrom::Start(0))?;
+    let mut buffer = Vec::new();
+    original.read_to_end(&mut buffer)?;
+    Ok(image::load_from_memory(&buffer)?.into_bytes())
+}
+
+#[cfg(not(feature = "image"))]
+fn read_original_pixels(_: &File) -> Result<Vec<u8>, MixError> {
+    Err(MixError::UnsupportedMode(String::from("Verifying image containers requires the \"image\" feature")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, Write};
+    use tempfile::tempfile;
+    use crate::container::{AlgorithmId, Container, decompress, SegmentCheck, SegmentWriter, verify};
+
+    fn write_segment(target: &std::fs::File, algorithm: AlgorithmId, level: u32, data: &[u8]) {
+        let mut segment = SegmentWriter::begin(target, algorithm, level).unwrap();
+        segment.update(data);
+        match algorithm {
+            AlgorithmId::Gzip => {
+                let mut e = flate2::write::GzEncoder::new(target, flate2::Compression::new(level));
+                e.write_all(data).unwrap();
+                e.finish().unwrap();
+            }
+            AlgorithmId::Bzip2 => {
+                let mut e = bzip2::write::BzEncoder::new(target, bzip2::Compression::new(level));
+                e.write_all(data).unwrap();
+                e.finish().unwrap();
+            }
+            AlgorithmId::Xz2 => {
+                let mut e = xz2::write::XzEncoder::new(target, level);
+                e.write_all(data).unwrap();
+                e.finish().unwrap();
+            }
+            AlgorithmId::Zstd => {
+                let mut e = zstd::stream::write::Encoder::new(target, level as i32).unwrap();
+                e.write_all(data).unwrap();
+                e.finish().unwrap();
+            }
+            AlgorithmId::Lz4 => {
+                let mut e = lz4::EncoderBuilder::new().level(level).build(target).unwrap();
+                e.write_all(data).unwrap();
+                e.finish().1.unwrap();
+            }
+            AlgorithmId::Brotli => {
+                let mut e = brotli::CompressorWriter::new(target, 4096, level, 22);
+                e.write_all(data).unwrap();
+                e.flush().unwrap();
+                e.into_inner();
+            }
+            _ => unreachable!()
+        }
+        segment.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trip_mixed_families() {
+        let data = "Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura".repeat(100);
+        let (first, second) = data.as_bytes().split_at(2000);
+        let mut target = tempfile().unwrap();
+        write_segment(&target, AlgorithmId::Gzip, 3, first);
+        write_segment(&target, AlgorithmId::Xz2, 6, second);
+
+        let container = Container::read(&target).unwrap();
+        assert_eq!(container.segments.len(), 2);
+        assert_eq!(container.segments[0].algorithm, AlgorithmId::Gzip);
+        assert_eq!(container.segments[1].algorithm, AlgorithmId::Xz2);
+        assert_eq!(container.segments[1].uncompressed_len, second.len() as u64);
+
+        let mut output = Vec::new();
+        decompress(&target, &mut output).unwrap();
+        assert_eq!(output, data.as_bytes());
+
+        // The first member is still a regular gzip member
+        target.rewind().unwrap();
+        let mut first_member = Vec::new();
+        flate2::read::GzDecoder::new(&target).read_to_end(&mut first_member).unwrap();
+        assert_eq!(first_member, first);
+    }
+
+    #[test]
+    fn zstd_mix_is_a_regular_zstd_stream() {
+        let data = "Per me si va ne la città dolente".repeat(100);
+        let (first, second) = data.as_bytes().split_at(1000);
+        let mut target = tempfile().unwrap();
+        write_segment(&target, AlgorithmId::Zstd, 1, first);
+        write_segment(&target, AlgorithmId::Zstd, 19, second);
+
+        let mut output = Vec::new();
+        decompress(&target, &mut output).unwrap();
+        assert_eq!(output, data.as_bytes());
+
+        // Both frames are decoded by a plain zstd decoder, which skips the trailer
+        target.rewind().unwrap();
+        assert_eq!(zstd::stream::decode_all(&target).unwrap(), data.as_bytes());
+    }
+
+    #[test]
+    fn round_trip_lz4_and_brotli() {
+        let data = "Lasciate ogne speranza, voi ch'intrate".repeat(100);
+        let (first, second) = data.as_bytes().split_at(1200);
+        let target = tempfile().unwrap();
+        write_segment(&target, AlgorithmId::Lz4, 9, first);
+        write_segment(&target, AlgorithmId::Brotli, 11, second);
+
+        let mut output = Vec::new();
+        decompress(&target, &mut output).unwrap();
+        assert_eq!(output, data.as_bytes());
+    }
+
+    #[test]
+    fn corrupted_segment() {
+        let data = "Ahi quanto a dir qual era è cosa dura".repeat(50);
+        let target = tempfile().unwrap();
+        write_segment(&target, AlgorithmId::Bzip2, 9, data.as_bytes());
+        let mut segment = SegmentWriter::begin(&target, AlgorithmId::Gzip, 1).unwrap();
+        // Declare more data than what is actually compressed
+        segment.update(data.as_bytes());
+        segment.update(b"missing");
+        let mut e = flate2::write::GzEncoder::new(&target, flate2::Compression::new(1));
+        e.write_all(data.as_bytes()).unwrap();
+        e.finish().unwrap();
+        segment.finish().unwrap();
+
+        let mut output = Vec::new();
+        assert!(decompress(&target, &mut output).is_err());
+    }
+
+    #[test]
+    fn corrupted_segment_table() {
+        use std::io::SeekFrom;
+        use crate::container::{FOOTER_SIZE, SEGMENT_ENTRY_SIZE};
+        use crate::error::MixError;
+        let data = "Amor, ch'a nullo amato amar perdona".repeat(50);
+        let mut target = tempfile().unwrap();
+        write_segment(&target, AlgorithmId::Gzip, 6, data.as_bytes());
+        let len = target.metadata().unwrap().len();
+
+        // A huge segment count must not be allocated
+        target.seek(SeekFrom::Start(len - FOOTER_SIZE)).unwrap();
+        target.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        assert!(matches!(Container::read(&target), Err(MixError::Decode(_))));
+        target.seek(SeekFrom::Start(len - FOOTER_SIZE)).unwrap();
+        target.write_all(&1u32.to_be_bytes()).unwrap();
+        assert!(Container::read(&target).is_ok());
+
+        // Nor can a segment run past the table
+        target.seek(SeekFrom::Start(len - FOOTER_SIZE - SEGMENT_ENTRY_SIZE + 1 + 4 + 8)).unwrap();
+        target.write_all(&u64::MAX.to_be_bytes()).unwrap();
+        assert!(matches!(Container::read(&target), Err(MixError::Decode(_))));
+    }
+
+    #[test]
+    fn verify_against_original() {
+        let data = "E io a lui: \"Poeta, io ti richeggio per quello Dio che tu non conoscesti\"".repeat(80);
+        let (first, second) = data.as_bytes().split_at(1500);
+        let mut original = tempfile().unwrap();
+        original.write_all(data.as_bytes()).unwrap();
+        let target = tempfile().unwrap();
+        write_segment(&target, AlgorithmId::Bzip2, 1, first);
+        write_segment(&target, AlgorithmId::Gzip, 9, second);
+
+        let verification = verify(&target, &original).unwrap();
+        assert!(verification.is_valid());
+        assert_eq!(verification.reports.len(), 2);
+
+        // Altering the original data must be reported on the affected segment only
+        original.rewind().unwrap();
+        original.write_all(b"Ahi").unwrap();
+        let verification = verify(&target, &original).unwrap();
+        assert!(!verification.is_valid());
+        assert!(matches!(verification.reports[0].check, SegmentCheck::Mismatch { .. }));
+        assert_eq!(verification.reports[1].check, SegmentCheck::Valid);
+    }
+
+    /// A workload of the image saved as png, and the partition splitting it in halves.
+    #[cfg(feature = "image")]
+    fn image_workload(image: &image::DynamicImage) -> (crate::workload::Workload, usize) {
+        let mut data = tempfile().unwrap();
+        image.write_to(&mut data, image::ImageOutputFormat::Png).unwrap();
+        data.rewind().unwrap();
+        let partition = data.metadata().unwrap().len() as usize / 2;
+        (crate::workload::Workload::new(String::from("image"), data, std::time::Duration::from_secs(1), Some(tempfile().unwrap())).unwrap(), partition)
+    }
+
+    /// Compresses both halves of an image with the given algorithm, and checks that the decompressed image is the original one.
+    #[cfg(feature = "image")]
+    fn round_trip_image(algorithm: &dyn crate::algorithms::Algorithm, image: image::DynamicImage) {
+        let (mut workload, partition) = image_workload(&image);
+        algorithm.execute_with_target(&mut workload, partition, true).unwrap();
+        algorithm.execute_with_target(&mut workload, partition, false).unwrap();
+
+        let container = Container::read(&workload.result_file).unwrap();
+        assert_eq!(container.segments.len(), 2);
+        let (pixels, width, height, color_type) = crate::container::decompress_image(&workload.result_file).unwrap();
+        assert_eq!((width, height, color_type), (image.width(), image.height(), image.color()));
+        assert_eq!(pixels, image.as_bytes());
+    }
+
+    /// A noisy gradient, compressible but not trivially.
+    #[cfg(feature = "image")]
+    fn gradient(width: u32, height: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let noise = ((x * 7919 + y * 104729) % 13) as u8;
+            image::Rgba([(x * 4) as u8 ^ noise, (y * 5) as u8, (x + y) as u8 + noise, 255 - noise * 3])
+        })
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn round_trip_jpegxl() {
+        round_trip_image(&crate::algorithms::jpegxl::JPEGXL::new(), image::DynamicImage::ImageRgba8(gradient(40, 30)).into_rgb8().into());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_larger_than_its_segment() {
+        use std::io::SeekFrom;
+        use crate::algorithms::Algorithm;
+        use crate::container::{FOOTER_SIZE, SEGMENT_ENTRY_SIZE};
+        use crate::error::MixError;
+        let image = image::DynamicImage::ImageRgba8(gradient(40, 30)).into_rgb8().into();
+        let (mut workload, partition) = image_workload(&image);
+        crate::algorithms::jpegxl::JPEGXL::new().execute_with_target(&mut workload, partition, true).unwrap();
+        crate::algorithms::jpegxl::JPEGXL::new().execute_with_target(&mut workload, partition, false).unwrap();
+
+        // Shrink the uncompressed length declared by the last segment, as a corrupt or hostile table would
+        let len = workload.result_file.metadata().unwrap().len();
+        workload.result_file.seek(SeekFrom::Start(len - FOOTER_SIZE - SEGMENT_ENTRY_SIZE + 1 + 4 + 8 + 8)).unwrap();
+        workload.result_file.write_all(&10u64.to_be_bytes()).unwrap();
+        assert!(matches!(crate::container::decompress_image(&workload.result_file), Err(MixError::Decode(_))));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn verify_jpegxl_and_felics_mix() {
+        use crate::algorithms::Algorithm;
+        let image = image::DynamicImage::ImageRgba8(gradient(40, 30)).into_rgb8().into();
+        let (mut workload, partition) = image_workload(&image);
+        crate::algorithms::jpegxl::JPEGXL::new().execute_with_target(&mut workload, partition, true).unwrap();
+        crate::algorithms::felics::FELICS::new().execute_with_target(&mut workload, partition, false).unwrap();
+
+        // The JPEG XL half is checked, and the FELICS half is reported instead of failing the whole verification
+        let verification = verify(&workload.result_file, &workload.data).unwrap();
+        assert!(!verification.is_valid());
+        assert_eq!(verification.reports[0].check, SegmentCheck::Valid);
+        assert!(matches!(verification.reports[1].check, SegmentCheck::Undecodable(_)));
+        assert_eq!(verification.reports.iter().map(|report| report.segment.algorithm).collect::<Vec<_>>(), vec![AlgorithmId::JpegXl, AlgorithmId::Felics]);
+    }
+}
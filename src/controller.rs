@@ -0,0 +1,164 @@
+use std::io::{copy, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+use tempfile::tempfile;
+use crate::algorithms::{Algorithm, AlgorithmMetrics};
+use crate::error::MixError;
+use crate::workload::Workload;
+
+/// A chunk of the expensive setup smaller than this fraction of the chunk size isn't worth its own segment.
+const MIN_CHUNK_FRACTION: f64 = 0.1;
+
+/// Applies a mix in closed loop: the expensive half is compressed one chunk at a time, and the partition is moved after each chunk so that the mix ends at the time budget.
+///
+/// After each chunk, the actual throughput of the expensive setup replaces the predicted one, and the cheap setup is assumed to be slowed down (or sped up) by the same factor,
+/// as both run on the same machine under the same load. The partition is then placed where the remaining bytes, compressed with both setups, take the remaining budget.
+/// Each chunk is a segment of the resulting container, followed by a single segment of the cheap setup: there is nothing left to trade once the expensive half is over.
+///
+/// Only byte ranges are chunked, so image setups (which partition rows) should be applied in open loop.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Controller {
+    /// Chunks the expensive half of the planned mix is split into. More chunks react sooner, but every segment restarts the compression context
+    pub chunks: u64,
+}
+
+/// How a mix was actually applied by a [Controller].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ControlReport {
+    pub planned_partition: u64,
+    pub final_partition: u64,
+    /// Segments written, chunks of the expensive setup included
+    pub segments: usize,
+    pub elapsed: Duration,
+}
+
+impl Controller {
+    pub fn apply(&self, (expensive, cheap): (&AlgorithmMetrics, &AlgorithmMetrics), fraction: f64, workload: &mut Workload) -> Result<ControlReport, MixError> {
+        let instant = Instant::now();
+        let data_len = workload.data.metadata()?.len();
+        let budget = workload.time_budget.as_secs_f64();
+        let planned_partition = (data_len as f64 * fraction).round() as u64;
+        let chunk_size = planned_partition.div_ceil(self.chunks.max(1)).max(1);
+        let bytes = data_len.max(1) as f64;
+        let (predicted_expensive, predicted_cheap) = (expensive.time_required.as_secs_f64() / bytes, cheap.time_required.as_secs_f64() / bytes);
+        log::info!("Applying mix in closed loop: planned partition at {} of {} bytes, chunks of {} bytes", planned_partition, data_len, chunk_size);
+
+        let (mut partition, mut segments) = (0, 0);
+        let mut expensive_time = 0.;
+        // The planned mix starts with a chunk of the expensive setup, there is nothing to correct yet
+        let mut next_chunk = chunk_size.min(planned_partition);
+        while next_chunk > 0 {
+            let chunk_instant = Instant::now();
+            compress_range(expensive.algorithm.as_ref(), workload, partition, partition + next_chunk)?;
+            expensive_time += chunk_instant.elapsed().as_secs_f64();
+            partition += next_chunk;
+            segments += 1;
+
+            // Seconds per byte of both setups, corrected by the slowdown measured so far
+            let slowdown = if predicted_expensive > 0. { expensive_time / partition as f64 / predicted_expensive } else { 1. };
+            let (expensive_rate, cheap_rate) = (predicted_expensive * slowdown, predicted_cheap * slowdown);
+            let remaining_budget = budget - instant.elapsed().as_secs_f64();
+            let remaining_bytes = (data_len - partition) as f64;
+            // The bytes of the expensive setup such that `x * expensive_rate + (remaining_bytes - x) * cheap_rate = remaining_budget`
+            let expensive_bytes = if expensive_rate > cheap_rate {
+                ((remaining_budget - remaining_bytes * cheap_rate) / (expensive_rate - cheap_rate)).clamp(0., remaining_bytes)
+            } else {
+                remaining_bytes
+            };
+            log::debug!("Closed loop: {} bytes compressed with {} (slowdown {:.3}), {:.3}s left, {} more bytes planned for it",
+                partition, expensive.algorithm.name(), slowdown, remaining_budget, expensive_bytes as u64);
+            next_chunk = (expensive_bytes as u64).min(chunk_size);
+            if (next_chunk as f64) < chunk_size as f64 * MIN_CHUNK_FRACTION && next_chunk as f64 != remaining_bytes {
+                next_chunk = 0;
+            }
+        }
+        if partition < data_len {
+            compress_range(cheap.algorithm.as_ref(), workload, partition, data_len)?;
+            segments += 1;
+        }
+        let report = ControlReport { planned_partition, final_partition: partition, segments, elapsed: instant.elapsed() };
+        log::info!("Closed loop mix applied: partition moved from {} to {} bytes, {} segments, time passed: {:?} (time budget: {:?})",
+            report.planned_partition, report.final_partition, report.segments, report.elapsed, workload.time_budget);
+        Ok(report)
+    }
+}
+
+/// Compresses a byte range of the workload as a segment of its result.
+/// Ranges at the start or at the end of the workload are compressed in place, others are copied to a temporary file first.
+pub(crate) fn compress_range(algorithm: &dyn Algorithm, workload: &mut Workload, start: u64, end: u64) -> Result<(), MixError> {
+    let data_len = workload.data.metadata()?.len();
+    if start == 0 {
+        return algorithm.execute_with_target(workload, end as usize, true);
+    }
+    if end == data_len {
+        return algorithm.execute_with_target(workload, start as usize, false);
+    }
+    let mut chunk = tempfile()?;
+    workload.data.seek(SeekFrom::Start(start))?;
+    copy(&mut (&workload.data).take(end - start), &mut chunk)?;
+    workload.data.rewind()?;
+    chunk.rewind()?;
+    let mut chunk_workload = Workload::new(format!("{} ({}..{})", workload.name, start, end), chunk, workload.time_budget, Some(workload.result_file.try_clone()?))?;
+    algorithm.execute_with_target(&mut chunk_workload, (end - start) as usize, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, Write};
+    use std::time::Duration;
+    use tempfile::tempfile;
+    use crate::algorithms::AlgorithmMetrics;
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::algorithms::xz2::{Xz2, Xz2CompressionLevel};
+    use crate::container::{decompress, AlgorithmId, Container};
+    use crate::controller::Controller;
+    use crate::workload::Workload;
+
+    fn workload(time_budget: Duration) -> (Workload, Vec<u8>) {
+        let original = "Amor, ch'a nullo amato amar perdona, mi prese del costui piacer sì forte".repeat(2000).into_bytes();
+        let mut data = tempfile().unwrap();
+        data.write_all(&original).unwrap();
+        data.rewind().unwrap();
+        (Workload::new(String::from("controller"), data, time_budget, Some(tempfile().unwrap())).unwrap(), original)
+    }
+
+    fn decompressed(workload: &mut Workload) -> Vec<u8> {
+        workload.result_file.rewind().unwrap();
+        let mut output = Vec::new();
+        decompress(&workload.result_file, &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn faster_than_predicted_moves_the_partition_forward() {
+        // Both setups are predicted to take far longer than they do, so the expensive one ends up compressing everything
+        let expensive = AlgorithmMetrics::new(Box::new(Xz2::new(Xz2CompressionLevel(6))), 100, Duration::from_secs(100));
+        let cheap = AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(1))), 200, Duration::from_secs(50));
+        let (mut workload, original) = workload(Duration::from_secs(75));
+        let report = Controller { chunks: 4 }.apply((&expensive, &cheap), 0.5, &mut workload).unwrap();
+        assert_eq!(report.planned_partition, original.len() as u64 / 2);
+        assert_eq!(report.final_partition, original.len() as u64);
+        let container = Container::read(&workload.result_file).unwrap();
+        assert!(container.segments.len() > 4);
+        assert!(container.segments.iter().all(|segment| segment.algorithm == AlgorithmId::Xz2));
+        assert_eq!(decompressed(&mut workload), original);
+    }
+
+    #[test]
+    fn exhausted_budget_switches_to_the_cheap_setup() {
+        let expensive = AlgorithmMetrics::new(Box::new(Xz2::new(Xz2CompressionLevel(6))), 100, Duration::from_millis(2));
+        let cheap = AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(1))), 200, Duration::from_millis(1));
+        let (mut workload, original) = workload(Duration::ZERO);
+        let report = Controller { chunks: 4 }.apply((&expensive, &cheap), 0.8, &mut workload).unwrap();
+        // Only the first chunk, compressed before any measurement, uses the expensive setup
+        assert_eq!(report.final_partition, report.planned_partition.div_ceil(4));
+        let mut algorithms = Container::read(&workload.result_file).unwrap().segments.into_iter().map(|segment| segment.algorithm);
+        assert_eq!(algorithms.next(), Some(AlgorithmId::Xz2));
+        assert_eq!(algorithms.next(), Some(AlgorithmId::Gzip));
+        assert_eq!(algorithms.next(), None);
+        assert_eq!(decompressed(&mut workload), original);
+
+        let mut data = Vec::new();
+        workload.data.read_to_end(&mut data).unwrap();
+        assert_eq!(data, original);
+    }
+}
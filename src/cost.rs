@@ -0,0 +1,137 @@
+use std::fmt;
+use std::fs::write;
+use std::path::Path;
+use std::time::Duration;
+use crate::algorithms::{AlgorithmMetrics, ByteSize};
+use crate::error::MixError;
+use crate::mixing_policy::OptimalMix;
+
+/// Bytes in a GB, as storage is billed.
+const BYTES_PER_GB: f64 = 1e9;
+
+/// Prices of the resources used by a compression job, to trade dollars rather than seconds for bytes.
+///
+/// A mix costs its compression time on one core, the storage of its result for the whole retention period, and the decompression of the result at every read.
+/// Decompression takes the decompression time of each setup of the mix when it has one (see [AlgorithmMetrics::decompression_time]), or else `decompression_seconds_per_gb` of cpu time per uncompressed GB.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CostModel {
+    /// Dollars per second of compression (or decompression) on one core
+    pub cpu_price_per_core_second: f64,
+    /// Dollars to store a GB for a month
+    pub storage_price_per_gb_month: f64,
+    /// Months the results are stored for
+    pub retention_months: f64,
+    /// Times the results are expected to be read (and decompressed) during the retention period
+    pub reads: f64,
+    /// Seconds of cpu time to decompress a GB of data, for mixes without a decompression time
+    pub decompression_seconds_per_gb: f64,
+}
+
+/// Dollars spent on a workload, by resource.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct CostBreakdown {
+    pub compression: f64,
+    pub storage: f64,
+    pub decompression: f64,
+}
+
+impl CostBreakdown {
+    pub fn total(&self) -> f64 {
+        self.compression + self.storage + self.decompression
+    }
+}
+
+impl CostModel {
+    /// Dollars to store a byte for the whole retention period.
+    pub fn storage_price_per_byte(&self) -> f64 {
+        self.storage_price_per_gb_month * self.retention_months / BYTES_PER_GB
+    }
+
+    /// Bytes a second of compression must save to pay for itself: moving to a more expensive setup of the lower convex hull lowers the total cost
+    /// as long as its benefit (the bytes saved per additional second) is above this one.
+    pub fn break_even_benefit(&self) -> f64 {
+        let storage_price_per_byte = self.storage_price_per_byte();
+        if storage_price_per_byte > 0. {
+            self.cpu_price_per_core_second / storage_price_per_byte
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// The cost of a workload of `uncompressed_size` bytes, compressed to `compressed_size` bytes in `time_required` and decompressed in `decompression_time` if known.
+    pub fn cost(&self, compressed_size: ByteSize, time_required: Duration, uncompressed_size: ByteSize, decompression_time: Option<Duration>) -> CostBreakdown {
+        let decompression_seconds = decompression_time.map_or(uncompressed_size as f64 / BYTES_PER_GB * self.decompression_seconds_per_gb, |decompression_time| decompression_time.as_secs_f64());
+        CostBreakdown {
+            compression: time_required.as_secs_f64() * self.cpu_price_per_core_second,
+            storage: compressed_size as f64 * self.storage_price_per_byte(),
+            decompression: self.reads * decompression_seconds * self.cpu_price_per_core_second,
+        }
+    }
+
+    /// The cost of a workload of `uncompressed_size` bytes compressed with a mix: the cost of each setup, weighted by the fraction of the data it compresses.
+    pub fn mix_cost(&self, optimal_mix: &OptimalMix, uncompressed_size: ByteSize) -> CostBreakdown {
+        let setup_cost = |metrics: &AlgorithmMetrics| self.cost(metrics.compressed_size, metrics.time_required, uncompressed_size, metrics.decompression_time);
+        match optimal_mix {
+            OptimalMix::Single(metrics) => setup_cost(metrics),
+            OptimalMix::Normal((expensive, cheap), fraction) => {
+                let (expensive, cheap) = (setup_cost(expensive), setup_cost(cheap));
+                CostBreakdown {
+                    compression: expensive.compression * fraction + cheap.compression * (1. - fraction),
+                    storage: expensive.storage * fraction + cheap.storage * (1. - fraction),
+                    decompression: expensive.decompression * fraction + cheap.decompression * (1. - fraction),
+                }
+            }
+        }
+    }
+}
+
+/// The expected cost of a workload, once its mix is chosen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadCost {
+    pub name: String,
+    pub compressed_size: ByteSize,
+    pub time_required: Duration,
+    pub breakdown: CostBreakdown,
+}
+
+/// The expected cost of every workload of a job.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CostReport {
+    pub workloads: Vec<WorkloadCost>,
+}
+
+impl CostReport {
+    pub fn total(&self) -> CostBreakdown {
+        self.workloads.iter().fold(CostBreakdown::default(), |total, workload| CostBreakdown {
+            compression: total.compression + workload.breakdown.compression,
+            storage: total.storage + workload.breakdown.storage,
+            decompression: total.decompression + workload.breakdown.decompression,
+        })
+    }
+
+    /// Saves the report as a csv file, with a line per workload and the columns
+    /// `workload,compressed_size,time_required,compression_cost,storage_cost,decompression_cost,total_cost` (time in seconds, costs in dollars).
+    pub fn save(&self, path: &Path) -> Result<(), MixError> {
+        let mut csv = String::from("workload,compressed_size,time_required,compression_cost,storage_cost,decompression_cost,total_cost\n");
+        for workload in &self.workloads {
+            let breakdown = workload.breakdown;
+            csv.push_str(&format!("{},{},{},{},{},{},{}\n", workload.name, workload.compressed_size, workload.time_required.as_secs_f64(),
+                breakdown.compression, breakdown.storage, breakdown.decompression, breakdown.total()));
+        }
+        write(path, csv)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected cost per workload (compression + storage + decompression = total):")?;
+        for workload in &self.workloads {
+            let breakdown = workload.breakdown;
+            write!(f, "\n'{}' ({} bytes in {:?}): ${:.6} + ${:.6} + ${:.6} = ${:.6}", workload.name, workload.compressed_size, workload.time_required,
+                breakdown.compression, breakdown.storage, breakdown.decompression, breakdown.total())?;
+        }
+        let total = self.total();
+        write!(f, "\nTotal: ${:.6} + ${:.6} + ${:.6} = ${:.6}", total.compression, total.storage, total.decompression, total.total())
+    }
+}
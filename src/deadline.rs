@@ -0,0 +1,242 @@
+use std::ptr;
+use std::time::{Duration, Instant};
+use crate::algorithms::{AlgorithmMetrics, ByteSize};
+use crate::algorithms::stored::Stored;
+use crate::controller::compress_range;
+use crate::error::MixError;
+use crate::mixing_policy::OptimalMix;
+use crate::workload::Workload;
+
+/// Applies mixes under a hard deadline: exceeding the budget is worse than a larger result.
+///
+/// The data of the workloads is compressed in chunks, and the elapsed time is checked before each one. A chunk keeps its planned setup only if,
+/// at the throughput measured so far, it leaves enough time to compress everything after it with the cheapest setup of each hull.
+/// Otherwise the remaining data falls back to the cheapest setups, and once not even those fit in the time left, the rest is stored uncompressed.
+/// A running chunk can't be interrupted, so the deadline holds as long as no single chunk is much slower than the chunks before it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Deadline {
+    /// Chunks the data of all the workloads is split into, i.e. how many times the elapsed time is checked. Every chunk is a segment of its result
+    pub checkpoints: u64,
+}
+
+/// How a plan was actually applied by the deadline mode.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeadlineReport {
+    pub elapsed: Duration,
+    pub budget: Duration,
+    /// Bytes compressed with the cheapest setup of their hull instead of the planned one
+    pub fallback_bytes: u64,
+    /// Bytes stored uncompressed
+    pub stored_bytes: u64,
+    /// How much the planned setups were projected to overrun the deadline at the first fallback, zero if the plan was kept
+    pub projected_overrun: Duration,
+    /// Expected growth of the result compared to the plan, from the compression ratio of each setup
+    pub size_shortfall: ByteSize,
+}
+
+impl DeadlineReport {
+    pub fn is_met(&self) -> bool {
+        self.elapsed <= self.budget
+    }
+}
+
+/// The planned setups of a workload, and the cheapest setup of its hull to fall back on.
+pub(crate) struct DeadlineJob<'a, 'w> {
+    pub workload: &'w mut Workload,
+    /// Setups of the planned mix in order, each one with the end of the byte range it compresses
+    pub parts: Vec<(&'a AlgorithmMetrics, u64)>,
+    pub cheapest: &'a AlgorithmMetrics,
+}
+
+impl<'a, 'w> DeadlineJob<'a, 'w> {
+    pub fn new(optimal_mix: &OptimalMix<'a>, workload: &'w mut Workload, cheapest: &'a AlgorithmMetrics) -> Result<DeadlineJob<'a, 'w>, MixError> {
+        let data_len = workload.data.metadata()?.len();
+        let parts = match optimal_mix {
+            OptimalMix::Single(metrics) => vec![(*metrics, data_len)],
+            OptimalMix::Normal((metric_a, metric_b), fraction) => {
+                vec![(*metric_a, ((data_len as f64) * fraction).round() as u64), (*metric_b, data_len)]
+            }
+        };
+        Ok(DeadlineJob { workload, parts, cheapest })
+    }
+
+    /// The planned setup of the byte at `position`, with the end of its range.
+    fn part_at(&self, position: u64) -> (&'a AlgorithmMetrics, u64) {
+        *self.parts.iter().find(|(_, end)| *end > position).expect("The parts of a job cover its whole workload")
+    }
+
+    /// Predicted time and compressed size of the planned setups on the bytes from `position` onwards.
+    fn planned_from(&self, position: u64, data_len: u64) -> (f64, f64) {
+        let mut start = 0;
+        let (mut time, mut size) = (0., 0.);
+        for &(metrics, end) in &self.parts {
+            let bytes = end.saturating_sub(start.max(position)) as f64;
+            time += bytes * seconds_per_byte(metrics, data_len);
+            size += bytes * ratio(metrics, data_len);
+            start = end;
+        }
+        (time, size)
+    }
+}
+
+fn seconds_per_byte(metrics: &AlgorithmMetrics, data_len: u64) -> f64 {
+    metrics.time_required.as_secs_f64() / data_len.max(1) as f64
+}
+
+fn ratio(metrics: &AlgorithmMetrics, data_len: u64) -> f64 {
+    metrics.compressed_size as f64 / data_len.max(1) as f64
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Fallback {
+    Planned,
+    Cheapest,
+    Stored,
+}
+
+impl Deadline {
+    /// Applies the jobs one after the other, so that all of them end within the budget.
+    pub(crate) fn apply(&self, jobs: &mut [DeadlineJob], budget: Duration) -> Result<DeadlineReport, MixError> {
+        let instant = Instant::now();
+        let data_lens = jobs.iter().map(|job| Ok(job.workload.data.metadata()?.len())).collect::<Result<Vec<u64>, MixError>>()?;
+        let chunk_size = data_lens.iter().sum::<u64>().div_ceil(self.checkpoints.max(1)).max(1);
+        // Predicted time of the cheapest setups, and of the planned ones, on the workloads after each one
+        let mut cheapest_after = vec![0.; jobs.len()];
+        let mut planned_after = vec![0.; jobs.len()];
+        for index in (1..jobs.len()).rev() {
+            cheapest_after[index - 1] = cheapest_after[index] + data_lens[index] as f64 * seconds_per_byte(jobs[index].cheapest, data_lens[index]);
+            planned_after[index - 1] = planned_after[index] + jobs[index].planned_from(0, data_lens[index]).0;
+        }
+
+        let mut report = DeadlineReport { elapsed: Duration::ZERO, budget, fallback_bytes: 0, stored_bytes: 0, projected_overrun: Duration::ZERO, size_shortfall: 0 };
+        let mut size_shortfall = 0.;
+        let mut fallback = Fallback::Planned;
+        // Actual and predicted time of the chunks compressed so far, their ratio being how much slower than profiled the setups run
+        let (mut actual_time, mut predicted_time) = (0., 0.);
+        for (index, job) in jobs.iter_mut().enumerate() {
+            let data_len = data_lens[index];
+            let mut start = 0;
+            while start < data_len {
+                if fallback == Fallback::Stored {
+                    compress_range(&Stored::new(), job.workload, start, data_len)?;
+                    report.stored_bytes += data_len - start;
+                    size_shortfall += (data_len - start) as f64 - job.planned_from(start, data_len).1;
+                    break;
+                }
+                let (planned, part_end) = job.part_at(start);
+                let chunk_end = (start + chunk_size).min(part_end);
+                let bytes = (chunk_end - start) as f64;
+                let slowdown = if predicted_time > 0. { actual_time / predicted_time } else { 1. };
+                let remaining_budget = budget.as_secs_f64() - instant.elapsed().as_secs_f64();
+                let cheapest_reserve = ((data_len - chunk_end) as f64 * seconds_per_byte(job.cheapest, data_len) + cheapest_after[index]) * slowdown;
+                if fallback == Fallback::Planned && bytes * seconds_per_byte(planned, data_len) * slowdown + cheapest_reserve > remaining_budget {
+                    let projected = (job.planned_from(start, data_len).0 + planned_after[index]) * slowdown;
+                    report.projected_overrun = Duration::from_secs_f64((projected - remaining_budget).max(0.));
+                    log::warn!("Deadline mode: {:.3}s left but the plan needs about {:.3}s more, falling back to the cheapest setups", remaining_budget, projected);
+                    fallback = Fallback::Cheapest;
+                }
+                // Storing is assumed to take no time, so the cheapest setup only has to fit by itself
+                if fallback == Fallback::Cheapest && bytes * seconds_per_byte(job.cheapest, data_len) * slowdown > remaining_budget {
+                    log::warn!("Deadline mode: {:.3}s left, not even the cheapest setups fit anymore, storing the remaining data", remaining_budget);
+                    fallback = Fallback::Stored;
+                    continue;
+                }
+
+                let metrics = if fallback == Fallback::Planned { planned } else { job.cheapest };
+                let chunk_instant = Instant::now();
+                compress_range(metrics.algorithm.as_ref(), job.workload, start, chunk_end)?;
+                actual_time += chunk_instant.elapsed().as_secs_f64();
+                predicted_time += bytes * seconds_per_byte(metrics, data_len);
+                if !ptr::eq(metrics, planned) {
+                    report.fallback_bytes += chunk_end - start;
+                    size_shortfall += bytes * (ratio(metrics, data_len) - ratio(planned, data_len));
+                }
+                start = chunk_end;
+            }
+        }
+        report.elapsed = instant.elapsed();
+        report.size_shortfall = size_shortfall.max(0.) as ByteSize;
+        if fallback == Fallback::Planned {
+            log::info!("Deadline mode: plan applied as is in {:?} (deadline: {:?})", report.elapsed, budget);
+        } else {
+            log::warn!("Deadline mode: the plan would have overrun the deadline by about {:?}. {} bytes compressed with the cheapest setups and {} bytes stored instead, about {} bytes larger than planned. Time passed: {:?} (deadline: {:?})",
+                report.projected_overrun, report.fallback_bytes, report.stored_bytes, report.size_shortfall, report.elapsed, budget);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, Write};
+    use std::time::Duration;
+    use tempfile::tempfile;
+    use crate::algorithms::AlgorithmMetrics;
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::algorithms::xz2::{Xz2, Xz2CompressionLevel};
+    use crate::container::{decompress, AlgorithmId, Container};
+    use crate::deadline::{Deadline, DeadlineJob};
+    use crate::mixing_policy::OptimalMix;
+    use crate::workload::Workload;
+
+    fn workload(text: &str) -> (Workload, Vec<u8>) {
+        let original = text.repeat(2000).into_bytes();
+        let mut data = tempfile().unwrap();
+        data.write_all(&original).unwrap();
+        data.rewind().unwrap();
+        (Workload::new(String::from("deadline"), data, Duration::ZERO, Some(tempfile().unwrap())).unwrap(), original)
+    }
+
+    fn segments(workload: &mut Workload, original: &[u8]) -> Vec<AlgorithmId> {
+        workload.result_file.rewind().unwrap();
+        let mut output = Vec::new();
+        decompress(&workload.result_file, &mut output).unwrap();
+        assert_eq!(output, original);
+        Container::read(&workload.result_file).unwrap().segments.into_iter().map(|segment| segment.algorithm).collect()
+    }
+
+    #[test]
+    fn plan_kept_within_the_deadline() {
+        let expensive = AlgorithmMetrics::new(Box::new(Xz2::new(Xz2CompressionLevel(6))), 100, Duration::from_millis(2));
+        let cheap = AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(1))), 200, Duration::from_millis(1));
+        let (mut workload, original) = workload("Per me si va ne la città dolente, per me si va ne l'etterno dolore");
+        let job = DeadlineJob::new(&OptimalMix::Normal((&expensive, &cheap), 0.5), &mut workload, &cheap).unwrap();
+        let report = Deadline { checkpoints: 4 }.apply(&mut [job], Duration::from_secs(60)).unwrap();
+        assert!(report.is_met());
+        assert_eq!((report.fallback_bytes, report.stored_bytes, report.size_shortfall), (0, 0, 0));
+        assert_eq!(segments(&mut workload, &original), vec![AlgorithmId::Xz2, AlgorithmId::Xz2, AlgorithmId::Gzip, AlgorithmId::Gzip]);
+    }
+
+    #[test]
+    fn fall_back_to_the_cheapest_setup_of_each_workload() {
+        // The planned setups are predicted to overrun the budget by far, while the cheapest ones fit
+        let expensive = AlgorithmMetrics::new(Box::new(Xz2::new(Xz2CompressionLevel(6))), 100, Duration::from_secs(1000));
+        let cheapest = AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(1))), 200, Duration::from_millis(1));
+        let (mut first, first_original) = workload("Nessun maggior dolore che ricordarsi del tempo felice ne la miseria");
+        let (mut second, second_original) = workload("E quindi uscimmo a riveder le stelle");
+        let mut jobs = [
+            DeadlineJob::new(&OptimalMix::Single(&expensive), &mut first, &cheapest).unwrap(),
+            DeadlineJob::new(&OptimalMix::Normal((&expensive, &cheapest), 0.5), &mut second, &cheapest).unwrap(),
+        ];
+        let report = Deadline { checkpoints: 8 }.apply(&mut jobs, Duration::from_secs(60)).unwrap();
+        assert!(report.is_met());
+        assert_eq!(report.fallback_bytes, (first_original.len() + second_original.len() / 2) as u64);
+        assert_eq!(report.stored_bytes, 0);
+        assert!(report.projected_overrun > Duration::from_secs(1000));
+        assert!(report.size_shortfall > 0);
+        assert!(segments(&mut first, &first_original).iter().all(|algorithm| *algorithm == AlgorithmId::Gzip));
+        assert!(segments(&mut second, &second_original).iter().all(|algorithm| *algorithm == AlgorithmId::Gzip));
+    }
+
+    #[test]
+    fn store_what_no_setup_can_compress_in_time() {
+        let expensive = AlgorithmMetrics::new(Box::new(Xz2::new(Xz2CompressionLevel(6))), 100, Duration::from_secs(2));
+        let cheapest = AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(1))), 200, Duration::from_secs(1));
+        let (mut workload, original) = workload("Fatti non foste a viver come bruti, ma per seguir virtute e canoscenza");
+        let job = DeadlineJob::new(&OptimalMix::Single(&expensive), &mut workload, &cheapest).unwrap();
+        let report = Deadline { checkpoints: 4 }.apply(&mut [job], Duration::ZERO).unwrap();
+        assert_eq!(report.stored_bytes, original.len() as u64);
+        assert!(report.size_shortfall.abs_diff(original.len() as u64 - 100) <= 1);
+        assert_eq!(segments(&mut workload, &original), vec![AlgorithmId::Stored]);
+    }
+}
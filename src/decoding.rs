@@ -0,0 +1,154 @@
+use std::time::Duration;
+use crate::algorithms::AlgorithmMetrics;
+use crate::mixing_policy::OptimalMix;
+
+/// How the decompression time of the result is traded for its size, when the metrics of the setups include it (see [AlgorithmMetrics::decompression_time]).
+///
+/// The mix minimizes `size + weight * decompression time`, within the time budget and within the decode budget if any.
+/// A zero weight with a decode budget finds the smallest result that can be decompressed in time.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct DecodingObjective {
+    /// Bytes a second of decompression is worth
+    pub weight: f64,
+    /// Maximum expected time to decompress the result
+    pub decode_budget: Option<Duration>,
+}
+
+/// A candidate mix, with the fraction of its first setup and its objective value.
+type Candidate<'a> = ((&'a AlgorithmMetrics, &'a AlgorithmMetrics), f64, f64);
+
+impl DecodingObjective {
+    fn value(&self, metrics: &AlgorithmMetrics, decompression_time: Duration) -> f64 {
+        metrics.compressed_size as f64 + self.weight * decompression_time.as_secs_f64()
+    }
+}
+
+/// The mix of two setups with the smallest objective, whose expected compression time is within the time budget and whose expected decompression time is within the decode budget.
+/// Setups without a decompression time are left out. Can result in a none if no mix meets both budgets.
+///
+/// Every setup is tried, not only the ones of the lower convex hull of time and size, since a setup above the hull can decompress much faster.
+/// Size, times and objective of a mix are linear in its fraction, so the best fraction of each pair is at an end of the interval meeting both budgets.
+/// Mixes are kept to two setups, as everywhere else: with both budgets binding, a mix of three setups can be slightly better.
+pub fn optimal_decoding_mix<'a>(metrics: &[&'a AlgorithmMetrics], objective: DecodingObjective, time_budget: Duration) -> Option<OptimalMix<'a>> {
+    let decodable: Vec<_> = metrics.iter().filter_map(|metric| Some((*metric, metric.decompression_time?))).collect();
+    let mut best: Option<Candidate> = None;
+    for (index, (a, a_decompression)) in decodable.iter().enumerate() {
+        for (b, b_decompression) in decodable.iter().skip(index) {
+            // The fraction of `a` lies in [low, high] to meet both budgets
+            let (mut low, mut high) = (0f64, 1f64);
+            let mut constrain = |at_zero: f64, at_one: f64, budget: f64| {
+                if at_one > at_zero {
+                    high = high.min((budget - at_zero) / (at_one - at_zero));
+                } else if at_one < at_zero {
+                    low = low.max((budget - at_zero) / (at_one - at_zero));
+                } else if at_zero > budget {
+                    high = -1.;
+                }
+            };
+            constrain(b.time_required.as_secs_f64(), a.time_required.as_secs_f64(), time_budget.as_secs_f64());
+            if let Some(decode_budget) = objective.decode_budget {
+                constrain(b_decompression.as_secs_f64(), a_decompression.as_secs_f64(), decode_budget.as_secs_f64());
+            }
+            if low > high {
+                continue;
+            }
+            let (a_value, b_value) = (objective.value(a, *a_decompression), objective.value(b, *b_decompression));
+            for fraction in [low, high] {
+                let value = a_value * fraction + b_value * (1. - fraction);
+                if best.is_none_or(|(_, _, best_value)| value < best_value) {
+                    best = Some(((*a, *b), fraction, value));
+                }
+            }
+        }
+    }
+    let ((a, b), fraction, value) = best?;
+    log::debug!("Optimal mix for {:?}: {} and {} with fraction {} (objective {:.0})", objective, a.algorithm.name(), b.algorithm.name(), fraction, value);
+    // The more expensive setup comes first, as in the mixes of the lower convex hull
+    let ((expensive, cheap), fraction) = if a.time_required >= b.time_required { ((a, b), fraction) } else { ((b, a), 1. - fraction) };
+    match fraction {
+        _ if std::ptr::eq(expensive, cheap) || fraction >= 1. => Some(OptimalMix::Single(expensive)),
+        _ if fraction <= 0. => Some(OptimalMix::Single(cheap)),
+        _ => Some(OptimalMix::Normal((expensive, cheap), fraction)),
+    }
+}
+
+/// Expected time to decompress the result of a mix, if every setup of the mix has a decompression time.
+pub fn expected_decompression_time(optimal_mix: &OptimalMix) -> Option<Duration> {
+    match optimal_mix {
+        OptimalMix::Single(metrics) => metrics.decompression_time,
+        OptimalMix::Normal((expensive, cheap), fraction) => Some(expensive.decompression_time?.mul_f64(*fraction) + cheap.decompression_time?.mul_f64(1. - fraction)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::algorithms::{AlgorithmMetrics, ByteSize};
+    use crate::algorithms::mock::metrics;
+    use crate::decoding::{expected_decompression_time, optimal_decoding_mix, DecodingObjective};
+    use crate::mixing_policy::OptimalMix;
+
+    /// Mock setups with their compressed size, compression time and decompression time (in ms).
+    fn setups(setups: &[(ByteSize, u64, Option<u64>)]) -> Vec<AlgorithmMetrics> {
+        let sizes_and_times: Vec<_> = setups.iter().map(|(size, millis, _)| (*size, *millis)).collect();
+        metrics(&sizes_and_times)
+            .into_iter()
+            .zip(setups)
+            .map(|(metrics, (_, _, decompression_millis))| metrics.with_decompression_time(decompression_millis.map(Duration::from_millis)))
+            .collect()
+    }
+
+    /// The setups of a mix, told apart by their compressed size.
+    fn sizes(optimal_mix: &OptimalMix) -> (ByteSize, Option<ByteSize>) {
+        match optimal_mix {
+            OptimalMix::Single(metrics) => (metrics.compressed_size, None),
+            OptimalMix::Normal((expensive, cheap), _) => (expensive.compressed_size, Some(cheap.compressed_size)),
+        }
+    }
+
+    #[test]
+    fn decompression_weight_moves_off_the_hull() {
+        let setups = setups(&[
+            // Fast
+            (1000, 100, Some(10)),
+            // Smaller than the fast one, but slow to decompress
+            (600, 200, Some(2000)),
+            // Above the hull of time and size, but much faster to decompress than the small one
+            (700, 300, Some(20)),
+            // Without a decompression time
+            (100, 100, None),
+        ]);
+        let metrics: Vec<_> = setups.iter().collect();
+
+        let size_only = optimal_decoding_mix(&metrics, DecodingObjective::default(), Duration::from_millis(400)).unwrap();
+        assert_eq!(sizes(&size_only), (600, None));
+
+        let weighted = DecodingObjective { weight: 1000., decode_budget: None };
+        let optimal_mix = optimal_decoding_mix(&metrics, weighted, Duration::from_millis(400)).unwrap();
+        assert_eq!(sizes(&optimal_mix), (700, None));
+
+        // Half the data fits in the time budget with the decodable setup
+        let optimal_mix = optimal_decoding_mix(&metrics, weighted, Duration::from_millis(200)).unwrap();
+        assert_eq!(sizes(&optimal_mix), (700, Some(1000)));
+        assert!((optimal_mix.expected_metrics().1.as_secs_f64() - 0.2).abs() < 1e-9);
+
+        assert!(optimal_decoding_mix(&metrics, weighted, Duration::from_millis(50)).is_none());
+    }
+
+    #[test]
+    fn decode_budget_limits_the_mix() {
+        let setups = setups(&[(1000, 100, Some(10)), (600, 200, Some(2010))]);
+        let metrics: Vec<_> = setups.iter().collect();
+        let objective = DecodingObjective { weight: 0., decode_budget: Some(Duration::from_millis(1010)) };
+        let optimal_mix = optimal_decoding_mix(&metrics, objective, Duration::from_secs(1)).unwrap();
+        match optimal_mix {
+            OptimalMix::Normal(_, fraction) => assert!((fraction - 0.5).abs() < 1e-9),
+            OptimalMix::Single(_) => panic!("A mix of both setups meets the decode budget"),
+        }
+        let decompression_time = expected_decompression_time(&optimal_mix).unwrap();
+        assert!((decompression_time.as_secs_f64() - 1.01).abs() < 1e-9);
+
+        let objective = DecodingObjective { weight: 0., decode_budget: Some(Duration::from_millis(5)) };
+        assert!(optimal_decoding_mix(&metrics, objective, Duration::from_secs(1)).is_none());
+    }
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use crate::algorithms::ByteSize;
+
+/// Errors that can happen while measuring, applying or decoding a mixed compression job.
+#[derive(Debug)]
+pub enum MixError {
+    /// Reading the workload or writing the results failed
+    Io(io::Error),
+    /// The input data (or a compressed segment) couldn't be decoded
+    Decode(String),
+    /// The color type of an image workload isn't supported by the algorithm
+    UnsupportedColorType(String),
+    /// The algorithm doesn't support the requested operation or setting
+    UnsupportedMode(String),
+    /// Not even the cheapest setup can compress the workload in the given time budget
+    BudgetInfeasible { budget: Duration, minimum: Duration },
+    /// Not even the most expensive setup can compress the workload within the given size budget, in bytes
+    SizeBudgetInfeasible { budget: ByteSize, minimum: ByteSize },
+    /// The limits of a workload (its cap and floor) can't be met by any of its mixes
+    LimitsInfeasible(String),
+    /// Not even the setup fastest to decompress can be decompressed in the given decode budget
+    DecodeBudgetInfeasible { budget: Duration, minimum: Duration },
+}
+
+impl fmt::Display for MixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MixError::Io(e) => write!(f, "I/O error: {}", e),
+            MixError::Decode(e) => write!(f, "Decoding failed: {}", e),
+            MixError::UnsupportedColorType(e) => write!(f, "Unsupported color type: {}", e),
+            MixError::UnsupportedMode(e) => write!(f, "Unsupported mode: {}", e),
+            MixError::BudgetInfeasible { budget, minimum } => write!(f, "No algorithm found that can compress data in the given time budget (Budget is {:?}, cheapest algorithm requires {:?}).", budget, minimum),
+            MixError::SizeBudgetInfeasible { budget, minimum } => write!(f, "No algorithm found that can compress data within the given size budget (Budget is {} bytes, smallest algorithm requires {} bytes).", budget, minimum),
+            MixError::LimitsInfeasible(e) => write!(f, "Limits can't be met: {}", e),
+            MixError::DecodeBudgetInfeasible { budget, minimum } => write!(f, "No mix found that can be decompressed in the given decode budget within the time budget (Budget is {:?}, fastest algorithm to decompress requires {:?}).", budget, minimum),
+        }
+    }
+}
+
+impl Error for MixError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MixError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MixError {
+    fn from(e: io::Error) -> Self {
+        MixError::Io(e)
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for MixError {
+    fn from(e: image::ImageError) -> Self {
+        use image::error::UnsupportedErrorKind;
+        match e {
+            image::ImageError::IoError(e) => MixError::Io(e),
+            image::ImageError::Unsupported(e) => match e.kind() {
+                UnsupportedErrorKind::Color(_) => MixError::UnsupportedColorType(e.to_string()),
+                _ => MixError::UnsupportedMode(e.to_string()),
+            },
+            e => MixError::Decode(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::fs::File;
+use std::time::{Duration, Instant};
+use tempfile::tempfile;
+use crate::algorithms::{AdaptiveEstimateMetadata, Algorithm, BlockInfo, ByteSize, ConfidenceInterval, EstimateMetadata};
+use crate::error::MixError;
+use crate::sampling::{FileSampleSet, SampleSet};
+use crate::timing::Timing;
+use crate::workload::Workload;
+
+/// Compressed size, time required and, for estimates, their confidence interval.
+pub type Profile = (ByteSize, Duration, Option<ConfidenceInterval>);
+
+/// Compressed data written by the profiling runs of a setup, with the bytes of the workload each one compresses, kept to time their decompression.
+pub type Outputs = Vec<(ByteSize, File)>;
+
+/// Sampled blocks of an estimate whose output is kept to time its decompression, at most.
+pub(crate) const DECOMPRESSION_BLOCKS: usize = 10;
+
+/// Two-sided 95% quantile of the standard normal distribution.
+const Z_95: f64 = 1.96;
+
+/// Blocks sampled by an adaptive estimate before its confidence intervals are trusted.
+const MIN_ADAPTIVE_BLOCKS: u64 = 5;
+
+/// Block sizes fitted by the power law model, as fractions of the sampled block size.
+const POWER_LAW_SCALES: [f64; 3] = [1., 0.5, 0.25];
+
+/// Times an empty block is measured to get the per-block overhead, keeping the fastest measurement.
+const OVERHEAD_RUNS: u32 = 3;
+
+/// How the metrics of the sampled blocks are extrapolated to the whole workload.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Extrapolation {
+    /// The workload costs as much as `1 / block_ratio` blocks
+    #[default]
+    Linear,
+    /// As linear, but the fixed cost of each block (headers, trailers and encoder setup, measured on an empty block) is only paid once by the workload.
+    /// More accurate for small blocks, or for setups with a costly setup like high xz2 levels.
+    OverheadCorrected,
+    /// Fits `a * length ^ b` on the sizes of blocks and prefixes of blocks of several lengths, for setups whose ratio improves with the amount of data (e.g. long windows).
+    /// Times are extrapolated linearly.
+    PowerLaw,
+}
+
+/// Running mean and variance of the metrics of the sampled blocks, with Welford's algorithm.
+#[derive(Debug, Default)]
+struct BlockSamples {
+    count: u64,
+    size_mean: f64,
+    size_m2: f64,
+    time_mean: f64,
+    time_m2: f64,
+}
+
+impl BlockSamples {
+    fn push(&mut self, compressed_size: f64, time_required: f64) {
+        self.count += 1;
+        let count = self.count as f64;
+        let delta = compressed_size - self.size_mean;
+        self.size_mean += delta / count;
+        self.size_m2 += delta * (compressed_size - self.size_mean);
+        let delta = time_required - self.time_mean;
+        self.time_mean += delta / count;
+        self.time_m2 += delta * (time_required - self.time_mean);
+    }
+
+    /// Half widths of the 95% confidence intervals of the mean size and time of a block, none with less than two blocks.
+    fn half_widths(&self) -> Option<(f64, f64)> {
+        if self.count < 2 {
+            return None;
+        }
+        let count = self.count as f64;
+        let half_width = |m2: f64| Z_95 * (m2 / (count - 1.) / count).sqrt();
+        Some((half_width(self.size_m2), half_width(self.time_m2)))
+    }
+
+    /// Whether both half widths, relative to their mean, are within the tolerance.
+    fn within(&self, tolerance: f64) -> bool {
+        match self.half_widths() {
+            Some((size, time)) => size <= tolerance * self.size_mean && time <= tolerance * self.time_mean,
+            None => false,
+        }
+    }
+}
+
+/// Estimates the metrics of a setup on a workload by compressing some blocks of it with [Algorithm::execute_on_tmp] (or [Algorithm::compress_in_memory], see [Timing]), so that every setup gets estimation for free.
+/// The blocks come from a [SampleSet] shared by every setup of the workload.
+#[derive(Debug)]
+pub struct Estimator<'a> {
+    workload: &'a mut Workload,
+    algorithm: &'a dyn Algorithm,
+    workload_size: u64,
+    block_ratio: f64,
+    extrapolation: Extrapolation,
+    sample_set: &'a SampleSet,
+    timing: Timing,
+    /// Index of the next block of the sample set
+    next_block: usize,
+    /// Metrics of the sampled blocks, followed by the ones of their prefixes for the power law
+    scales: Vec<BlockSamples>,
+    /// Compressed size and seconds required by an empty block, for the overhead corrected model
+    overhead: (f64, f64),
+    /// Outputs of the first sampled blocks, if they are kept
+    outputs: Option<&'a mut Outputs>,
+}
+
+impl<'a> Estimator<'a> {
+    /// Prepares the estimate of a setup on the blocks (of `block_ratio` of the workload) of the sample set, each block being timed as described by the timing.
+    /// The outputs of the first [DECOMPRESSION_BLOCKS] blocks are pushed to `outputs`, if any.
+    pub fn new(workload: &'a mut Workload, algorithm: &'a dyn Algorithm, block_ratio: f64, sample_set: &'a SampleSet, extrapolation: Extrapolation, timing: Timing, outputs: Option<&'a mut Outputs>) -> Result<Estimator<'a>, MixError> {
+        let workload_size = workload.data.metadata()?.len();
+        let scales = match extrapolation {
+            Extrapolation::PowerLaw => POWER_LAW_SCALES.len(),
+            _ => 1,
+        };
+        let overhead = match extrapolation {
+            Extrapolation::OverheadCorrected => Estimator::overhead(workload, algorithm, timing),
+            _ => (0., 0.),
+        };
+        Ok(Estimator {
+            workload,
+            algorithm,
+            workload_size,
+            block_ratio,
+            extrapolation,
+            sample_set,
+            timing,
+            next_block: 0,
+            scales: (0..scales).map(|_| BlockSamples::default()).collect(),
+            overhead,
+            outputs,
+        })
+    }
+
+    /// Measures the cost of compressing an empty block. Setups that can't compress an empty block (e.g. images) are considered overhead free.
+    fn overhead(workload: &mut Workload, algorithm: &dyn Algorithm, timing: Timing) -> (f64, f64) {
+        let mut overhead: Option<(f64, f64)> = None;
+        for _ in 0..OVERHEAD_RUNS {
+            match timing.measure(workload, algorithm, Some(BlockInfo { block_size: 0, block_end_index: 0 })) {
+                Ok((compressed_size, time)) => {
+                    let time = time.as_secs_f64();
+                    overhead = Some((compressed_size as f64, overhead.map_or(time, |(_, fastest)| time.min(fastest))));
+                }
+                Err(e) => {
+                    log::warn!("Couldn't measure the overhead of {} on an empty block, assuming none: {}", algorithm.name(), e);
+                    return (0., 0.);
+                }
+            }
+        }
+        let overhead = overhead.unwrap_or((0., 0.));
+        log::debug!("Overhead of {} per block: {} bytes, {} s", algorithm.name(), overhead.0, overhead.1);
+        overhead
+    }
+
+}
+
+/// An estimate refined one sample at a time, until enough samples are drawn or it is accurate enough.
+pub trait Sampled {
+    /// Draws and compresses one more sample.
+    fn sample(&mut self) -> Result<(), MixError>;
+
+    fn samples(&self) -> u64;
+
+    /// Whether the confidence intervals of the samples are within the tolerance, relative to their mean.
+    fn within(&self, tolerance: f64) -> bool;
+
+    /// Extrapolates the metrics of the whole workload from the samples.
+    fn extrapolate(&self) -> Profile;
+}
+
+impl Sampled for Estimator<'_> {
+    /// Compresses the next block of the sample set, and its prefixes for the power law.
+    fn sample(&mut self) -> Result<(), MixError> {
+        let block_info = self.sample_set.blocks[self.next_block];
+        self.next_block += 1;
+        let block_start = block_info.block_end_index - block_info.block_size;
+        for (index, (scale, samples)) in POWER_LAW_SCALES.iter().zip(self.scales.iter_mut()).enumerate() {
+            let block_size = (block_info.block_size as f64 * scale).round() as u64;
+            let (compressed_size, time, output) = self.timing.measure_with_output(self.workload, self.algorithm, Some(BlockInfo { block_size, block_end_index: block_start + block_size }))?;
+            samples.push(compressed_size as f64, time.as_secs_f64());
+            // Only whole blocks are decompressed
+            if let (0, Some(outputs), Some(output)) = (index, self.outputs.as_deref_mut(), output) {
+                if outputs.len() < DECOMPRESSION_BLOCKS {
+                    outputs.push((block_size, output));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn samples(&self) -> u64 {
+        self.scales[0].count
+    }
+
+    fn within(&self, tolerance: f64) -> bool {
+        self.scales[0].within(tolerance)
+    }
+
+    /// The confidence intervals keep the relative width of the ones of the blocks, except for the linear model which scales them as the metrics.
+    fn extrapolate(&self) -> Profile {
+        let blocks = &self.scales[0];
+        let (compressed_size, time_required) = match self.extrapolation {
+            Extrapolation::Linear => {
+                // Sizes are scaled by an integer number of blocks, as in the original implementation
+                (blocks.size_mean * (1. / self.block_ratio).round(), blocks.time_mean / self.block_ratio)
+            }
+            Extrapolation::OverheadCorrected => {
+                let (size_overhead, time_overhead) = self.overhead;
+                let size_overhead = size_overhead.min(blocks.size_mean);
+                let time_overhead = time_overhead.min(blocks.time_mean);
+                (
+                    size_overhead + (blocks.size_mean - size_overhead) / self.block_ratio,
+                    time_overhead + (blocks.time_mean - time_overhead) / self.block_ratio,
+                )
+            }
+            Extrapolation::PowerLaw => {
+                // Throughput doesn't depend on the amount of data, so only sizes follow the power law
+                let lengths: Vec<_> = POWER_LAW_SCALES.iter().map(|scale| (self.workload_size as f64 * self.block_ratio * scale).max(1.)).collect();
+                let sizes: Vec<_> = self.scales.iter().map(|samples| samples.size_mean).collect();
+                let compressed_size = power_law(&lengths, &sizes, self.workload_size as f64).unwrap_or_else(|| {
+                    log::warn!("Couldn't fit a power law on the blocks of {}, extrapolating linearly", self.algorithm.name());
+                    blocks.size_mean / self.block_ratio
+                });
+                (compressed_size, blocks.time_mean / self.block_ratio)
+            }
+        };
+        let confidence_interval = blocks.half_widths().map(|(size, time)| {
+            let (size_scale, time_scale) = match self.extrapolation {
+                Extrapolation::Linear => ((1. / self.block_ratio).round(), 1. / self.block_ratio),
+                _ => (relative_scale(compressed_size, blocks.size_mean), relative_scale(time_required, blocks.time_mean)),
+            };
+            ConfidenceInterval {
+                compressed_size: (size * size_scale) as ByteSize,
+                time_required: Duration::from_secs_f64(time * time_scale),
+                samples: blocks.count,
+            }
+        });
+        (compressed_size.max(0.) as ByteSize, Duration::from_secs_f64(time_required.max(0.)), confidence_interval)
+    }
+}
+
+/// Estimates the metrics of a setup on a folder workload, by compressing the file subsets of a [FileSampleSet] and scaling them by the total bytes of the folder.
+/// Each subset is a sample, so that files of very different sizes or content don't need to be split in blocks.
+#[derive(Debug)]
+pub struct FolderEstimator<'a> {
+    algorithm: &'a dyn Algorithm,
+    sample_set: &'a FileSampleSet,
+    timing: Timing,
+    /// Index of the next subset of the sample set
+    next_subset: usize,
+    samples: BlockSamples,
+}
+
+impl<'a> FolderEstimator<'a> {
+    pub fn new(algorithm: &'a dyn Algorithm, sample_set: &'a FileSampleSet, timing: Timing) -> FolderEstimator<'a> {
+        FolderEstimator { algorithm, sample_set, timing, next_subset: 0, samples: BlockSamples::default() }
+    }
+}
+
+impl Sampled for FolderEstimator<'_> {
+    fn sample(&mut self) -> Result<(), MixError> {
+        let mut subset_size = 0;
+        let mut compressed_size = 0;
+        let mut time = Duration::ZERO;
+        let subset = &self.sample_set.subsets[self.next_subset];
+        self.next_subset += 1;
+        for file in subset {
+            let (size, path) = &self.sample_set.files[*file];
+            let mut file_workload = Workload::new(format!("{}-{:?}", self.sample_set.folder_name, path.file_name().unwrap_or_default()), File::open(path)?, Duration::ZERO, Some(tempfile()?))?;
+            let (file_compressed_size, file_time) = self.timing.measure(&mut file_workload, self.algorithm, None)?;
+            compressed_size += file_compressed_size;
+            time += file_time;
+            subset_size += size;
+        }
+        let time = time.as_secs_f64();
+        let scale = if subset_size == 0 { 1. } else { self.sample_set.total_size as f64 / subset_size as f64 };
+        self.samples.push(compressed_size as f64 * scale, time * scale);
+        Ok(())
+    }
+
+    fn samples(&self) -> u64 {
+        self.samples.count
+    }
+
+    fn within(&self, tolerance: f64) -> bool {
+        self.samples.within(tolerance)
+    }
+
+    /// Samples are already scaled to the whole folder, so the estimate is their mean.
+    fn extrapolate(&self) -> Profile {
+        let confidence_interval = self.samples.half_widths().map(|(size, time)| ConfidenceInterval {
+            compressed_size: size as ByteSize,
+            time_required: Duration::from_secs_f64(time),
+            samples: self.samples.count,
+        });
+        (self.samples.size_mean as ByteSize, Duration::from_secs_f64(self.samples.time_mean), confidence_interval)
+    }
+}
+
+fn relative_scale(extrapolated: f64, mean: f64) -> f64 {
+    if mean > 0. { extrapolated / mean } else { 0. }
+}
+
+/// Fits `y = a * x ^ b` by least squares on the logarithms, and evaluates it at `target`.
+/// The exponent is capped to 1, since more data never makes compression worse than linear.
+/// Can result in a none if some value isn't positive, or all the lengths are the same.
+fn power_law(x: &[f64], y: &[f64], target: f64) -> Option<f64> {
+    if x.iter().chain(y).any(|value| *value <= 0.) {
+        return None;
+    }
+    let n = x.len() as f64;
+    let log_x: Vec<_> = x.iter().map(|value| value.ln()).collect();
+    let log_y: Vec<_> = y.iter().map(|value| value.ln()).collect();
+    let mean_x = log_x.iter().sum::<f64>() / n;
+    let mean_y = log_y.iter().sum::<f64>() / n;
+    let covariance: f64 = log_x.iter().zip(&log_y).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = log_x.iter().map(|x| (x - mean_x).powi(2)).sum();
+    if variance == 0. {
+        return None;
+    }
+    let exponent = (covariance / variance).min(1.);
+    log::debug!("Power law fit: exponent {}", exponent);
+    Some((mean_y + exponent * (target.ln() - mean_x)).exp())
+}
+
+/// Estimates the metrics of a setup on every block of the sample set, keeping the outputs of the first blocks in `outputs` if any.
+pub fn estimate(workload: &mut Workload, algorithm: &dyn Algorithm, metadata: EstimateMetadata, sample_set: &SampleSet, extrapolation: Extrapolation, timing: Timing, outputs: Option<&mut Outputs>) -> Result<Profile, MixError> {
+    log::debug!("Estimating metrics by using {} blocks of ratio {} ({:?} extrapolation)", sample_set.blocks.len(), metadata.block_ratio, extrapolation);
+    let estimator = Estimator::new(workload, algorithm, metadata.block_ratio, sample_set, extrapolation, timing, outputs)?;
+    sample_fixed(estimator, sample_set.blocks.len() as u64)
+}
+
+/// Samples the blocks of the sample set in order, until the relative half widths of both confidence intervals are within the tolerance, or a limit (blocks or time) is reached.
+/// The outputs of the first blocks are kept in `outputs`, if any.
+pub fn adaptive_estimate(workload: &mut Workload, algorithm: &dyn Algorithm, metadata: AdaptiveEstimateMetadata, sample_set: &SampleSet, extrapolation: Extrapolation, timing: Timing, outputs: Option<&mut Outputs>) -> Result<Profile, MixError> {
+    log::debug!("Estimating metrics by using blocks of ratio {} until the confidence intervals are within {} of the mean (at most {} blocks, time cap {:?}, {:?} extrapolation)", metadata.block_ratio, metadata.tolerance, sample_set.blocks.len(), metadata.time_cap, extrapolation);
+    let estimator = Estimator::new(workload, algorithm, metadata.block_ratio, sample_set, extrapolation, timing, outputs)?;
+    sample_adaptively(estimator, metadata.tolerance, sample_set.blocks.len() as u64, metadata.time_cap, &algorithm.name())
+}
+
+/// Estimates the metrics of a setup on a folder, on every file subset of the sample set.
+pub fn estimate_folder(algorithm: &dyn Algorithm, sample_set: &FileSampleSet, timing: Timing) -> Result<Profile, MixError> {
+    log::debug!("Estimating folder metrics by using {} file subsets", sample_set.subsets.len());
+    sample_fixed(FolderEstimator::new(algorithm, sample_set, timing), sample_set.subsets.len() as u64)
+}
+
+/// Samples the file subsets of the sample set in order, until the relative half widths of both confidence intervals are within the tolerance, or a limit (subsets or time) is reached.
+pub fn adaptive_estimate_folder(algorithm: &dyn Algorithm, metadata: AdaptiveEstimateMetadata, sample_set: &FileSampleSet, timing: Timing) -> Result<Profile, MixError> {
+    log::debug!("Estimating folder metrics until the confidence intervals are within {} of the mean (at most {} file subsets, time cap {:?})", metadata.tolerance, sample_set.subsets.len(), metadata.time_cap);
+    sample_adaptively(FolderEstimator::new(algorithm, sample_set, timing), metadata.tolerance, sample_set.subsets.len() as u64, metadata.time_cap, &algorithm.name())
+}
+
+fn sample_fixed(mut estimator: impl Sampled, samples: u64) -> Result<Profile, MixError> {
+    let current_unix = Instant::now();
+    for _ in 0..samples {
+        estimator.sample()?;
+    }
+    let profile = estimator.extrapolate();
+    log::debug!("Final metrics:\nCompressed size: {}\nTime required: {:?}\nConfidence interval: {:?}\nTime taken for estimation: {:?}", profile.0, profile.1, profile.2, current_unix.elapsed());
+    Ok(profile)
+}
+
+fn sample_adaptively(mut estimator: impl Sampled, tolerance: f64, max_samples: u64, time_cap: Option<Duration>, algorithm_name: &str) -> Result<Profile, MixError> {
+    let current_unix = Instant::now();
+    loop {
+        estimator.sample()?;
+        if estimator.samples() >= MIN_ADAPTIVE_BLOCKS && estimator.within(tolerance) {
+            log::debug!("Confidence intervals within the tolerance after {} samples", estimator.samples());
+            break;
+        }
+        if estimator.samples() >= max_samples {
+            log::warn!("Estimate of {} stopped at the maximum number of samples ({}) before reaching the tolerance", algorithm_name, max_samples);
+            break;
+        }
+        if time_cap.is_some_and(|time_cap| current_unix.elapsed() >= time_cap) {
+            log::warn!("Estimate of {} stopped at the time cap after {} samples, before reaching the tolerance", algorithm_name, estimator.samples());
+            break;
+        }
+    }
+    let profile = estimator.extrapolate();
+    log::debug!("Final metrics:\nCompressed size: {}\nTime required: {:?}\nConfidence interval: {:?}\nTime taken for estimation: {:?}", profile.0, profile.1, profile.2, current_unix.elapsed());
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, Write};
+    use std::time::Duration;
+    use tempfile::tempfile;
+    use crate::algorithms::EstimateMetadata;
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::estimation::{estimate, power_law, Extrapolation};
+    use crate::sampling::{SampleSet, Sampling};
+    use crate::timing::Timing;
+    use crate::workload::Workload;
+
+    #[test]
+    fn power_law_fit() {
+        // y = 3 * x ^ 0.5
+        let x = [100., 400., 1600.];
+        let y: Vec<_> = x.iter().map(|x: &f64| 3. * x.sqrt()).collect();
+        assert!((power_law(&x, &y, 10_000.).unwrap() - 300.).abs() < 1e-6);
+        assert!(power_law(&[1., 1.], &[2., 2.], 10.).is_none());
+        assert!(power_law(&[1., 2.], &[0., 2.], 10.).is_none());
+    }
+
+    #[test]
+    fn overhead_correction_shrinks_estimates() {
+        let mut data = tempfile().unwrap();
+        data.write_all("Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura".repeat(200).as_bytes()).unwrap();
+        data.rewind().unwrap();
+        let mut workload = Workload::new(String::from("estimation"), data, Duration::from_secs(1), Some(tempfile().unwrap())).unwrap();
+        let algorithm = Gzip::new(GzipCompressionLevel(6));
+        let metadata = EstimateMetadata { block_number: 5, block_ratio: 0.05 };
+
+        let sample_set = SampleSet::new(workload.data.metadata().unwrap().len(), metadata.block_ratio, metadata.block_number, Sampling::Stratified, 1);
+        let linear = estimate(&mut workload, &algorithm, metadata, &sample_set, Extrapolation::Linear, Timing::default(), None).unwrap();
+        let corrected = estimate(&mut workload, &algorithm, metadata, &sample_set, Extrapolation::OverheadCorrected, Timing::default(), None).unwrap();
+        // Gzip writes a header and a trailer of 18 bytes in every block, but only once in the workload
+        assert!(corrected.0 + 19 * 18 <= linear.0 + 20);
+        assert!(corrected.2.is_some());
+    }
+}
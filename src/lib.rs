@@ -1,43 +1,71 @@
+use std::path::Path;
 use std::time::Duration;
 use plotly::{Bar, Layout, Plot, Scatter};
-use plotly::common::Title;
+use plotly::common::{ErrorData, ErrorType, Mode, Title};
 use plotly::layout::{Axis, Legend};
-use crate::algorithms::{Algorithm, AlgorithmMetrics, ByteSize};
-use crate::mixing_policy::{MetricsWithBenefit, MixingPolicy, MixingPolicyMultipleWorkloads};
+use crate::algorithms::{AlgorithmMetrics, ByteSize};
+use crate::cost::{CostModel, CostReport, WorkloadCost};
+use crate::decoding::{expected_decompression_time, optimal_decoding_mix};
+use crate::error::MixError;
+use crate::mixing_policy::{MetricsWithBenefit, MixingPolicy, MixingPolicyMultipleWorkloads, OptimalMix};
+pub use crate::allocation::WorkloadLimits;
+pub use crate::controller::{ControlReport, Controller};
+pub use crate::deadline::{Deadline, DeadlineReport};
+pub use crate::decoding::DecodingObjective;
+pub use crate::mixing_policy::ExecutionMode;
 use crate::workload::{FolderWorkload, Workload};
 
 pub mod workload;
 pub mod algorithms;
+pub mod cache;
+pub mod calibration;
+pub mod container;
+pub mod cost;
+pub mod error;
+pub mod estimation;
+pub mod predictor;
+pub mod profiler;
+pub mod sampling;
+pub mod timing;
+mod controller;
+mod deadline;
+mod decoding;
 mod mixing_policy;
+mod allocation;
 mod convex_hull;
+mod scheduler;
 
 /// Find the optimal setups for a given document and time budget, and apply them. The result will be written in the `results` folder.
+/// The metrics of each setup are obtained beforehand, see [profiler::Profiler].
 ///
-pub fn process_single_document(mut workload: Workload, algorithms: Vec<Box<dyn Algorithm>>) {
-    log::debug!("Workload size: {:?}, time budget: {:?}", workload.data.metadata().unwrap().len(), workload.time_budget);
-    let algorithms: Vec<_> = algorithms
-        .into_iter()
-        .map(|alg| {
-            AlgorithmMetrics::new(alg)
-        })
-        .collect();
+/// The execution mode defines whether the halves of the mix run concurrently, and how the time budget is interpreted, see [ExecutionMode].
+///
+/// With a [Controller], a mix of two setups is applied in chunks and its partition is moved to hit the time budget if the setups run slower (or faster) than profiled.
+///
+/// With a [Deadline], the mix falls back to the cheapest setup (or stores the data) rather than overrunning the time budget.
+///
+/// Fails with [MixError::BudgetInfeasible] if not even the fastest setup fits in the time budget, unless a deadline is given: the fastest setup is then applied in deadline mode.
+pub fn process_single_document(mut workload: Workload, algorithms: Vec<AlgorithmMetrics>, execution_mode: ExecutionMode, controller: Option<Controller>, deadline: Option<Deadline>) -> Result<(), MixError> {
+    log::debug!("Workload size: {:?}, time budget: {:?}, execution mode: {:?}", workload.data.metadata()?.len(), workload.time_budget, execution_mode);
     let mixing_policy = MixingPolicy::new(algorithms.iter().collect());
     draw_workload_plots(&mixing_policy.lower_convex_hull, &workload.name);
+    draw_decompression_plot(&algorithms, &workload.name);
 
-    let optimal_mix = mixing_policy.optimal_mix(workload.time_budget);
-    match optimal_mix {
-        Some(optimal_mix) => {
-            MixingPolicy::apply_optimal_mix(&optimal_mix, &mut workload);
+    let optimal_mix = mixing_policy.optimal_mix_with_mode(workload.time_budget, execution_mode);
+    let cheapest = mixing_policy.lower_convex_hull.first().map(|metric| metric.0);
+    let deadline = deadline.zip(cheapest);
+    match (optimal_mix, deadline.filter(|_| execution_mode.concurrent_halves() == 1)) {
+        (Some(optimal_mix), _) => {
+            MixingPolicy::apply_optimal_mix(&optimal_mix, &mut workload, execution_mode, controller, deadline)?;
         }
-        None => {
-            let minimum_time_budget = mixing_policy
-                .lower_convex_hull
-                .iter()
-                .map(|el| el.0)
-                .min();
-            match minimum_time_budget {
-                Some(min) => {
-                    log::info!("No algorithm found that can compress data in the given time budget (Budget is {:?}, cheapest algorithm requires {:?}).", workload.time_budget, min.time_required)
+        (None, Some((_, cheapest))) => {
+            log::warn!("Not even the fastest setup fits in the time budget, applying it in deadline mode");
+            MixingPolicy::apply_optimal_mix(&OptimalMix::Single(cheapest), &mut workload, execution_mode, None, deadline)?;
+        }
+        (None, None) => {
+            match mixing_policy.minimum_budget(execution_mode) {
+                Some(minimum) => {
+                    return Err(MixError::BudgetInfeasible { budget: workload.time_budget, minimum });
                 }
                 None => {
                     log::info!("The lower convex hull is empty. Is this an error?");
@@ -45,90 +73,242 @@ pub fn process_single_document(mut workload: Workload, algorithms: Vec<Box<dyn A
             }
         }
     }
+    Ok(())
 }
 
-pub fn process_multiple_documents(mut workloads: Vec<Workload>, workload_algorithms: Vec<Vec<Box<dyn Algorithm>>>, total_time_budget: Duration) {
-    let mut algorithms = Vec::new();
-    workload_algorithms
-        .into_iter()
-        .for_each(|algorithm| {
-            let compression_configurations: Vec<_> = algorithm
-                .into_iter()
-                .map(|alg| {
-                    AlgorithmMetrics::new(alg)
-                })
-                .collect();
-            algorithms.push(compression_configurations);
-        });
+/// Find the optimal setups for each document so that all of them are compressed within the total time budget, and apply them.
+///
+/// With more than one thread (see [ExecutionMode]), the budget is a wall-clock deadline: the setups are chosen and packed on the workers so that each one ends before the deadline, and the workers run in parallel.
+/// Otherwise, the documents are compressed one after the other, as in the paper. With a [Deadline], they fall back to the cheapest setups (or are stored) rather than overrunning the total budget,
+/// and the cheapest setups are applied in deadline mode even if they don't fit in it.
+///
+/// When compressed one after the other, the limits of each document (by index, see [WorkloadLimits]) are enforced alongside the total budget. Fails with [MixError::LimitsInfeasible]
+/// if the limits of a document can't be met by any of its mixes, and with [MixError::BudgetInfeasible] if the minimum time of every document within its limits exceeds the total budget.
+pub fn process_multiple_documents(mut workloads: Vec<Workload>, algorithms: Vec<Vec<AlgorithmMetrics>>, total_time_budget: Duration, limits: &[WorkloadLimits], execution_mode: ExecutionMode, deadline: Option<Deadline>) -> Result<(), MixError> {
 
     // TODO sort out the borrow issue with &AlgorithmMetrics to remove this hack
     let alg2 = algorithms.iter().map(|el| el.iter().collect()).collect();
     let mixing_policy = MixingPolicyMultipleWorkloads::new(alg2);
 
-    for (metrics, workload) in mixing_policy
-        .lower_convex_hull_per_workload
-        .iter()
-        .zip(&workloads) {
-        let mut lch_info = format!("Metrics for workload '{}'\n(time in s. ; compressed size)", workload.name);
-        for metric in metrics {
-            lch_info.push_str(&*format!("\n{} ; {} (benefit: {})", metric.0.time_required.as_secs_f32(), metric.0.compressed_size, metric.1));
+    report_multiple_workloads(&algorithms, &mixing_policy, &workloads);
+
+    // Apply the actual mix and write the resulting compressed data in the results folder
+    let workers = execution_mode.threads();
+    let optimal_mixes = if workers > 1 {
+        if limits.iter().any(|limits| *limits != WorkloadLimits::default()) {
+            log::warn!("The documents are packed on {} workers, the limits of each document are ignored", workers);
         }
-        log::info!("{}", lch_info);
+        mixing_policy.mix_with_deadline(workers, total_time_budget)
+    } else {
+        mixing_policy.mix_with_total_time_budget(total_time_budget, limits)
+    };
+    let cheapest: Option<Vec<_>> = mixing_policy.lower_convex_hull_per_workload.iter().map(|lch| lch.first().map(|metric| metric.0)).collect();
+    let deadline = deadline.zip(cheapest);
+    match optimal_mixes {
+        Some(optimal_mixes) => {
+            MixingPolicyMultipleWorkloads::apply_optimal_combination(&optimal_mixes, &mut workloads, total_time_budget, execution_mode, deadline)?;
+        }
+        None if workers <= 1 && deadline.is_some() => {
+            log::warn!("Not even the fastest setups fit in the total time budget, applying them in deadline mode");
+            let optimal_mixes = deadline.iter().flat_map(|(_, cheapest)| cheapest).map(|metrics| OptimalMix::Single(metrics)).collect();
+            MixingPolicyMultipleWorkloads::apply_optimal_combination(&optimal_mixes, &mut workloads, total_time_budget, execution_mode, deadline)?;
+        }
+        None if workers > 1 => {
+            return Err(MixError::BudgetInfeasible { budget: total_time_budget, minimum: mixing_policy.minimum_makespan(workers) });
+        }
+        None if mixing_policy.lower_convex_hull_per_workload.iter().any(|lch| lch.is_empty()) => {
+            log::warn!("The lower convex hull is empty. Is this an error?");
+        }
+        None => {
+            let minimum_times = allocation::minimum_times(&mixing_policy.lower_convex_hull_per_workload, limits);
+            if let Some((_, workload)) = minimum_times.iter().zip(&workloads).find(|(minimum, _)| minimum.is_none()) {
+                return Err(MixError::LimitsInfeasible(format!("no mix of '{}' meets its limits", workload.name)));
+            }
+            let minimum = minimum_times.into_iter().flatten().sum();
+            return Err(MixError::BudgetInfeasible { budget: total_time_budget, minimum });
+        }
+    }
+    Ok(())
+}
 
-        draw_workload_plots(metrics, &workload.name);
+/// Find the setups with the smallest time required whose expected compressed size is within the size budget, and apply them, the dual of [process_single_document].
+///
+/// Fails with [MixError::SizeBudgetInfeasible] if not even the most expensive setup compresses the document within the size budget.
+pub fn process_single_document_with_size_budget(mut workload: Workload, algorithms: Vec<AlgorithmMetrics>, size_budget: ByteSize, execution_mode: ExecutionMode) -> Result<(), MixError> {
+    log::debug!("Workload size: {:?}, size budget: {} bytes, execution mode: {:?}", workload.data.metadata()?.len(), size_budget, execution_mode);
+    let mixing_policy = MixingPolicy::new(algorithms.iter().collect());
+    draw_workload_plots(&mixing_policy.lower_convex_hull, &workload.name);
+
+    match mixing_policy.optimal_mix_for_size(size_budget) {
+        Some(optimal_mix) => {
+            // The time the mix is expected to take is the time budget it is applied with
+            workload.time_budget = optimal_mix.expected_metrics().1;
+            MixingPolicy::apply_optimal_mix(&optimal_mix, &mut workload, execution_mode, None, None)?;
+        }
+        None => {
+            match mixing_policy.lower_convex_hull.last() {
+                Some(smallest) => {
+                    return Err(MixError::SizeBudgetInfeasible { budget: size_budget, minimum: smallest.0.compressed_size });
+                }
+                None => {
+                    log::info!("The lower convex hull is empty. Is this an error?");
+                }
+            }
+        }
     }
+    Ok(())
+}
 
+/// Find the setups for each document with the smallest total time required whose expected total compressed size is within the size budget, and apply them, the dual of [process_multiple_documents].
+/// The limits of each document are enforced as well, and the mixes are packed on the workers with more than one thread.
+///
+/// Fails with [MixError::LimitsInfeasible] if the limits of a document can't be met by any of its mixes,
+/// and with [MixError::SizeBudgetInfeasible] if not even the most expensive setups within the limits compress the documents within the size budget.
+pub fn process_multiple_documents_with_size_budget(mut workloads: Vec<Workload>, algorithms: Vec<Vec<AlgorithmMetrics>>, total_size_budget: ByteSize, limits: &[WorkloadLimits], execution_mode: ExecutionMode) -> Result<(), MixError> {
+    let alg2 = algorithms.iter().map(|el| el.iter().collect()).collect();
+    let mixing_policy = MixingPolicyMultipleWorkloads::new(alg2);
+    report_multiple_workloads(&algorithms, &mixing_policy, &workloads);
 
-    draw_multiple_workloads_plots(&algorithms, &mixing_policy, &workloads);
-    let mut result_info = "Resulting lower convex hull for the multiple document mix:".to_string();
-    for metrics in &mixing_policy.lower_convex_hull {
-        let display: Vec<_> = metrics.0.iter().map(|metric| (metric.0.time_required.as_secs_f64(), metric.0.compressed_size)).collect();
-        result_info.push_str(&*format!("\n{:?} (benefit: {})", display, metrics.1));
+    match mixing_policy.mix_with_total_size_budget(total_size_budget, limits) {
+        Some((total_time_budget, optimal_mixes)) => {
+            MixingPolicyMultipleWorkloads::apply_optimal_combination(&optimal_mixes, &mut workloads, total_time_budget, execution_mode, None)?;
+        }
+        None if mixing_policy.lower_convex_hull_per_workload.iter().any(|lch| lch.is_empty()) => {
+            log::warn!("The lower convex hull is empty. Is this an error?");
+        }
+        None => {
+            if let Some((_, workload)) = allocation::minimum_times(&mixing_policy.lower_convex_hull_per_workload, limits).iter().zip(&workloads).find(|(minimum, _)| minimum.is_none()) {
+                return Err(MixError::LimitsInfeasible(format!("no mix of '{}' meets its limits", workload.name)));
+            }
+            // The smallest total size, with the whole time of every cap
+            let minimum = allocation::optimal_allocation(&mixing_policy.lower_convex_hull_per_workload, Duration::MAX, limits)
+                .map_or(0, |allocation| allocation.certificate.total_size.ceil() as ByteSize);
+            return Err(MixError::SizeBudgetInfeasible { budget: total_size_budget, minimum });
+        }
     }
-    log::info!("{}", result_info);
+    Ok(())
+}
 
-    // Apply the actual mix and write the resulting compressed data in the results folder
-    let optimal_mixes = mixing_policy.mix_with_total_time_budget(total_time_budget);
-    match optimal_mixes {
-        Some(optimal_mixes) => {
-            MixingPolicyMultipleWorkloads::apply_optimal_combination(&optimal_mixes, &mut workloads, total_time_budget);
+/// Find the setups with the smallest total cost for a given document, within the time budget if any, and apply them. See [CostModel].
+/// The expected cost of the mix is logged and saved in `results/cost-report.csv`.
+///
+/// Fails with [MixError::BudgetInfeasible] if not even the fastest setup fits in the time budget.
+pub fn process_single_document_with_cost_model(mut workload: Workload, algorithms: Vec<AlgorithmMetrics>, cost_model: CostModel, time_budget: Option<Duration>, execution_mode: ExecutionMode) -> Result<(), MixError> {
+    log::debug!("Workload size: {:?}, cost model: {:?}, time budget: {:?}, execution mode: {:?}", workload.data.metadata()?.len(), cost_model, time_budget, execution_mode);
+    let mixing_policy = MixingPolicy::new(algorithms.iter().collect());
+    draw_workload_plots(&mixing_policy.lower_convex_hull, &workload.name);
+
+    match mixing_policy.optimal_mix_for_cost(&cost_model, workload.data.metadata()?.len(), time_budget) {
+        Some(optimal_mix) => {
+            workload.time_budget = optimal_mix.expected_metrics().1;
+            report_cost(&cost_model, std::slice::from_ref(&optimal_mix), std::slice::from_ref(&workload))?;
+            MixingPolicy::apply_optimal_mix(&optimal_mix, &mut workload, execution_mode, None, None)?;
         }
         None => {
-            let minimum_time_budget = mixing_policy
-                .lower_convex_hull
-                .iter()
-                .map(|metric| {
-                    // we're analyzing a combination
-                    metric.0.iter().fold(0., |acc, setup| acc + setup.0.time_required.as_secs_f32())
-                })
-                .min_by(|a, b| a.total_cmp(b));
-            match minimum_time_budget {
-                Some(min) => {
-                    log::info!("No algorithm found that can compress data in the given time budget (Budget is {:?}, cheapest algorithm requires {:?}).", total_time_budget, Duration::from_secs_f32(min))
+            match (mixing_policy.lower_convex_hull.first(), time_budget) {
+                (Some(cheapest), Some(budget)) => {
+                    return Err(MixError::BudgetInfeasible { budget, minimum: cheapest.0.time_required });
                 }
-                None => {
-                    log::warn!("The lower convex hull is empty. Is this an error?");
+                _ => {
+                    log::info!("The lower convex hull is empty. Is this an error?");
                 }
             }
         }
     }
+    Ok(())
 }
 
-pub fn process_folder(mut workload: FolderWorkload, algorithms: Vec<Box<dyn Algorithm>>) {
-    log::debug!("Workload size: {:?}, time budget: {:?}", workload.data_files_size(), workload.time_budget);
-    let algorithms: Vec<_> = algorithms
-        .into_iter()
-        .map(|alg| {
-            AlgorithmMetrics::new(alg)
-        })
-        .collect();
+/// Find the setups for each document with the smallest total cost, within the total time budget if any, and apply them. See [CostModel].
+/// The limits of each document are enforced as well, and the mixes are packed on the workers with more than one thread. The expected cost of each document is logged and saved in `results/cost-report.csv`.
+///
+/// Fails with [MixError::LimitsInfeasible] if the limits of a document can't be met by any of its mixes, and with [MixError::BudgetInfeasible] if the minimum time of every document within its limits exceeds the total budget.
+/// Fails with [MixError::UnsupportedMode] if the documents are read and their setups have a decompression time, as the allocation doesn't account for the decompression cost of each setup.
+pub fn process_multiple_documents_with_cost_model(mut workloads: Vec<Workload>, algorithms: Vec<Vec<AlgorithmMetrics>>, cost_model: CostModel, total_time_budget: Option<Duration>, limits: &[WorkloadLimits], execution_mode: ExecutionMode) -> Result<(), MixError> {
+    if cost_model.reads > 0. && algorithms.iter().flatten().any(|metrics| metrics.decompression_time.is_some()) {
+        return Err(MixError::UnsupportedMode(String::from("The cost of multiple documents can't take the decompression time of their setups into account, leave it out or pass --reads 0")));
+    }
+    let alg2 = algorithms.iter().map(|el| el.iter().collect()).collect();
+    let mixing_policy = MixingPolicyMultipleWorkloads::new(alg2);
+    report_multiple_workloads(&algorithms, &mixing_policy, &workloads);
+
+    match mixing_policy.mix_for_cost(&cost_model, total_time_budget, limits) {
+        Some(optimal_mixes) => {
+            report_cost(&cost_model, &optimal_mixes, &workloads)?;
+            let total_time_budget = optimal_mixes.iter().map(|optimal_mix| optimal_mix.expected_metrics().1).sum();
+            MixingPolicyMultipleWorkloads::apply_optimal_combination(&optimal_mixes, &mut workloads, total_time_budget, execution_mode, None)?;
+        }
+        None if mixing_policy.lower_convex_hull_per_workload.iter().any(|lch| lch.is_empty()) => {
+            log::warn!("The lower convex hull is empty. Is this an error?");
+        }
+        None => {
+            let minimum_times = allocation::minimum_times(&mixing_policy.lower_convex_hull_per_workload, limits);
+            if let Some((_, workload)) = minimum_times.iter().zip(&workloads).find(|(minimum, _)| minimum.is_none()) {
+                return Err(MixError::LimitsInfeasible(format!("no mix of '{}' meets its limits", workload.name)));
+            }
+            let minimum = minimum_times.into_iter().flatten().sum();
+            return Err(MixError::BudgetInfeasible { budget: total_time_budget.unwrap_or(Duration::MAX), minimum });
+        }
+    }
+    Ok(())
+}
+
+/// Find the mix with the smallest decoding objective (see [DecodingObjective]) within the time budget of the workload, and apply it.
+/// Only the setups with a decompression time (see [AlgorithmMetrics::decompression_time]) are mixed, whether or not they are on the lower convex hull of time and size.
+///
+/// Fails with [MixError::BudgetInfeasible] if not even the fastest setup fits in the time budget, and with [MixError::DecodeBudgetInfeasible] if no mix within it can be decompressed in the decode budget.
+pub fn process_single_document_with_decoding(mut workload: Workload, algorithms: Vec<AlgorithmMetrics>, objective: DecodingObjective, execution_mode: ExecutionMode) -> Result<(), MixError> {
+    log::debug!("Workload size: {:?}, time budget: {:?}, decoding objective: {:?}", workload.data.metadata()?.len(), workload.time_budget, objective);
+    let mixing_policy = MixingPolicy::new(algorithms.iter().collect());
+    draw_workload_plots(&mixing_policy.lower_convex_hull, &workload.name);
+    draw_decompression_plot(&algorithms, &workload.name);
+
+    let decodable: Vec<_> = algorithms.iter().filter(|metric| metric.decompression_time.is_some()).collect();
+    if decodable.is_empty() {
+        return Err(MixError::UnsupportedMode(String::from("No setup has a decompression time, measure it or supply it in the metrics table")));
+    }
+    if execution_mode != ExecutionMode::Sequential {
+        log::warn!("The decoding objective assumes the halves of the mix run one after the other, the time budget is their total time");
+    }
+    match optimal_decoding_mix(&decodable, objective, workload.time_budget) {
+        Some(optimal_mix) => {
+            let (compressed_size, time_required, _) = optimal_mix.expected_metrics();
+            log::info!("Optimal mix for {:?}: {:?}\nExpected compressed size: {} bytes, time required: {:?}, decompression time: {:?}",
+                objective, optimal_mix, compressed_size, time_required, expected_decompression_time(&optimal_mix));
+            MixingPolicy::apply_optimal_mix(&optimal_mix, &mut workload, execution_mode, None, None)?;
+        }
+        None => {
+            let minimum = decodable.iter().map(|metric| metric.time_required).min().unwrap_or_default();
+            if minimum > workload.time_budget {
+                return Err(MixError::BudgetInfeasible { budget: workload.time_budget, minimum });
+            }
+            let minimum = decodable.iter().filter_map(|metric| metric.decompression_time).min().unwrap_or_default();
+            return Err(MixError::DecodeBudgetInfeasible { budget: objective.decode_budget.unwrap_or_default(), minimum });
+        }
+    }
+    Ok(())
+}
+
+/// Logs the expected cost of the mix of each workload, and saves it in `results/cost-report.csv`.
+fn report_cost(cost_model: &CostModel, optimal_mixes: &[OptimalMix], workloads: &[Workload]) -> Result<(), MixError> {
+    let mut report = CostReport::default();
+    for (optimal_mix, workload) in optimal_mixes.iter().zip(workloads) {
+        let (compressed_size, time_required, _) = optimal_mix.expected_metrics();
+        let breakdown = cost_model.mix_cost(optimal_mix, workload.data.metadata()?.len());
+        report.workloads.push(WorkloadCost { name: workload.name.clone(), compressed_size, time_required, breakdown });
+    }
+    log::info!("{}", report);
+    report.save(Path::new("results/cost-report.csv"))
+}
+
+pub fn process_folder(mut workload: FolderWorkload, algorithms: Vec<AlgorithmMetrics>) -> Result<(), MixError> {
+    log::debug!("Workload size: {:?}, time budget: {:?}", workload.data_files_size()?, workload.time_budget);
     let mixing_policy = MixingPolicy::new(algorithms.iter().collect());
     draw_workload_plots(&mixing_policy.lower_convex_hull, &workload.name);
 
     let optimal_mix = mixing_policy.optimal_mix(workload.time_budget);
     match optimal_mix {
         Some(optimal_mix) => {
-            MixingPolicy::apply_optimal_mix_folder(&optimal_mix, &mut workload);
+            MixingPolicy::apply_optimal_mix_folder(&optimal_mix, &mut workload)?;
         }
         None => {
             let minimum_time_budget = mixing_policy
@@ -138,7 +318,7 @@ pub fn process_folder(mut workload: FolderWorkload, algorithms: Vec<Box<dyn Algo
                 .min();
             match minimum_time_budget {
                 Some(min) => {
-                    log::info!("No algorithm found that can compress data in the given time budget (Budget is {:?}, cheapest algorithm requires {:?}).", workload.time_budget, min.time_required)
+                    return Err(MixError::BudgetInfeasible { budget: workload.time_budget, minimum: min.time_required });
                 }
                 None => {
                     log::info!("The lower convex hull is empty. Is this an error?");
@@ -146,11 +326,39 @@ pub fn process_folder(mut workload: FolderWorkload, algorithms: Vec<Box<dyn Algo
             }
         }
     }
+    Ok(())
+}
+
+/// Logs the lower convex hull of each workload and of their combinations, and draws their plots.
+fn report_multiple_workloads(algorithms: &[Vec<AlgorithmMetrics>], mixing_policy: &MixingPolicyMultipleWorkloads, workloads: &[Workload]) {
+    for (metrics, workload) in mixing_policy
+        .lower_convex_hull_per_workload
+        .iter()
+        .zip(workloads) {
+        let mut lch_info = format!("Metrics for workload '{}'\n(time in s. ; compressed size)", workload.name);
+        for metric in metrics {
+            lch_info.push_str(&*format!("\n{} ; {} (benefit: {})", metric.0.time_required.as_secs_f32(), metric.0.compressed_size, metric.1));
+        }
+        log::info!("{}", lch_info);
+
+        draw_workload_plots(metrics, &workload.name);
+    }
+    for (metrics, workload) in algorithms.iter().zip(workloads) {
+        draw_decompression_plot(metrics, &workload.name);
+    }
+
+    draw_multiple_workloads_plots(algorithms, mixing_policy, workloads);
+    let mut result_info = "Resulting lower convex hull for the multiple document mix:".to_string();
+    for metrics in &mixing_policy.lower_convex_hull {
+        let display: Vec<_> = metrics.0.iter().map(|metric| (metric.0.time_required.as_secs_f64(), metric.0.compressed_size)).collect();
+        result_info.push_str(&*format!("\n{:?} (benefit: {})", display, metrics.1));
+    }
+    log::info!("{}", result_info);
 }
 
 /// Draws convex hull and benefit plots for a MixingPolicyMultipleWorkloads struct,
 /// with a comparison with a naive approach using the same compression level for each algorithm in each combination.
-fn draw_multiple_workloads_plots(algorithms: &Vec<Vec<AlgorithmMetrics>>, mixing_policy: &MixingPolicyMultipleWorkloads, workload_filenames: &Vec<Workload>) {
+fn draw_multiple_workloads_plots(algorithms: &[Vec<AlgorithmMetrics>], mixing_policy: &MixingPolicyMultipleWorkloads, workload_filenames: &[Workload]) {
     let workload_filenames = workload_filenames.iter().map(|el| el.name.clone()).collect::<Vec<_>>().join(",");
     // Convex hull plot for the whole multiple document mixing process
     let mut plot = Plot::new();
@@ -274,6 +482,29 @@ fn draw_multiple_workloads_plots(algorithms: &Vec<Vec<AlgorithmMetrics>>, mixing
     plot.write_html("results/result-benefit.html");
 }
 
+/// Draws the compressed size of each setup against its decompression time, if any setup has one.
+fn draw_decompression_plot(algorithms: &[AlgorithmMetrics], workload_name: &str) {
+    let metrics: Vec<_> = algorithms.iter().filter_map(|el| Some((el, el.decompression_time?))).collect();
+    if metrics.is_empty() {
+        return;
+    }
+    let mut plot = Plot::new();
+    plot.set_layout(Layout::new()
+        .title(Title::new(&format!("Decompression time of workload \"{}\"", workload_name)))
+        .x_axis(Axis::new().title(Title::new("Decompression time (sec)")))
+        .y_axis(Axis::new().title(Title::new("Size (bytes)")))
+        .legend(Legend::new()));
+    let trace = Scatter::new(
+        metrics.iter().map(|(_, decompression_time)| decompression_time.as_secs_f32()).collect(),
+        metrics.iter().map(|(el, _)| el.compressed_size).collect())
+        .mode(Mode::Markers)
+        .name(format!("Workload {}", workload_name))
+        .text_array(metrics.iter().map(|(el, _)| el.algorithm.name()).collect());
+    plot.add_trace(trace);
+
+    plot.write_html(format!("results/decompression-{}.html", workload_name));
+}
+
 /// Draws two plots, one showing the convex hull associated to the provided metrics and one showing the benefits.
 fn draw_workload_plots(metrics: &Vec<MetricsWithBenefit>, workload_name: &str) {
     // Convex hull plot for a specific workload
@@ -290,6 +521,14 @@ fn draw_workload_plots(metrics: &Vec<MetricsWithBenefit>, workload_name: &str) {
         .text_template(".3s")
         .name(format!("Workload {}", workload_name))
         .text_array(metrics.iter().map(|el| el.0.algorithm.name()).collect());
+    // Estimated metrics are drawn with their confidence intervals
+    let trace = if metrics.iter().any(|el| el.0.confidence_interval.is_some()) {
+        trace
+            .error_x(ErrorData::new(ErrorType::Data).array(metrics.iter().map(|el| el.0.confidence_interval.map_or(0., |ci| ci.time_required.as_secs_f64())).collect()))
+            .error_y(ErrorData::new(ErrorType::Data).array(metrics.iter().map(|el| el.0.confidence_interval.map_or(0., |ci| ci.compressed_size as f64)).collect()))
+    } else {
+        trace
+    };
     plot.add_trace(trace);
 
     plot.write_html(format!("results/convex-hull-{}.html", workload_name));
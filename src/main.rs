@@ -1,25 +1,43 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::fs::{File, metadata};
-use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
-use clap::{CommandFactory, Parser};
+use clap::{CommandFactory, Parser, ValueEnum};
 use clap::error::ErrorKind;
-use mix_compression::{algorithms, process_folder, process_multiple_documents, process_single_document};
-use mix_compression::algorithms::{Algorithm, EstimateMetadata};
+use mix_compression::{algorithms, Controller, Deadline, ExecutionMode, process_folder, process_multiple_documents, process_multiple_documents_with_cost_model, process_multiple_documents_with_size_budget, process_single_document, process_single_document_with_cost_model, process_single_document_with_decoding, process_single_document_with_size_budget, DecodingObjective, WorkloadLimits};
+use mix_compression::algorithms::{AdaptiveEstimateMetadata, Algorithm, AlgorithmMetrics, EstimateMetadata};
 use mix_compression::algorithms::bzip2::{Bzip2, Bzip2CompressionLevel};
 use mix_compression::algorithms::gzip::{Gzip, GzipCompressionLevel};
 use mix_compression::algorithms::xz2::{Xz2, Xz2CompressionLevel};
+use mix_compression::algorithms::zstd::{Zstd, ZstdCompressionLevel};
+use mix_compression::algorithms::lz4::{Lz4, Lz4CompressionLevel};
+use mix_compression::algorithms::brotli::{Brotli, BrotliCompressionLevel};
+use mix_compression::cache::{DEFAULT_CACHE_FOLDER, machine_fingerprint, MetricsCache};
+use mix_compression::calibration::{DEFAULT_CALIBRATION_FOLDER, MachineProfile};
+use mix_compression::container;
+use mix_compression::container::{Container, SegmentCheck};
+use mix_compression::cost::CostModel;
+use mix_compression::error::MixError;
+use mix_compression::estimation::Extrapolation;
+use mix_compression::predictor::{Features, Observation, PredictorModel};
+use mix_compression::profiler::{MetricsSource, Profiler};
+use mix_compression::sampling::Sampling;
+use mix_compression::timing::{Timing, TimingMode};
 use mix_compression::workload::{FolderWorkload, Workload};
 #[cfg(feature = "image")]
 use {
     mix_compression::algorithms::png::{PNG, PNGCompressionType, PNGFilterType},
-    image::codecs::png::{PngDecoder, PngEncoder},
-    image::{ImageDecoder, ImageEncoder},
+    image::codecs::png::PngEncoder,
+    image::ImageEncoder,
     crate::Alg::FELICS
 };
 
+/// Maximum number of blocks sampled by adaptive estimates, if --estimate-block-number isn't passed.
+const DEFAULT_MAX_ESTIMATE_BLOCKS: u64 = 100;
+
 /// Parse a single key-value pair
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error + Send + Sync + 'static>>
     where
@@ -43,6 +61,11 @@ fn parse_ratio(s: &str) -> Result<f64, Box<dyn Error + Send + Sync + 'static>> {
     Ok(float)
 }
 
+/// Parse a machine fingerprint, as the hexadecimal file names of the `results/.calibration` folder
+fn parse_fingerprint(s: &str) -> Result<u64, Box<dyn Error + Send + Sync + 'static>> {
+    Ok(u64::from_str_radix(s, 16).map_err(|_| format!("invalid machine fingerprint: {s} (expected a hexadecimal number)"))?)
+}
+
 /// A general optimization framework to allocate computing resources to the compression of massive and heterogeneous data sets.
 ///
 /// Specify which documents to compress (from the `data` folder) and the time budget to allocate for the compression.
@@ -50,22 +73,77 @@ fn parse_ratio(s: &str) -> Result<f64, Box<dyn Error + Send + Sync + 'static>> {
 ///
 /// If a single document is passed, it will be compressed by taking the optimal mix of all levels of the provided algorithm to satisfy the time budget constraint.
 /// If multiple documents are passed, the time budget constraint will be applied to the whole compression task. In this case, one document will possibly benefit of a level mixing strategy, while the others will be compressed with a specific algorithm level.
-/// The mixing strategy works by mixing compression settings (the level) for a specific algorithm.
+/// The mixing strategy works by mixing compression settings (the level) of one or more algorithms.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     /// List of file names from the `data` folder to process, associated to the algorithm to use and separated with a comma.
-    /// Algorithms currently supported: gzip, bzip2, xz2.
+    /// Algorithms currently supported: gzip, bzip2, xz2, zstd, lz4, brotli.
     ///
     /// For example: `RLbook2020.pdf=gzip,cyber.pdf=bzip2` will set up a mix job using gzip for `RLbook2020.pdf` and bzip2 for `cyber.pdf`. Documents can be repeated as long as they use different algorithms, e.g. `cyber.pdf=gzip,cyber.pdf=xz2`.
-    #[arg(short, long, value_delimiter = ',', value_parser = parse_key_val::< String, Alg >)]
-    documents: Vec<(String, Alg)>,
+    ///
+    /// Several algorithms can be mixed on the same document by separating them with a `+`: `cyber.pdf=gzip+xz2+bzip2` builds a single lower convex hull over the setups of all three algorithms.
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_key_val::< String, Families >)]
+    documents: Vec<(String, Families)>,
 
     /// Time budget, represented as a f64 value describing the budget in seconds.
     #[arg(short, long)]
     budget: Option<f64>,
 
+    /// Size budget, the maximum total compressed size in bytes. Instead of the smallest result within a time budget, the mix with the smallest time required
+    /// whose expected size is within this budget is applied (e.g. when the constraint is a storage quota rather than CPU time).
+    #[arg(long, conflicts_with = "budget")]
+    size_budget: Option<u64>,
+
+    /// Price of a second of compression on one core, in dollars. Along with --storage-price, the mix with the smallest total cost (compression, storage and decompression at every read)
+    /// is applied instead of the smallest one, within the time budget if --budget is passed. The expected cost of each document is saved in `results/cost-report.csv`.
+    #[arg(long, requires = "storage_price", conflicts_with = "size_budget")]
+    cpu_price: Option<f64>,
+
+    /// Price to store a GB for a month, in dollars. See --cpu-price.
+    #[arg(long, requires = "cpu_price")]
+    storage_price: Option<f64>,
+
+    /// Months the results are stored for, with --cpu-price.
+    #[arg(long, default_value_t = 12.)]
+    retention_months: f64,
+
+    /// Times the results are expected to be read (and decompressed) while they are stored, with --cpu-price.
+    #[arg(long, default_value_t = 0.)]
+    reads: f64,
+
+    /// Seconds of cpu time to decompress a GB of data at each read, with --cpu-price. Mixes whose setups have a decompression time (see --decompression) use it instead.
+    #[arg(long, default_value_t = 2.)]
+    decompression_seconds_per_gb: f64,
+
+    /// Also measure (or estimate) the decompression time of every setup, cached with its metrics. The compressed size of each setup is plotted against it in `results/decompression-<workload>.html`.
+    /// A --metrics-table can supply it in an optional fifth column instead.
+    #[arg(long)]
+    decompression: bool,
+
+    /// Bytes a second of decompression is worth: the mix of a single document minimizes `size + weight * decompression time` within the time budget, over every setup with a decompression time.
+    /// Requires the decompression times, see --decompression.
+    #[arg(long, requires = "budget", conflicts_with_all = ["size_budget", "cpu_price"])]
+    decode_weight: Option<f64>,
+
+    /// Maximum expected time (in seconds) to decompress the result of a single document, enforced alongside the time budget. See --decode-weight.
+    #[arg(long, requires = "budget", conflicts_with_all = ["size_budget", "cpu_price"])]
+    decode_budget: Option<f64>,
+
+    /// With multiple documents, the maximum time (in seconds) each listed document may take, separated with a comma, e.g. `langlinks.sql=60`.
+    /// Enforced alongside the total budget, when the documents are compressed one after the other. Documents listed more than once can't be capped.
+    #[arg(long, value_delimiter = ',', value_parser = parse_key_val::< String, f64 >)]
+    document_cap: Vec<(String, f64)>,
+
+    /// With multiple documents, the weakest setup acceptable for each listed document, separated with a comma, e.g. `pagelinks.sql=Gzip_6`:
+    /// the document is compressed at least as much as that setup would. Enforced alongside the total budget, when the documents are compressed one after the other.
+    /// Documents listed more than once can't be floored.
+    #[arg(long, value_delimiter = ',', value_parser = parse_key_val::< String, String >)]
+    document_floor: Vec<(String, String)>,
+
     /// Estimate metrics calculation by using a portion of the workload instead of executing a full run. Requires specifying --estimate-block-ratio and --estimate-block-number flags. Avoid using estimation for small workloads (e.g. workloads requiring less than a 100 seconds budget)
+    ///
+    /// For folder workloads, each block is a subset of the files: --estimate-block-ratio is the fraction of the files in each subset and --estimate-block-number the number of subsets.
     #[arg(short, long)]
     estimate: bool,
 
@@ -74,13 +152,153 @@ struct Cli {
     estimate_block_ratio: Option<f64>,
 
     /// The number of blocks to use to estimate the algorithm metrics. More blocks generate a better averaged estimate, but the execution is slower.
+    /// With --estimate-tolerance, the maximum number of blocks to use (100 by default).
     #[arg(short = 'n', long)]
     estimate_block_number: Option<u64>,
 
+    /// Keep sampling blocks until the 95% confidence intervals of both size and time are within this fraction of their estimate (e.g. 0.05 for ±5%), instead of using a fixed number of blocks.
+    /// Only requires --estimate-block-ratio. The intervals are shown in the logs and as error bars in the convex hull plots.
+    #[arg(long, value_parser = parse_ratio)]
+    estimate_tolerance: Option<f64>,
+
+    /// Maximum time (in seconds) spent sampling blocks for each setup with --estimate-tolerance, even if the tolerance isn't reached.
+    #[arg(long)]
+    estimate_time_cap: Option<f64>,
+
+    /// How estimation blocks are drawn: stratified splits the document into as many strata as blocks and draws one block per stratum (blocks don't overlap), random draws each block anywhere in the document.
+    #[arg(long, value_enum, default_value_t = SamplingMode::Stratified)]
+    estimate_sampling: SamplingMode,
+
+    /// Seed of the estimation block sampling, to reproduce an estimate. Every setup of a document is estimated on the same blocks either way (paired measurements).
+    /// Without a seed, the content of the document is used as seed when caching, so that cached estimates are paired with new ones, otherwise a random seed is drawn.
+    #[arg(long)]
+    estimate_seed: Option<u64>,
+
+    /// How the metrics of the estimation blocks are extrapolated to the whole document: linearly, linearly once the fixed cost of each block (headers, encoder setup) is removed,
+    /// or with a power law fitted on blocks of several sizes (for setups whose ratio improves with more data).
+    #[arg(long, value_enum, default_value_t = ExtrapolationModel::Linear)]
+    estimate_extrapolation: ExtrapolationModel,
+
+    /// What the time required by each setup measures, and so what the budget means: the elapsed time of a run (wall), including reading the document and writing the result,
+    /// the CPU time of the process (cpu), or the elapsed time of the compression alone, on data read in memory beforehand and with the result discarded (in-memory).
+    /// The timing is recorded with the metrics of each setup, and applies to estimation blocks too.
+    #[arg(long, value_enum, default_value_t = TimingArg::Wall)]
+    timing: TimingArg,
+
+    /// Untimed runs of each setup before measuring it (on the whole document, or on each estimation block), to warm up the page cache and the cpu caches.
+    #[arg(long, default_value_t = 0)]
+    timing_warm_up: u32,
+
+    /// Timed runs of each setup, of which the median time is kept.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    timing_repetitions: u32,
+
+    /// Use the metrics from a csv file instead of measuring them, with one `workload,algorithm,compressed size (bytes),time required (sec)` line per setup,
+    /// optionally followed by the decompression time (sec). Workload and algorithm names are the ones used in the logs and plots, e.g. `gzip_cyber.pdf,Gzip_6,1048576,1.5,0.2`.
+    #[arg(long)]
+    metrics_table: Option<PathBuf>,
+
+    /// Predict the metrics from cheap statistics of each document (entropies, repeated sequences) with a model calibrated by --calibrate-predictor, instead of running any setup.
+    /// Much faster than estimates on very large documents, but only as accurate as the calibration documents resemble the new ones, and only on the calibration machine.
+    #[arg(long)]
+    predictor: Option<PathBuf>,
+
+    /// Calibrate a predictor model on the passed documents and save it to the given file, then exit without compressing anything.
+    /// The metrics of every setup are measured (or estimated, with --estimate) on each document; the more varied the documents, the better the model.
+    #[arg(long)]
+    calibrate_predictor: Option<PathBuf>,
+
+    /// Compress a standard benchmark corpus with every setup (of every single file algorithm, with the --zstd-* and --brotli-windows flags) and store their throughput on this machine in `results/.calibration`, then exit.
+    /// Once two machines are calibrated, metrics profiled on one of them can be rescaled to the other: cached metrics of other calibrated machines are reused, see also --metrics-machine and --target-machine.
+    #[arg(long)]
+    calibrate_machine: bool,
+
+    /// Fingerprint of the machine the --metrics-table was profiled on (the name of its file in `results/.calibration`), to rescale its times to the target machine.
+    #[arg(long, requires = "metrics_table", value_parser = parse_fingerprint)]
+    metrics_machine: Option<u64>,
+
+    /// Fingerprint of the machine the mix is planned for, if not this one. Profiled metrics are rescaled to it before computing the optimal mix, so that a job can be planned on a laptop and run on a server.
+    #[arg(long, value_parser = parse_fingerprint)]
+    target_machine: Option<u64>,
+
+    /// Don't reuse nor store measured metrics in the cache (`results/.cache`).
+    /// The cache is keyed by the document content, the setup, the estimation parameters and the machine, so it is safe to keep it between jobs.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Also use zstd levels with long distance matching (same as `zstd --long`), as additional setups.
+    #[arg(long)]
+    zstd_long: bool,
+
+    /// Also use zstd levels compressing with the given number of worker threads, as additional setups.
+    #[arg(long)]
+    zstd_workers: Option<u32>,
+
+    /// Window sizes (log2, between 10 and 24) to use for brotli setups, separated with a comma. Each window size is used with every quality.
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u32).range(10..=24), default_value = "22")]
+    brotli_windows: Vec<u32>,
+
+    /// Number of threads to use to apply a mix. With more than one thread, the two halves of the mix of a single document are compressed concurrently,
+    /// and multiple documents are packed on the threads so that every thread ends before the time budget, which is then a wall-clock deadline.
+    #[arg(short = 't', long, default_value_t = 1)]
+    threads: usize,
+
+    /// How the time budget is interpreted when using more than one thread: either the compression time available on each core (per-core), or a deadline for the whole mix (wall-clock).
+    #[arg(long, value_enum, default_value_t = BudgetMode::WallClock)]
+    budget_mode: BudgetMode,
+
+    /// Apply the mix of a single document in closed loop: the expensive setup compresses its part in this many chunks, and after each one the partition is moved
+    /// so that the mix ends at the time budget, based on the throughput actually measured. Each chunk is a segment of the result.
+    /// Only for sequential mixes of byte-oriented (non image) setups.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    controller_chunks: Option<u64>,
+
+    /// Never overrun the time budget: the elapsed time is checked while applying the mixes, and if the time left isn't enough for the planned setups, the remaining data falls back to the cheapest setup of its hull,
+    /// or is stored uncompressed once not even that fits. The bytes affected and the expected growth of the result are reported in the logs.
+    /// An infeasible budget applies the cheapest setups this way instead of failing. Only for sequential mixes of byte-oriented (non image) setups.
+    #[arg(long)]
+    hard_deadline: bool,
+
+    /// How many times the elapsed time is checked with --hard-deadline. The data is split at each check, so each one adds a segment to the results.
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    deadline_checkpoints: u64,
+
+    /// Verify the results once the mix has been applied, by decoding each segment and comparing it with the original document.
+    /// The process exits with a non-zero code if any segment doesn't match.
+    #[arg(long)]
+    verify: bool,
+
+    /// Decompress a mixed container from the `results` folder (without the `.zip` extension), writing it as `results/uncompressed_<name>`.
     #[arg(long)]
     decompress: Option<String>,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum SamplingMode {
+    Random,
+    Stratified,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum ExtrapolationModel {
+    Linear,
+    OverheadCorrected,
+    PowerLaw,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum TimingArg {
+    Wall,
+    Cpu,
+    InMemory,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum BudgetMode {
+    PerCore,
+    WallClock,
+}
+
 #[derive(Debug)]
 struct AlgParseError(String);
 
@@ -97,6 +315,9 @@ enum Alg {
     Gzip,
     Bzip2,
     Xz2,
+    Zstd,
+    Lz4,
+    Brotli,
     Png,
     FELICS,
     JPEGXL,
@@ -111,6 +332,9 @@ impl FromStr for Alg {
             "gzip" => Ok(Alg::Gzip),
             "bzip2" => Ok(Alg::Bzip2),
             "xz2" => Ok(Alg::Xz2),
+            "zstd" => Ok(Alg::Zstd),
+            "lz4" => Ok(Alg::Lz4),
+            "brotli" => Ok(Alg::Brotli),
             #[cfg(feature = "image")]
             "png" => Ok(Alg::Png),
             "felics" => Ok(Alg::FELICS),
@@ -127,6 +351,9 @@ impl fmt::Display for Alg {
             Alg::Gzip => write!(f, "gzip"),
             Alg::Bzip2 => write!(f, "bzip2"),
             Alg::Xz2 => write!(f, "xz2"),
+            Alg::Zstd => write!(f, "zstd"),
+            Alg::Lz4 => write!(f, "lz4"),
+            Alg::Brotli => write!(f, "brotli"),
             Alg::Png => write!(f, "png"),
             Alg::FELICS => write!(f, "felics"),
             Alg::JPEGXL => write!(f, "jpegxl"),
@@ -135,80 +362,340 @@ impl fmt::Display for Alg {
     }
 }
 
+/// One or more algorithm families to mix for the same document, separated with a `+` (e.g. `gzip+xz2`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Families(Vec<Alg>);
+
+impl FromStr for Families {
+    type Err = AlgParseError;
+
+    fn from_str(input: &str) -> Result<Families, Self::Err> {
+        let mut families = Vec::new();
+        for family in input.split('+') {
+            let family = family.parse()?;
+            // Repeating a family would only duplicate its setups
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+        Ok(Families(families))
+    }
+}
+
+impl fmt::Display for Families {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let families: Vec<_> = self.0.iter().map(|family| family.to_string()).collect();
+        write!(f, "{}", families.join("+"))
+    }
+}
+
+/// Zstd levels 1 to 22, along with their long distance matching and multithreaded variants if requested.
+fn zstd_setups(long_distance_matching: bool, workers: Option<u32>) -> Vec<Box<dyn Algorithm>> {
+    let mut variants = vec![(false, 0)];
+    if long_distance_matching {
+        variants.push((true, 0));
+    }
+    if let Some(workers) = workers.filter(|workers| *workers > 0) {
+        variants.push((false, workers));
+        if long_distance_matching {
+            variants.push((true, workers));
+        }
+    }
+    let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::with_capacity(22 * variants.len());
+    for i in 1..=22 {
+        for (long, workers) in &variants {
+            algorithms.push(Box::new(Zstd::new(ZstdCompressionLevel(i), *long, *workers)))
+        }
+    }
+    algorithms
+}
+
+/// Lz4 fast mode accelerations 16 to 1, followed by the default fast level and the HC levels 3 to 12.
+fn lz4_setups() -> Vec<Box<dyn Algorithm>> {
+    let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::new();
+    for acceleration in [16, 8, 4, 2, 1] {
+        algorithms.push(Box::new(Lz4::new(Lz4CompressionLevel(-acceleration))))
+    }
+    algorithms.push(Box::new(Lz4::new(Lz4CompressionLevel(1))));
+    for i in 3..=12 {
+        algorithms.push(Box::new(Lz4::new(Lz4CompressionLevel(i))))
+    }
+    algorithms
+}
+
+/// Brotli qualities 0 to 11, for every window size.
+fn brotli_setups(window_sizes: &[u32]) -> Vec<Box<dyn Algorithm>> {
+    let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::with_capacity(12 * window_sizes.len());
+    for i in 0..=11 {
+        for window_size in window_sizes {
+            algorithms.push(Box::new(Brotli::new(BrotliCompressionLevel(i), *window_size)))
+        }
+    }
+    algorithms
+}
+
+/// Setups of an algorithm family for a single document.
+fn file_setups(alg: Alg, args: &Cli) -> Result<Vec<Box<dyn Algorithm>>, MixError> {
+    let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::new();
+    match alg {
+        Alg::Gzip => {
+            for i in 1..=9 {
+                algorithms.push(Box::new(Gzip::new(GzipCompressionLevel(i))))
+            }
+        }
+        Alg::Bzip2 => {
+            for i in 1..=9 {
+                algorithms.push(Box::new(Bzip2::new(Bzip2CompressionLevel(i))))
+            }
+        }
+        Alg::Xz2 => {
+            for i in 1..=9 {
+                algorithms.push(Box::new(Xz2::new(Xz2CompressionLevel(i))))
+            }
+        }
+        Alg::Zstd => {
+            algorithms.append(&mut zstd_setups(args.zstd_long, args.zstd_workers))
+        }
+        Alg::Lz4 => {
+            algorithms.append(&mut lz4_setups())
+        }
+        Alg::Brotli => {
+            algorithms.append(&mut brotli_setups(&args.brotli_windows))
+        }
+        Alg::Png => {
+            #[cfg(feature = "image")]
+            for compression_type in vec![PNGCompressionType::Fast, PNGCompressionType::Best] {
+                for filter_type in vec![
+                    PNGFilterType::NoFilter,
+                    PNGFilterType::Adaptive,
+                    PNGFilterType::Avg,
+                    PNGFilterType::Paeth,
+                    PNGFilterType::Sub,
+                    PNGFilterType::Up
+                ] {
+                    algorithms.push(Box::new(PNG::new(compression_type, filter_type)))
+                }
+            }
+        }
+        _ => return Err(MixError::UnsupportedMode(format!("Algorithm {} is not supported on single files.", alg)))
+    }
+    Ok(algorithms)
+}
+
+/// Checks that every result (workload name, document file name) decodes to its original document, exiting with a non-zero code otherwise.
+fn verify_results(results: Vec<(String, String)>) {
+    let mut valid = true;
+    for (workload_name, file_name) in results {
+        let files = File::open(format!("results/{}.zip", workload_name)).and_then(|result| Ok((result, File::open(format!("data/{}", file_name))?)));
+        let (result, original) = match files {
+            Ok(files) => files,
+            Err(e) => {
+                log::error!("Couldn't open the result or the document of workload \"{}\": {}", workload_name, e);
+                valid = false;
+                continue;
+            }
+        };
+        match container::verify(&result, &original) {
+            Ok(verification) => {
+                for report in &verification.reports {
+                    match &report.check {
+                        SegmentCheck::Valid => log::info!("Workload \"{}\", segment #{} ({:?}): valid", workload_name, report.index, report.segment.algorithm),
+                        SegmentCheck::Mismatch { expected, found } => log::error!("Workload \"{}\", segment #{} ({:?}): mismatch (expected hash {:x}, found {:x})", workload_name, report.index, report.segment.algorithm, expected, found),
+                        SegmentCheck::Undecodable(e) => log::error!("Workload \"{}\", segment #{} ({:?}): couldn't be decoded ({})", workload_name, report.index, report.segment.algorithm, e),
+                    }
+                }
+                if verification.covered_len != verification.original_len {
+                    log::error!("Workload \"{}\": segments cover {} bytes out of {}", workload_name, verification.covered_len, verification.original_len);
+                }
+                valid &= verification.is_valid();
+            }
+            Err(e) => {
+                log::error!("Couldn't verify the result of workload \"{}\": {}", workload_name, e);
+                valid = false;
+            }
+        }
+    }
+    if !valid {
+        std::process::exit(1);
+    }
+}
+
+/// The time budget of a document of a multiple document job: its cap, if any, or the total budget.
+fn document_time_budget(args: &Cli, file_name: &str, budget: f64) -> Duration {
+    let cap = args.document_cap.iter().find(|(document, _)| document == file_name).map(|(_, cap)| *cap);
+    Duration::from_secs_f64(cap.unwrap_or(budget))
+}
+
+/// The limits of each entry of a multiple document job, from the caps and floors of its document.
+/// Floors name a setup of the document, whose profiled size is the maximum size of every entry of that document.
+fn document_limits(args: &Cli, workload_algorithms: &[Vec<AlgorithmMetrics>]) -> Result<Vec<WorkloadLimits>, MixError> {
+    let mut floors = HashMap::new();
+    for (file_name, setup) in &args.document_floor {
+        let size = args.documents
+            .iter()
+            .zip(workload_algorithms)
+            .filter(|((document, _), _)| document == file_name)
+            .flat_map(|(_, metrics)| metrics)
+            .find(|metrics| metrics.algorithm.name() == *setup)
+            .map(|metrics| metrics.compressed_size)
+            .ok_or_else(|| MixError::LimitsInfeasible(format!("no setup named {} was profiled on '{}'", setup, file_name)))?;
+        floors.insert(file_name, size);
+    }
+    Ok(args.documents
+        .iter()
+        .map(|(file_name, _)| WorkloadLimits {
+            cap: args.document_cap.iter().find(|(document, _)| document == file_name).map(|(_, cap)| Duration::from_secs_f64(*cap)),
+            floor: floors.get(file_name).copied(),
+        })
+        .collect())
+}
+
+/// Profiles every setup of each document, and fits a predictor model on the features of the documents.
+fn calibrate_predictor(args: &Cli, profiler: &Profiler, model_path: &Path) -> Result<(), MixError> {
+    let mut observations = Vec::new();
+    for (file_name, families) in &args.documents {
+        if metadata(format!("data/{}", file_name))?.is_dir() {
+            log::warn!("Skipping folder workload \"{}\": predictors are only calibrated on single documents.", file_name);
+            continue;
+        }
+        let mut workload = Workload::open(format!("{}_{}", families, file_name), format!("data/{}", file_name), Duration::from_secs(0), None)?;
+        let features = Features::from_file(&workload.data)?;
+        let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::new();
+        for alg in &families.0 {
+            algorithms.append(&mut file_setups(*alg, args)?);
+        }
+        for metrics in profiler.profile(&mut workload, algorithms)? {
+            observations.push(Observation { features, algorithm_name: metrics.algorithm.name(), compressed_size: metrics.compressed_size, time_required: metrics.time_required });
+        }
+    }
+    let model = PredictorModel::calibrate(&observations);
+    model.save(model_path)?;
+    log::info!("Predictor model of {} setups calibrated on {} documents, saved to {}", model.setups.len(), args.documents.len(), model_path.display());
+    Ok(())
+}
+
+/// The deadline mode of the mixes of a document, if requested and if its setups partition bytes.
+fn deadline(args: &Cli, families: &Families) -> Option<Deadline> {
+    if !args.hard_deadline {
+        return None;
+    }
+    if families.0.contains(&Alg::Png) {
+        log::warn!("Image setups partition rows, not bytes: the deadline mode is disabled");
+        return None;
+    }
+    Some(Deadline { checkpoints: args.deadline_checkpoints })
+}
+
+/// The profiles of the machine the metrics are profiled on and of the machine the mix is planned for, if they differ.
+fn machine_rescaling(args: &Cli, machines: &HashMap<u64, MachineProfile>) -> Result<Option<(MachineProfile, MachineProfile)>, MixError> {
+    let this_machine = machine_fingerprint();
+    let from = args.metrics_machine.unwrap_or(this_machine);
+    let to = args.target_machine.unwrap_or(this_machine);
+    if from == to {
+        return Ok(None);
+    }
+    let profile = |fingerprint: u64| machines.get(&fingerprint).cloned().ok_or_else(|| MixError::UnsupportedMode(format!(
+        "Machine {:016x} isn't calibrated: run --calibrate-machine on it and copy its profile to {}", fingerprint, DEFAULT_CALIBRATION_FOLDER)));
+    log::info!("Rescaling the metrics profiled on machine {:016x} to machine {:016x}", from, to);
+    Ok(Some((profile(from)?, profile(to)?)))
+}
+
 fn main() {
     env_logger::init();
     let args = Cli::parse();
+    if let Err(e) = run(args) {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Cli) -> Result<(), MixError> {
     if let Some(decompress_file) = args.decompress {
-        #[cfg(feature = "image")]
-        {
-            let mut file = File::open(format!("results/{}.zip", decompress_file)).unwrap();
-            let mut header_buffer = [0; 11];
-            file.read(&mut header_buffer).unwrap();
-            log::debug!("Header: {:?} - {}", header_buffer, file.metadata().unwrap().len());
-            if header_buffer == [137u8, 77u8, 73u8, 88u8, 80u8, 78u8, 71u8, 13u8, 10u8, 26u8, 10u8] {
-                log::debug!("Matches!");
-                let mut partition = [0; 8]; // an u64
-                let mut original_width = [0; 4]; // u32
-                let mut original_height = [0; 4]; // u32
-                file.read(&mut partition).unwrap();
-                file.read(&mut original_width).unwrap();
-                file.read(&mut original_height).unwrap();
-                let partition = u64::from_be_bytes(partition);
-                let original_width = u32::from_be_bytes(original_width);
-                let original_height = u32::from_be_bytes(original_height);
-                log::debug!("Partition is {}, width and height: {}-{}", partition, original_width, original_height);
-
-                let (first_image_bytes, color_type) = {
-                    let png = PngDecoder::new(&file).unwrap();
-                    let color_type = png.color_type();
-                    log::debug!("First image metadata: {}, {}, {}, {:?}", png.total_bytes(), png.dimensions().0, png.dimensions().1, color_type);
-                    let mut image_bytes = vec![0; png.total_bytes() as usize];
-                    png.read_image(&mut image_bytes).expect("failed to read first image");
-                    (image_bytes, color_type)
-                };
-
-                log::debug!("{} - {}", file.stream_position().unwrap(), file.metadata().unwrap().len());
-                file.seek(SeekFrom::Start(partition)).unwrap();
-                let mut header_buffer = [0; 11];
-                file.read(&mut header_buffer).unwrap();
-                log::debug!("Second header: {:?} - {}", header_buffer, file.metadata().unwrap().len());
-                if header_buffer == [137u8, 77u8, 73u8, 88u8, 80u8, 78u8, 71u8, 13u8, 10u8, 26u8, 10u8] {
-                    log::debug!("Second half matches!");
-
-                    let second_png = PngDecoder::new(&file).unwrap();
-                    // seeked
-                    log::debug!("Second image metadata: {}, {}, {}", second_png.total_bytes(), second_png.dimensions().0, second_png.dimensions().1);
-
-                    let mut image_bytes = vec![0; second_png.total_bytes() as usize];
-                    second_png.read_image(&mut image_bytes).expect("failed to read first image");
-                    let mut complete_image_bytes = first_image_bytes;
-                    complete_image_bytes.extend_from_slice(&image_bytes);
-
-                    let x = PngEncoder::new(File::create(format!("results/uncompressed_{}", decompress_file)).unwrap());
-                    x.write_image(&complete_image_bytes, original_width, original_height, color_type).expect("Failed to write uncompressed png");
-                }
+        let file = File::open(format!("results/{}.zip", decompress_file))?;
+        let container = Container::read(&file)?;
+        log::info!("Decompressing {} segments: {:?}", container.segments.len(), container.segments);
+        if container.segments.iter().any(|segment| segment.algorithm.is_image()) {
+            #[cfg(feature = "image")]
+            {
+                let (pixels, width, height, color_type) = container::decompress_image(&file)?;
+                let encoder = PngEncoder::new(File::create(format!("results/uncompressed_{}", decompress_file))?);
+                encoder.write_image(&pixels, width, height, color_type)?;
             }
-            return;
-        }
 
-        #[cfg(not(feature = "image"))]
-        panic!("Decompressing works only for images and requires the \"image\" feature.");
+            #[cfg(not(feature = "image"))]
+            return Err(MixError::UnsupportedMode(String::from("Decompressing images requires the \"image\" feature.")));
+        } else {
+            let mut output = File::create(format!("results/uncompressed_{}", decompress_file))?;
+            container::decompress(&file, &mut output)?;
+        }
+        return Ok(());
     }
 
-    let estimate_metadata = if args.estimate {
-        if let (Some(block_number), Some(block_ratio)) = (args.estimate_block_number, args.estimate_block_ratio) {
-            Some(EstimateMetadata{ block_number, block_ratio })
+    let estimate_source = if args.estimate {
+        if let (Some(tolerance), Some(block_ratio)) = (args.estimate_tolerance, args.estimate_block_ratio) {
+            Some(MetricsSource::AdaptiveEstimate(AdaptiveEstimateMetadata {
+                block_ratio,
+                tolerance,
+                max_blocks: args.estimate_block_number.unwrap_or(DEFAULT_MAX_ESTIMATE_BLOCKS).max(1),
+                time_cap: args.estimate_time_cap.map(Duration::from_secs_f64),
+            }))
+        } else if let (Some(block_number), Some(block_ratio)) = (args.estimate_block_number, args.estimate_block_ratio) {
+            Some(MetricsSource::Estimate(EstimateMetadata{ block_number, block_ratio }))
         } else {
             let mut cmd = Cli::command();
             cmd.error(
                 ErrorKind::MissingRequiredArgument,
-                "Estimating algorithm metrics requires passing both the --estimate-block-number and --estimate-block-ratio flags.",
+                "Estimating algorithm metrics requires passing both the --estimate-block-number and --estimate-block-ratio flags (or --estimate-tolerance and --estimate-block-ratio).",
             )
                 .exit();
         }
     } else {
         None
     };
+    let mut profiler = if let Some(metrics_table) = &args.metrics_table {
+        Profiler::new(MetricsSource::table_from_file(metrics_table)?)
+    } else if let Some(predictor) = &args.predictor {
+        Profiler::new(MetricsSource::Predict(PredictorModel::load(predictor)?))
+    } else if let Some(estimate_source) = estimate_source {
+        Profiler::new(estimate_source)
+    } else {
+        Profiler::new(MetricsSource::Measure)
+    };
+    let sampling = match args.estimate_sampling {
+        SamplingMode::Random => Sampling::Random,
+        SamplingMode::Stratified => Sampling::Stratified,
+    };
+    let extrapolation = match args.estimate_extrapolation {
+        ExtrapolationModel::Linear => Extrapolation::Linear,
+        ExtrapolationModel::OverheadCorrected => Extrapolation::OverheadCorrected,
+        ExtrapolationModel::PowerLaw => Extrapolation::PowerLaw,
+    };
+    let timing = Timing {
+        mode: match args.timing {
+            TimingArg::Wall => TimingMode::Wall,
+            TimingArg::Cpu => TimingMode::Cpu,
+            TimingArg::InMemory => TimingMode::InMemory,
+        },
+        warm_up: args.timing_warm_up,
+        repetitions: args.timing_repetitions,
+    };
+    profiler = profiler.with_sampling(sampling, args.estimate_seed).with_extrapolation(extrapolation).with_timing(timing).with_decompression(args.decompression);
+    if !args.no_cache {
+        profiler = profiler.with_cache(MetricsCache::new(DEFAULT_CACHE_FOLDER)?);
+    }
+
+    if args.calibrate_machine {
+        let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::new();
+        for alg in [Alg::Gzip, Alg::Bzip2, Alg::Xz2, Alg::Zstd, Alg::Lz4, Alg::Brotli] {
+            algorithms.append(&mut file_setups(alg, &args)?);
+        }
+        let path = MachineProfile::calibrate(&algorithms)?.save(Path::new(DEFAULT_CALIBRATION_FOLDER))?;
+        log::info!("Machine profile saved to {}", path.display());
+        return Ok(());
+    }
+    let machines = MachineProfile::load_all(Path::new(DEFAULT_CALIBRATION_FOLDER))?;
+    let rescaling = machine_rescaling(&args, &machines)?;
+    profiler = profiler.with_machines(machines);
 
     if args.documents.is_empty() {
         let mut cmd = Cli::command();
@@ -219,50 +706,114 @@ fn main() {
             .exit();
     }
 
-    let budget = if let Some(budget) = args.budget {
-        budget
-    } else {
+    let limited_documents = args.document_cap.iter().map(|(file_name, _)| file_name).chain(args.document_floor.iter().map(|(file_name, _)| file_name));
+    if let Some(file_name) = limited_documents.clone().find(|file_name| !args.documents.iter().any(|(document, _)| document == *file_name)) {
         let mut cmd = Cli::command();
         cmd.error(
-            ErrorKind::MissingRequiredArgument,
-            "The --budget argument was not provided.",
+            ErrorKind::ValueValidation,
+            format!("Limits were passed for '{}', which is not one of the documents.", file_name),
+        )
+            .exit();
+    }
+    if let Some(file_name) = limited_documents.clone().find(|file_name| args.documents.iter().filter(|(document, _)| document == *file_name).count() > 1) {
+        let mut cmd = Cli::command();
+        cmd.error(
+            ErrorKind::ValueValidation,
+            format!("Limits were passed for '{}', which is listed more than once: caps and floors only apply to documents listed once.", file_name),
         )
             .exit();
+    }
+    if args.document_cap.iter().any(|(_, cap)| !cap.is_finite() || *cap < 0.) {
+        let mut cmd = Cli::command();
+        cmd.error(
+            ErrorKind::ValueValidation,
+            "Document caps must be non-negative numbers of seconds.",
+        )
+            .exit();
+    }
+    if args.documents.len() == 1 && limited_documents.count() > 0 {
+        log::warn!("Document caps and floors only apply to multiple documents, use --budget for a single one.");
+    }
+
+    if let Some(model_path) = &args.calibrate_predictor {
+        return calibrate_predictor(&args, &profiler, model_path);
+    }
+
+    let cost_model = args.cpu_price.zip(args.storage_price).map(|(cpu_price_per_core_second, storage_price_per_gb_month)| CostModel {
+        cpu_price_per_core_second,
+        storage_price_per_gb_month,
+        retention_months: args.retention_months,
+        reads: args.reads,
+        decompression_seconds_per_gb: args.decompression_seconds_per_gb,
+    });
+    let budget = match (args.budget, args.size_budget, cost_model) {
+        (Some(budget), _, _) => budget,
+        // The time budget then follows from the mix within the size budget, or with the smallest cost
+        (None, Some(_), _) | (None, None, Some(_)) => 0.,
+        (None, None, None) => {
+            let mut cmd = Cli::command();
+            cmd.error(
+                ErrorKind::MissingRequiredArgument,
+                "Either the --budget or the --size-budget argument must be provided.",
+            )
+                .exit();
+        }
+    };
+    let decoding_objective = match (args.decode_weight, args.decode_budget) {
+        (None, None) => None,
+        (weight, decode_budget) => {
+            if weight.is_some_and(|weight| !weight.is_finite() || weight < 0.) || decode_budget.is_some_and(|decode_budget| !decode_budget.is_finite() || decode_budget < 0.) {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::ValueValidation,
+                    "The decode weight and the decode budget must be non-negative numbers.",
+                )
+                    .exit();
+            }
+            if !args.decompression && args.metrics_table.is_none() {
+                let mut cmd = Cli::command();
+                cmd.error(
+                    ErrorKind::MissingRequiredArgument,
+                    "The decoding objective requires the decompression time of the setups: pass --decompression, or a --metrics-table with decompression times.",
+                )
+                    .exit();
+            }
+            Some(DecodingObjective { weight: weight.unwrap_or(0.), decode_budget: decode_budget.map(Duration::from_secs_f64) })
+        }
     };
+    if args.decompression && args.predictor.is_some() {
+        log::warn!("Decompression times can't be predicted, they are only measured or estimated.");
+    }
+    if (args.size_budget.is_some() || cost_model.is_some() || decoding_objective.is_some()) && (args.controller_chunks.is_some() || args.hard_deadline) {
+        log::warn!("The controller and the deadline mode keep the mix within a time budget, they are ignored with a size budget, a cost model or a decoding objective.");
+    }
+
+    let execution_mode = match (args.threads, args.budget_mode) {
+        (0 | 1, _) => ExecutionMode::Sequential,
+        (threads, BudgetMode::PerCore) => ExecutionMode::PerCore(threads),
+        (threads, BudgetMode::WallClock) => ExecutionMode::WallClock(threads),
+    };
+    if args.hard_deadline && execution_mode != ExecutionMode::Sequential {
+        return Err(MixError::UnsupportedMode(String::from("The deadline mode only applies to mixes compressed sequentially, it can't be used with more than one thread")));
+    }
+    let available_threads = std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+    if args.threads > available_threads {
+        log::warn!("Using {} threads with only {} available: the time required by each setup won't hold when running them concurrently.", args.threads, available_threads);
+    }
 
     if args.documents.len() == 1 {
-        let (file_name, alg) = args.documents.first().unwrap();
+        let (file_name, families) = args.documents.first().unwrap();
         let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::new();
 
-        if metadata(format!("data/{}", file_name)).unwrap().is_dir() {
-            let mut workload = FolderWorkload::new(file_name.clone(), Duration::from_secs_f64(budget));
-            match alg {
-                Alg::Png => {
-                    #[cfg(feature = "image")]
-                    for compression_type in vec![PNGCompressionType::Fast, PNGCompressionType::Best] {
-                        for filter_type in vec![
-                            PNGFilterType::NoFilter,
-                            PNGFilterType::Adaptive,
-                            PNGFilterType::Avg,
-                            PNGFilterType::Paeth,
-                            PNGFilterType::Sub,
-                            PNGFilterType::Up
-                        ] {
-                            algorithms.push(Box::new(PNG::new_folder_workload(&mut workload, compression_type, filter_type, estimate_metadata)))
-                        }
-                    }
-                },
-                Alg::FELICS => {
-                    #[cfg(feature = "image")]
-                    algorithms.push(Box::new(algorithms::felics::FELICS::new_folder_workload(&mut workload, estimate_metadata)))
-                },
-                Alg::JPEGXL => {
-                    #[cfg(feature = "image")]
-                    algorithms.push(Box::new(algorithms::jpegxl::JPEGXL::new_folder_workload(&mut workload, estimate_metadata)))
-                },
-                Alg::Lossless => {
-                    #[cfg(feature = "image")]
-                    {
+        if metadata(format!("data/{}", file_name))?.is_dir() {
+            if args.size_budget.is_some() || cost_model.is_some() || decoding_objective.is_some() {
+                return Err(MixError::UnsupportedMode(String::from("Size budgets, cost models and decoding objectives are not supported for folder workloads")));
+            }
+            let mut workload = FolderWorkload::new(file_name.clone(), Duration::from_secs_f64(budget))?;
+            for alg in &families.0 {
+                match alg {
+                    Alg::Png => {
+                        #[cfg(feature = "image")]
                         for compression_type in vec![PNGCompressionType::Fast, PNGCompressionType::Best] {
                             for filter_type in vec![
                                 PNGFilterType::NoFilter,
@@ -272,116 +823,134 @@ fn main() {
                                 PNGFilterType::Sub,
                                 PNGFilterType::Up
                             ] {
-                                algorithms.push(Box::new(PNG::new_folder_workload(&mut workload, compression_type, filter_type, estimate_metadata)))
+                                algorithms.push(Box::new(PNG::new(compression_type, filter_type)))
+                            }
+                        }
+                    },
+                    Alg::FELICS => {
+                        #[cfg(feature = "image")]
+                        algorithms.push(Box::new(algorithms::felics::FELICS::new()))
+                    },
+                    Alg::JPEGXL => {
+                        #[cfg(feature = "image")]
+                        algorithms.push(Box::new(algorithms::jpegxl::JPEGXL::new()))
+                    },
+                    Alg::Lossless => {
+                        #[cfg(feature = "image")]
+                        {
+                            for compression_type in vec![PNGCompressionType::Fast, PNGCompressionType::Best] {
+                                for filter_type in vec![
+                                    PNGFilterType::NoFilter,
+                                    PNGFilterType::Adaptive,
+                                    PNGFilterType::Avg,
+                                    PNGFilterType::Paeth,
+                                    PNGFilterType::Sub,
+                                    PNGFilterType::Up
+                                ] {
+                                    algorithms.push(Box::new(PNG::new(compression_type, filter_type)))
+                                }
                             }
+                            algorithms.push(Box::new(algorithms::felics::FELICS::new()));
+                            algorithms.push(Box::new(algorithms::jpegxl::JPEGXL::new()));
+                            algorithms.push(Box::new(algorithms::losslessjpeg::LosslessJPEG::new(7)));
                         }
-                        algorithms.push(Box::new(algorithms::felics::FELICS::new_folder_workload(&mut workload, estimate_metadata)));
-                        algorithms.push(Box::new(algorithms::jpegxl::JPEGXL::new_folder_workload(&mut workload, estimate_metadata)));
-                        algorithms.push(Box::new(algorithms::losslessjpeg::LosslessJPEG::new_folder_workload(&mut workload, 7, estimate_metadata)));
                     }
-                }
-                _ => {todo!()}
-            }
-            log::info!("Applying mixed compression to single file '{}'", file_name);
-            process_folder(workload, algorithms);
-        } else {
-        let mut workload = Workload::new(format!("{}_{}", alg, file_name),
-                                         File::open(format!("data/{}", file_name))
-                                         .expect("Missing data file. Ensure the file exists and that it has been correctly placed in the project data folder.")
-                                         , Duration::from_secs_f64(budget), None);
-
-        match alg {
-            Alg::Gzip => {
-                for i in 1..=9 {
-                    algorithms.push(Box::new(Gzip::new(&mut workload, GzipCompressionLevel(i), estimate_metadata)))
+                    _ => return Err(MixError::UnsupportedMode(format!("Algorithm {} is not supported on folders.", alg)))
                 }
             }
-            Alg::Bzip2 => {
-                for i in 1..=9 {
-                    algorithms.push(Box::new(Bzip2::new(&mut workload, Bzip2CompressionLevel(i), estimate_metadata)))
-                }
+            let mut metrics = profiler.profile_folder(&mut workload, algorithms)?;
+            if let Some((from, to)) = &rescaling {
+                from.rescale(to, &mut metrics);
             }
-            Alg::Xz2 => {
-                for i in 1..=9 {
-                    algorithms.push(Box::new(Xz2::new(&mut workload, Xz2CompressionLevel(i), estimate_metadata)))
-                }
+            log::info!("Applying mixed compression to single file '{}'", file_name);
+            if execution_mode != ExecutionMode::Sequential {
+                log::warn!("Folder workloads are always compressed sequentially.");
             }
-            Alg::Png => {
-                #[cfg(feature = "image")]
-                for compression_type in vec![PNGCompressionType::Fast, PNGCompressionType::Best] {
-                    for filter_type in vec![
-                        PNGFilterType::NoFilter,
-                        PNGFilterType::Adaptive,
-                        PNGFilterType::Avg,
-                        PNGFilterType::Paeth,
-                        PNGFilterType::Sub,
-                        PNGFilterType::Up
-                    ] {
-                        algorithms.push(Box::new(PNG::new(&mut workload, compression_type, filter_type, estimate_metadata)))
-                    }
-                }
+            process_folder(workload, metrics)?;
+            if args.verify {
+                log::warn!("Verification is not supported for folder workloads.");
             }
-            _ => panic!("Algorithm not supported on single files.")
+        } else {
+        let mut workload = Workload::open(format!("{}_{}", families, file_name), format!("data/{}", file_name), Duration::from_secs_f64(budget), None)?;
+
+        for alg in &families.0 {
+            algorithms.append(&mut file_setups(*alg, &args)?);
+        }
+        let mut metrics = profiler.profile(&mut workload, algorithms)?;
+        if let Some((from, to)) = &rescaling {
+            from.rescale(to, &mut metrics);
         }
         log::info!("Applying mixed compression to single file '{}'", file_name);
-        process_single_document(workload, algorithms);
+        let workload_name = workload.name.clone();
+        let controller = match args.controller_chunks {
+            Some(_) if families.0.contains(&Alg::Png) => {
+                log::warn!("Image setups partition rows, not bytes: the mix is applied without a controller");
+                None
+            }
+            chunks => chunks.map(|chunks| Controller { chunks }),
+        };
+        match (args.size_budget, cost_model, decoding_objective) {
+            (Some(size_budget), _, _) => process_single_document_with_size_budget(workload, metrics, size_budget, execution_mode)?,
+            (None, Some(cost_model), _) => process_single_document_with_cost_model(workload, metrics, cost_model, args.budget.map(Duration::from_secs_f64), execution_mode)?,
+            (None, None, Some(objective)) => process_single_document_with_decoding(workload, metrics, objective, execution_mode)?,
+            (None, None, None) => process_single_document(workload, metrics, execution_mode, controller, deadline(&args, families))?,
+        }
+        if args.verify {
+            verify_results(vec![(workload_name, file_name.clone())]);
+        }
             }
     } else {
+        if decoding_objective.is_some() {
+            return Err(MixError::UnsupportedMode(String::from("Decoding objectives are only supported for a single document")));
+        }
         let mut workloads = Vec::new();
         let mut workload_algorithms = Vec::new();
         for (workload_filename, _) in args.documents.iter() {
-            if metadata(format!("data/{}", workload_filename)).unwrap().is_dir() {
-                panic!("Multiple folder processing is currently not supported.");
+            if metadata(format!("data/{}", workload_filename))?.is_dir() {
+                return Err(MixError::UnsupportedMode(String::from("Multiple folder processing is currently not supported.")));
             }
         }
 
-        for (workload_filename, alg) in args.documents {
-            let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::with_capacity(9);
-            let mut workload = Workload::new(format!("{}_{}", alg, workload_filename),
-                                             File::open(format!("data/{}", workload_filename))
-                                                 .expect("Missing data file. Ensure the file exists and that it has been correctly placed in the project data folder.")
-                                             , Duration::from_secs(0), None);
-            match alg {
-                Alg::Gzip => {
-                    for i in 1..=9 {
-                        algorithms.push(Box::new(Gzip::new(&mut workload, GzipCompressionLevel(i), estimate_metadata)))
-                    }
-                }
-                Alg::Bzip2 => {
-                    for i in 1..=9 {
-                        algorithms.push(Box::new(Bzip2::new(&mut workload, Bzip2CompressionLevel(i), estimate_metadata)))
-                    }
-                }
-                Alg::Xz2 => {
-                    for i in 1..=9 {
-                        algorithms.push(Box::new(Xz2::new(&mut workload, Xz2CompressionLevel(i), estimate_metadata)))
-                    }
-                }
-                Alg::Png => {
-                    #[cfg(feature = "image")]
-                    for compression_type in vec![PNGCompressionType::Fast, PNGCompressionType::Best] {
-                        for filter_type in vec![
-                            PNGFilterType::NoFilter,
-                            PNGFilterType::Adaptive,
-                            PNGFilterType::Avg,
-                            PNGFilterType::Paeth,
-                            PNGFilterType::Sub,
-                            PNGFilterType::Up
-                        ] {
-                            algorithms.push(Box::new(PNG::new(&mut workload, compression_type, filter_type, estimate_metadata)))
-                        }
-                    }
-                }
-                _ => panic!("Algorithm not supported on specific files.")
+        let mut results = Vec::new();
+        for (workload_filename, families) in &args.documents {
+            let mut algorithms: Vec<Box<dyn Algorithm>> = Vec::new();
+            let mut workload = Workload::open(format!("{}_{}", families, workload_filename), format!("data/{}", workload_filename), document_time_budget(&args, workload_filename, budget), None)?;
+            for alg in &families.0 {
+                algorithms.append(&mut file_setups(*alg, &args)?);
+            }
+            results.push((workload.name.clone(), workload_filename.clone()));
+            let mut metrics = profiler.profile(&mut workload, algorithms)?;
+            if let Some((from, to)) = &rescaling {
+                from.rescale(to, &mut metrics);
             }
+            workload_algorithms.push(metrics);
             workloads.push(workload);
-            workload_algorithms.push(algorithms);
         }
-        log::info!(
-            "Applying mixed compression to multiple documents: {:?}, with duration: {}s",
-            workloads.iter().map(|el| el.name.clone()).collect::<Vec<_>>(),
-            budget);
-        process_multiple_documents(workloads, workload_algorithms, Duration::from_secs_f64(budget))
+        let limits = document_limits(&args, &workload_algorithms)?;
+        if let Some(size_budget) = args.size_budget {
+            log::info!(
+                "Applying mixed compression to multiple documents: {:?}, with size budget: {} bytes",
+                workloads.iter().map(|el| el.name.clone()).collect::<Vec<_>>(),
+                size_budget);
+            process_multiple_documents_with_size_budget(workloads, workload_algorithms, size_budget, &limits, execution_mode)?;
+        } else if let Some(cost_model) = cost_model {
+            log::info!(
+                "Applying mixed compression to multiple documents: {:?}, with cost model: {:?}",
+                workloads.iter().map(|el| el.name.clone()).collect::<Vec<_>>(),
+                cost_model);
+            process_multiple_documents_with_cost_model(workloads, workload_algorithms, cost_model, args.budget.map(Duration::from_secs_f64), &limits, execution_mode)?;
+        } else {
+            log::info!(
+                "Applying mixed compression to multiple documents: {:?}, with duration: {}s",
+                workloads.iter().map(|el| el.name.clone()).collect::<Vec<_>>(),
+                budget);
+            let deadline = args.documents.iter().map(|(_, families)| deadline(&args, families)).collect::<Option<Vec<_>>>().and_then(|deadlines| deadlines.first().copied());
+            process_multiple_documents(workloads, workload_algorithms, Duration::from_secs_f64(budget), &limits, execution_mode, deadline)?;
+        }
+        if args.verify {
+            verify_results(results);
+        }
     }
+    Ok(())
 }
 
@@ -1,11 +1,56 @@
+use std::fs::File;
+use std::thread;
 use std::time::{Duration, Instant};
-use crate::algorithms::AlgorithmMetrics;
+use tempfile::tempfile;
+use crate::algorithms::{AlgorithmMetrics, ByteSize, ConfidenceInterval};
+use crate::allocation;
+use crate::allocation::{Binding, WorkloadLimits};
+use crate::container;
+use crate::controller::Controller;
+use crate::cost::CostModel;
+use crate::deadline::{Deadline, DeadlineJob};
 use crate::convex_hull::convex_hull_graham;
+use crate::error::MixError;
+use crate::scheduler;
+use crate::scheduler::{Job, Part};
 use crate::workload::{FolderWorkload, Workload};
 
 pub type MetricsWithBenefit<'a> = (&'a AlgorithmMetrics, f64);
 /// Also stores an identifier of the combination
 pub type CombinationWithBenefit<'a> = (Vec<MetricsWithBenefit<'a>>, f64, String);
+/// The segments compressed by a worker of a schedule, with the time it took
+type WorkerResult<'a> = Result<(Vec<(Job<'a>, File)>, Duration), MixError>;
+
+/// How the halves of a mix are executed, and how the time budget is interpreted accordingly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ExecutionMode {
+    /// The halves run one after the other, and the budget is their total time, as in the paper.
+    #[default]
+    Sequential,
+    /// The halves run concurrently on up to the given number of threads, and the budget is the compression time available on each core.
+    /// Each half of a mix runs on its own core, so it must fit in the budget: for a single document, this is the same as a wall-clock deadline.
+    PerCore(usize),
+    /// The halves run concurrently on up to the given number of threads, and the budget is a wall-clock deadline for the whole mix.
+    WallClock(usize),
+}
+
+impl ExecutionMode {
+    /// How many halves of a mix run at the same time.
+    pub fn concurrent_halves(&self) -> usize {
+        match self {
+            ExecutionMode::Sequential => 1,
+            ExecutionMode::PerCore(threads) | ExecutionMode::WallClock(threads) => (*threads).clamp(1, 2),
+        }
+    }
+
+    /// How many workers can compress at the same time.
+    pub fn threads(&self) -> usize {
+        match self {
+            ExecutionMode::Sequential => 1,
+            ExecutionMode::PerCore(threads) | ExecutionMode::WallClock(threads) => (*threads).max(1),
+        }
+    }
+}
 
 pub struct MixingPolicy<'a> {
     pub lower_convex_hull: Vec<MetricsWithBenefit<'a>>,
@@ -39,8 +84,8 @@ impl MixingPolicyMultipleWorkloads<'_> {
         }
 
         // Initial combination of initial useful setups for each doc
-        let mut previous_complessive_time = 0.;
-        let mut previous_complessive_size = 0;
+        let mut previous_complessive_time = current_combination.iter().fold(0., |acc, setup| acc + setup.0.time_required.as_secs_f64());
+        let mut previous_complessive_size = current_combination.iter().fold(0, |acc, setup| acc + setup.0.compressed_size);
         setup_combinations.push((current_combination.clone(), 0., "initial".to_string()));
         while !workload_lchs_by_benefit.iter().all(|x| x.is_empty()) {
             log::debug!("New lchs iteration: {:?}", workload_lchs_by_benefit);
@@ -74,112 +119,172 @@ impl MixingPolicyMultipleWorkloads<'_> {
         }
     }
 
-    /// Returns the mixes to apply to each workload to respect the total time budget provided.
-    /// At all times, only at most one workload will consist of an actual mix between useful setups. All the other workloads will only use one specific setup.
-    /// For now, only the total time budget is taken into account. The workload time budget is ignored.
-    pub fn mix_with_total_time_budget(&self, total_time_budget: Duration) -> Option<Vec<OptimalMix>> {
-        log::debug!("Calling mix_with_total_time_budget, {:?}", self.lower_convex_hull);
-        let optimal_combination: Option<Vec<_>> = self
-            .lower_convex_hull
-            .windows(2)
-            .find(|combination_pair| {
-                let (prev, curr) = (&combination_pair[0], &combination_pair[1]);
-                let prev_total_required_time = prev
-                    .0
-                    .iter()
-                    .fold(Duration::from_secs(0), |acc, metric| acc + metric.0.time_required);
-
-                let curr_total_required_time = curr
-                    .0
-                    .iter()
-                    .fold(Duration::from_secs(0), |acc, metric| acc + metric.0.time_required);
-
-                if total_time_budget >= prev_total_required_time && total_time_budget < curr_total_required_time {
-                    return true;
-                }
-                false
-            })
-            .map(|group| {
-                let (expensive_combination, cheap_combination) = (&group[1].0, &group[0].0);
-
-                let expensive_total_required_time = expensive_combination
-                    .iter()
-                    .fold(Duration::from_secs(0), |acc, metric| acc + metric.0.time_required);
-
-                let cheap_total_required_time = cheap_combination
-                    .iter()
-                    .fold(Duration::from_secs(0), |acc, metric| acc + metric.0.time_required);
-
-                // Merge the two combinations in a single vec of optimal mixes
-                cheap_combination
-                    .iter()
-                    .zip(expensive_combination)
-                    .map(|(cheap_metric, expensive_metric)| {
-                        if cheap_metric == expensive_metric {
-                            OptimalMix::Single(cheap_metric.0)
-                        } else {
-                            let fraction = (total_time_budget.as_secs_f64() - cheap_total_required_time.as_secs_f64()) / (expensive_total_required_time.as_secs_f64() - cheap_total_required_time.as_secs_f64());
-                            let fraction = (fraction * 100.).round();
-                            OptimalMix::Normal((expensive_metric.0, cheap_metric.0), fraction / 100.)
-                        }
-                    })
-                    .collect()
-            });
-        let optimal_combination = if let None = optimal_combination {
-            log::debug!("Checking the most expensive combination");
-            let most_expensive_combination = self
-                .lower_convex_hull
-                .last()
-                .unwrap();
-            let total_required_time = most_expensive_combination
-                .0
-                .iter()
-                .fold(Duration::from_secs(0), |acc, metric| acc + metric.0.time_required);
-
-            if total_required_time < total_time_budget {
-                Some(most_expensive_combination
-                    .0
-                    .iter()
-                    .map(|metric| OptimalMix::Single(metric.0))
-                    .collect::<Vec<_>>())
-            } else {
-                None
+    /// Returns the mixes to apply to each workload to respect the total time budget provided, with the smallest total size, see [allocation::optimal_allocation].
+    /// At most one workload will consist of an actual mix because of the total time budget, plus the workloads whose limits fall between two useful setups. All the other workloads will only use one specific setup.
+    /// The limits of each workload (by index, missing ones don't constrain their workload) are enforced alongside the total time budget. The workload time budget itself is ignored: pass it as the cap of the workload.
+    pub fn mix_with_total_time_budget(&self, total_time_budget: Duration, limits: &[WorkloadLimits]) -> Option<Vec<OptimalMix>> {
+        let allocation = allocation::optimal_allocation(&self.lower_convex_hull_per_workload, total_time_budget, limits)?;
+        let certificate = allocation.certificate;
+        log::info!("Optimal allocation: expected total size {:.0} bytes in {:.3}s, {:.1} bytes saved per additional second, lower bound {:.0} bytes",
+            certificate.total_size, certificate.total_time, certificate.multiplier, certificate.dual_bound);
+        if !certificate.is_optimal() {
+            log::warn!("The allocation is {:.0} bytes above its lower bound, it may not be optimal", certificate.gap());
+        }
+        for binding in &allocation.binding {
+            match binding {
+                Binding::TotalBudget => log::info!("Binding constraint: the total time budget of {:?}", total_time_budget),
+                Binding::Cap(workload) => log::info!("Binding constraint: the cap of workload {} ({:?})", workload, limits[*workload].cap),
+                Binding::Floor(workload) => log::info!("Binding constraint: the floor of workload {} ({:?} bytes)", workload, limits[*workload].floor),
             }
-        } else {
-            optimal_combination
-        };
-        log::debug!("Optimal combination: {:?}", optimal_combination);
+        }
+        log::debug!("Optimal combination: {:?}", allocation.mixes);
+        Some(allocation.mixes)
+    }
+
+    /// Returns the mixes to apply to each workload with the smallest total time required whose expected total size is within the size budget, along with that time.
+    /// They are the mixes of the smallest total time budget reaching the size budget, see [allocation::minimum_time_for_size], so the limits of each workload are enforced as well.
+    pub fn mix_with_total_size_budget(&self, total_size_budget: ByteSize, limits: &[WorkloadLimits]) -> Option<(Duration, Vec<OptimalMix<'_>>)> {
+        let total_time_budget = allocation::minimum_time_for_size(&self.lower_convex_hull_per_workload, total_size_budget, limits)?;
+        log::info!("Smallest total time budget within {} bytes: {:?}", total_size_budget, total_time_budget);
+        let optimal_mixes = self.mix_with_total_time_budget(total_time_budget, limits)?;
+        Some((total_time_budget, optimal_mixes))
+    }
+
+    /// Returns the mixes to apply to each workload with the smallest total cost, see [CostModel]. A time budget, if any, is the total time of the mixes, as in [MixingPolicyMultipleWorkloads::mix_with_total_time_budget].
+    /// Setups saving more bytes per second than the break-even benefit of the cost model pay for themselves, so the mixes are the optimal ones for the total time budget up to where they stop doing so.
+    /// The decompression cost of a workload is assumed to be the same whatever its mix, as without decompression times (see [CostModel]): the setups of each workload must not have one.
+    pub fn mix_for_cost(&self, cost_model: &CostModel, total_time_budget: Option<Duration>, limits: &[WorkloadLimits]) -> Option<Vec<OptimalMix<'_>>> {
+        let break_even_benefit = cost_model.break_even_benefit();
+        let break_even_time = allocation::time_for_benefit(&self.lower_convex_hull_per_workload, break_even_benefit, limits)?;
+        log::info!("Setups saving more than {:.1} bytes per second pay for themselves, up to a total time of {:?}", break_even_benefit, break_even_time);
+        self.mix_with_total_time_budget(total_time_budget.map_or(break_even_time, |total_time_budget| total_time_budget.min(break_even_time)), limits)
+    }
+
+    /// Returns the mixes to apply to each workload so that, once packed on `workers` workers, every worker ends before the deadline.
+    /// With more workers, the budget of each one is the deadline itself (both per-core and wall-clock budgets end up meaning the same), see [scheduler::mixes_within_deadline].
+    pub fn mix_with_deadline(&self, workers: usize, deadline: Duration) -> Option<Vec<OptimalMix>> {
+        let optimal_combination = scheduler::mixes_within_deadline(&self.lower_convex_hull_per_workload, workers, deadline);
+        log::debug!("Optimal combination on {} workers: {:?}", workers, optimal_combination);
         optimal_combination
     }
 
-    pub fn apply_optimal_combination(optimal_mixes: &Vec<OptimalMix>, workloads: &mut Vec<Workload>, total_time_budget: Duration) {
+    /// The makespan of the cheapest setups of each workload, packed on `workers` workers.
+    pub fn minimum_makespan(&self, workers: usize) -> Duration {
+        let cheapest: Vec<_> = self
+            .lower_convex_hull_per_workload
+            .iter()
+            .map(|lch| OptimalMix::Single(lch[0].0))
+            .collect();
+        scheduler::pack(scheduler::jobs(&cheapest), workers).predicted_makespan
+    }
+
+    /// Applies the optimal mix of each workload. With more than one thread, the jobs are packed on the workers and run in parallel, see [MixingPolicyMultipleWorkloads::apply_schedule].
+    /// Otherwise, with a deadline along with the cheapest setup of each hull, the workloads fall back to those setups (or to storing the data) rather than overrunning the total budget, see [Deadline].
+    pub fn apply_optimal_combination(optimal_mixes: &Vec<OptimalMix>, workloads: &mut Vec<Workload>, total_time_budget: Duration, execution_mode: ExecutionMode, deadline: Option<(Deadline, Vec<&AlgorithmMetrics>)>) -> Result<(), MixError> {
+        if execution_mode.threads() > 1 {
+            if deadline.is_some() {
+                log::warn!("The deadline mode only applies to workloads compressed sequentially, it is ignored");
+            }
+            return MixingPolicyMultipleWorkloads::apply_schedule(optimal_mixes, workloads, total_time_budget, execution_mode.threads());
+        }
+        if let Some((deadline, cheapest)) = deadline {
+            log::info!("Applying optimal combination in deadline mode");
+            let mut jobs = optimal_mixes
+                .iter()
+                .zip(workloads.iter_mut())
+                .zip(cheapest)
+                .map(|((optimal_mix, workload), cheapest)| DeadlineJob::new(optimal_mix, workload, cheapest))
+                .collect::<Result<Vec<_>, MixError>>()?;
+            deadline.apply(&mut jobs, total_time_budget)?;
+            return Ok(());
+        }
         log::info!("Applying optimal combination");
         let instant = Instant::now();
-        optimal_mixes
-            .iter()
-            .zip(workloads)
-            .for_each(|(optimal_mix, workload)| {
-                match optimal_mix {
-                    OptimalMix::Single(metrics) => {
-                        let instant = Instant::now();
-                        log::info!("Applying single algorithm for workload {}", workload.name);
-                        let data = metrics.algorithm.execute(workload);
-                        log::info!("Time passed for workload {}: {:?}", workload.name, instant.elapsed());
-                        data
-                    }
-                    OptimalMix::Normal((metric_a, metric_b), fraction) => {
-                        let workload_partition = ((workload.data.metadata().unwrap().len() as f64) * fraction).round() as usize;
-                        log::info!("Applying mix of algorithms with fraction {} and partition at index {} (data len is {})", fraction, workload_partition, workload.data.metadata().unwrap().len());
-                        let instant = Instant::now();
-                        log::debug!("Applying optimal mix: before algorithm A {:?}", instant.elapsed());
-                        metric_a.algorithm.execute_with_target(workload, workload_partition, true);
-                        log::debug!("Applying optimal mix: after algorithm A, before B {:?}", instant.elapsed());
-                        metric_b.algorithm.execute_with_target(workload, workload_partition, false);
-                        log::info!("Time passed for workload {}: {:?}", workload.name, instant.elapsed());
-                    }
+        for (optimal_mix, workload) in optimal_mixes.iter().zip(workloads) {
+            match optimal_mix {
+                OptimalMix::Single(metrics) => {
+                    let instant = Instant::now();
+                    log::info!("Applying single algorithm for workload {}", workload.name);
+                    // A single segment covering the whole workload, to always produce a mixed container
+                    let data_len = workload.data.metadata()?.len() as usize;
+                    metrics.algorithm.execute_with_target(workload, data_len, true)?;
+                    log::info!("Time passed for workload {}: {:?}", workload.name, instant.elapsed());
                 }
-            });
+                OptimalMix::Normal((metric_a, metric_b), fraction) => {
+                    let data_len = workload.data.metadata()?.len();
+                    let workload_partition = ((data_len as f64) * fraction).round() as usize;
+                    log::info!("Applying mix of algorithms with fraction {} and partition at index {} (data len is {})", fraction, workload_partition, data_len);
+                    let instant = Instant::now();
+                    log::debug!("Applying optimal mix: before algorithm A {:?}", instant.elapsed());
+                    metric_a.algorithm.execute_with_target(workload, workload_partition, true)?;
+                    log::debug!("Applying optimal mix: after algorithm A, before B {:?}", instant.elapsed());
+                    metric_b.algorithm.execute_with_target(workload, workload_partition, false)?;
+                    log::info!("Time passed for workload {}: {:?}", workload.name, instant.elapsed());
+                }
+            }
+        }
         log::info!("Time passed for the application of all mixes: {:?} (should be near the time budget which is {:?})", instant.elapsed(), total_time_budget);
+        Ok(())
+    }
+
+    /// Packs the jobs of the optimal mixes on the workers, and runs the jobs of each worker in its own thread.
+    /// Every job writes its segment in a temporary container, and the segments of each workload are then appended to its result in order.
+    /// The predicted and actual load of each worker are reported, along with the makespan.
+    fn apply_schedule(optimal_mixes: &[OptimalMix], workloads: &mut [Workload], deadline: Duration, workers: usize) -> Result<(), MixError> {
+        let schedule = scheduler::pack(scheduler::jobs(optimal_mixes), workers);
+        log::info!("Applying optimal combination on {} workers, predicted makespan: {:?}", workers, schedule.predicted_makespan);
+        // Every job gets its own handle on the data, since jobs on the same workload may run concurrently
+        let mut worker_jobs = Vec::with_capacity(workers);
+        for jobs in &schedule.workers {
+            let mut prepared_jobs = Vec::with_capacity(jobs.len());
+            for job in jobs {
+                let workload = &workloads[job.workload];
+                let data_len = workload.data.metadata()?.len();
+                let (partition, first_half) = match job.part {
+                    Part::Whole => (data_len as usize, true),
+                    Part::FirstHalf(fraction) => (((data_len as f64) * fraction).round() as usize, true),
+                    Part::SecondHalf(fraction) => (((data_len as f64) * fraction).round() as usize, false),
+                };
+                let target = Workload::new(format!("{} ({})", workload.name, job.metrics.algorithm.name()), workload.reopen_data()?, workload.time_budget, Some(tempfile()?))?;
+                prepared_jobs.push((*job, target, partition, first_half));
+            }
+            worker_jobs.push(prepared_jobs);
+        }
+
+        let instant = Instant::now();
+        let results: Vec<WorkerResult> = thread::scope(|scope| {
+            let handles: Vec<_> = worker_jobs
+                .into_iter()
+                .map(|jobs| scope.spawn(move || {
+                    let instant = Instant::now();
+                    let mut results = Vec::with_capacity(jobs.len());
+                    for (job, mut target, partition, first_half) in jobs {
+                        job.metrics.algorithm.execute_with_target(&mut target, partition, first_half)?;
+                        results.push((job, target.result_file));
+                    }
+                    Ok((results, instant.elapsed()))
+                }))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("A worker thread panicked"))
+                .collect()
+        });
+        let actual_makespan = instant.elapsed();
+
+        let mut segments = Vec::new();
+        for (worker, result) in results.into_iter().enumerate() {
+            let (results, load) = result?;
+            log::info!("Worker #{}: {} jobs, predicted load {:?}, actual load {:?}", worker, results.len(), schedule.worker_load(worker), load);
+            segments.extend(results);
+        }
+        // The first half of a mix precedes the second one
+        segments.sort_by_key(|(job, _)| (job.workload, matches!(job.part, Part::SecondHalf(_))));
+        for (job, result) in segments {
+            container::append(&workloads[job.workload].result_file, &result)?;
+        }
+        log::info!("Predicted makespan: {:?}, actual makespan: {:?} (deadline: {:?})", schedule.predicted_makespan, actual_makespan, deadline);
+        Ok(())
     }
 }
 
@@ -314,46 +419,230 @@ impl MixingPolicy<'_> {
         optimal_mix
     }
 
-    pub fn apply_optimal_mix(optimal_mix: &OptimalMix, workload: &mut Workload) {
+    /// The mix with the smallest time required whose expected compressed size is within the size budget, the dual of [MixingPolicy::optimal_mix].
+    /// Can result in a none if the size budget doesn't allow even for the most expensive algorithm.
+    pub fn optimal_mix_for_size(&self, size_budget: ByteSize) -> Option<OptimalMix<'_>> {
+        let cheapest = self.lower_convex_hull.first()?.0;
+        if cheapest.compressed_size <= size_budget {
+            return Some(OptimalMix::Single(cheapest));
+        }
+        let optimal_mix = self
+            .lower_convex_hull
+            .windows(2)
+            .find(|mix_group| mix_group[1].0.compressed_size <= size_budget)
+            .map(|group| {
+                let (expensive_alg, cheap_alg) = (group[1].0, group[0].0);
+                if expensive_alg.compressed_size == size_budget {
+                    return OptimalMix::Single(expensive_alg);
+                }
+                let fraction = (cheap_alg.compressed_size - size_budget) as f64 / (cheap_alg.compressed_size - expensive_alg.compressed_size) as f64;
+                // Rounded up, so that the mix stays within the size budget
+                OptimalMix::Normal((expensive_alg, cheap_alg), (fraction * 100.).ceil() / 100.)
+            });
+        log::debug!("Optimal mix for a size budget of {} bytes: {:?}", size_budget, optimal_mix);
+        optimal_mix
+    }
+
+    /// The mix with the smallest total cost, see [CostModel], within the time budget if any, for a workload of `uncompressed_size` bytes.
+    /// Each setup of the lower convex hull costs its compression, storage and decompression: with decompression times the cost along the hull isn't convex anymore, so every setup within the budget is tried,
+    /// along with the mixes of each of them with a slower setup filling the budget. The cost of a mix is linear in its fraction, so the other mixes can't be cheaper.
+    /// Equally cheap mixes (of collinear setups of the hull) are told apart by the slowest of their cheap setups.
+    /// Can result in a none if the time budget doesn't allow even for the cheapest algorithm.
+    pub fn optimal_mix_for_cost(&self, cost_model: &CostModel, uncompressed_size: ByteSize, workload_budget: Option<Duration>) -> Option<OptimalMix<'_>> {
+        let budget = workload_budget.map_or(f64::INFINITY, |workload_budget| workload_budget.as_secs_f64());
+        let mut optimal_mix = None;
+        let mut optimal_cost = f64::INFINITY;
+        let within_budget = self.lower_convex_hull.iter().take_while(|metric| metric.0.time_required.as_secs_f64() <= budget).count();
+        for (index, cheap_alg) in self.lower_convex_hull[..within_budget].iter().map(|metric| metric.0).enumerate().rev() {
+            let cheap_time = cheap_alg.time_required.as_secs_f64();
+            let mut candidates = vec![OptimalMix::Single(cheap_alg)];
+            for expensive_alg in self.lower_convex_hull[index + 1..].iter().map(|metric| metric.0) {
+                let expensive_time = expensive_alg.time_required.as_secs_f64();
+                if expensive_time <= budget {
+                    continue;
+                }
+                // Rounded down, so that the mix stays within the time budget
+                let fraction = ((budget - cheap_time) / (expensive_time - cheap_time) * 100.).floor() / 100.;
+                if fraction > 0. {
+                    candidates.push(OptimalMix::Normal((expensive_alg, cheap_alg), fraction));
+                }
+            }
+            for candidate in candidates {
+                let cost = cost_model.mix_cost(&candidate, uncompressed_size).total();
+                if cost < optimal_cost * (1. - 1e-9) {
+                    optimal_cost = cost;
+                    optimal_mix = Some(candidate);
+                }
+            }
+        }
+        log::debug!("Cheapest mix: {:?} (expected cost: ${:.6})", optimal_mix, optimal_cost);
+        optimal_mix
+    }
+
+    /// Like [MixingPolicy::optimal_mix], taking into account the halves of the mix that can run concurrently.
+    /// Concurrent halves must each fit in the budget, whether it is the time of each core or a wall-clock deadline.
+    pub fn optimal_mix_with_mode(&self, workload_budget: Duration, execution_mode: ExecutionMode) -> Option<OptimalMix<'_>> {
+        if execution_mode.concurrent_halves() == 1 {
+            self.optimal_mix(workload_budget)
+        } else {
+            self.optimal_concurrent_mix(workload_budget)
+        }
+    }
+
+    /// The smallest time budget allowing to compress the workload with the given execution mode, if the lower convex hull isn't empty.
+    pub fn minimum_budget(&self, execution_mode: ExecutionMode) -> Option<Duration> {
+        let cheapest = self.lower_convex_hull.first()?.0.time_required;
+        match self.lower_convex_hull.get(1) {
+            _ if execution_mode.concurrent_halves() == 1 => Some(cheapest),
+            // The two cheapest setups, with the fraction making both halves end at the same time
+            Some(second_cheapest) => {
+                let (cheapest, second_cheapest) = (cheapest.as_secs_f64(), second_cheapest.0.time_required.as_secs_f64());
+                Some(Duration::from_secs_f64(cheapest * second_cheapest / (cheapest + second_cheapest)))
+            }
+            None => Some(cheapest),
+        }
+    }
+
+    /// Finds the mix with the smallest expected size whose halves, running at the same time, both end before the deadline.
+    ///
+    /// The expensive half of a mix of setups A (expensive) and B (cheap) with fraction f ends after `f * time(A)`, and the cheap one after `(1 - f) * time(B)`.
+    /// The size only decreases with f, so every pair of useful setups is tried with the biggest f fitting the deadline.
+    /// Unlike the sequential case, the best pair isn't necessarily made of adjacent setups of the lower convex hull.
+    fn optimal_concurrent_mix(&self, deadline: Duration) -> Option<OptimalMix<'_>> {
+        let deadline = deadline.as_secs_f64();
+        let mut optimal_mix = None;
+        let mut optimal_size = f64::MAX;
+        for (index, expensive_alg) in self.lower_convex_hull.iter().map(|metric| metric.0).enumerate() {
+            let expensive_time = expensive_alg.time_required.as_secs_f64();
+            if expensive_time <= deadline {
+                if (expensive_alg.compressed_size as f64) < optimal_size {
+                    optimal_size = expensive_alg.compressed_size as f64;
+                    optimal_mix = Some(OptimalMix::Single(expensive_alg));
+                }
+                continue;
+            }
+            // Rounded down, so that the expensive half still fits in the deadline
+            let fraction = (deadline / expensive_time * 100.).floor() / 100.;
+            for cheap_alg in self.lower_convex_hull[..index].iter().map(|metric| metric.0) {
+                if (1. - fraction) * cheap_alg.time_required.as_secs_f64() > deadline {
+                    continue;
+                }
+                let size = fraction * expensive_alg.compressed_size as f64 + (1. - fraction) * cheap_alg.compressed_size as f64;
+                if size < optimal_size {
+                    optimal_size = size;
+                    optimal_mix = Some(OptimalMix::Normal((expensive_alg, cheap_alg), fraction));
+                }
+            }
+        }
+        log::debug!("Optimal concurrent mix: {:?} (expected size: {})", optimal_mix, optimal_size);
+        optimal_mix
+    }
+
+    /// Applies a mix to the workload. With a controller, a mix of two setups applied sequentially is applied in closed loop, see [Controller].
+    /// With a deadline, along with the cheapest setup of the hull, a mix applied sequentially falls back to that setup (or to storing the data) rather than overrunning the budget, see [Deadline].
+    pub fn apply_optimal_mix(optimal_mix: &OptimalMix, workload: &mut Workload, execution_mode: ExecutionMode, controller: Option<Controller>, deadline: Option<(Deadline, &AlgorithmMetrics)>) -> Result<(), MixError> {
         let instant = Instant::now();
+        match optimal_mix.expected_metrics() {
+            (compressed_size, time_required, Some(confidence_interval)) => {
+                log::info!("Expected compressed size: {} ± {} bytes, expected time required: {:?} ± {:?} (95% confidence, {} blocks)", compressed_size, confidence_interval.compressed_size, time_required, confidence_interval.time_required, confidence_interval.samples);
+            }
+            (compressed_size, time_required, None) => {
+                log::info!("Expected compressed size: {} bytes, expected time required: {:?}", compressed_size, time_required);
+            }
+        }
+        if let Some((deadline, cheapest)) = deadline {
+            if execution_mode.concurrent_halves() > 1 {
+                log::warn!("The halves of the mix run concurrently, the deadline mode is ignored");
+            } else {
+                if controller.is_some() {
+                    log::warn!("The deadline mode already checks the elapsed time, the controller is ignored");
+                }
+                let time_budget = workload.time_budget;
+                deadline.apply(&mut [DeadlineJob::new(optimal_mix, workload, cheapest)?], time_budget)?;
+                return Ok(());
+            }
+        }
         match optimal_mix {
             OptimalMix::Single(metrics) => {
                 log::debug!("Applying single algorithm");
-                metrics.algorithm.execute(workload)
+                // A single segment covering the whole workload, to always produce a mixed container
+                let data_len = workload.data.metadata()?.len() as usize;
+                metrics.algorithm.execute_with_target(workload, data_len, true)?;
             }
             OptimalMix::Normal((metric_a, metric_b), fraction) => {
-                let workload_partition = ((workload.data.metadata().unwrap().len() as f64) * fraction).round() as usize;
-                log::debug!("Applying mix of algorithms with fraction {} and partition at index {} (data len is {})", fraction, workload_partition, workload.data.metadata().unwrap().len());
+                let data_len = workload.data.metadata()?.len();
+                let workload_partition = ((data_len as f64) * fraction).round() as usize;
+                log::debug!("Applying mix of algorithms with fraction {} and partition at index {} (data len is {})", fraction, workload_partition, data_len);
                 let instant = Instant::now();
-                log::debug!("Applying optimal mix: before algorithm A {:?}", instant.elapsed());
-                metric_a.algorithm.execute_with_target(workload, workload_partition, true);
-                log::debug!("Applying optimal mix: after algorithm A, before B {:?}", instant.elapsed());
-                metric_b.algorithm.execute_with_target(workload, workload_partition, false);
+                if let (Some(controller), 1) = (controller, execution_mode.concurrent_halves()) {
+                    controller.apply((metric_a, metric_b), *fraction, workload)?;
+                } else if execution_mode.concurrent_halves() > 1 {
+                    if controller.is_some() {
+                        log::warn!("The halves of the mix run concurrently, the controller is ignored");
+                    }
+                    MixingPolicy::apply_halves_concurrently((metric_a, metric_b), workload, workload_partition)?;
+                } else {
+                    log::debug!("Applying optimal mix: before algorithm A {:?}", instant.elapsed());
+                    metric_a.algorithm.execute_with_target(workload, workload_partition, true)?;
+                    log::debug!("Applying optimal mix: after algorithm A, before B {:?}", instant.elapsed());
+                    metric_b.algorithm.execute_with_target(workload, workload_partition, false)?;
+                }
                 log::info!("Time passed: {:?} (should be near the time budget which is {:?})", instant.elapsed(), workload.time_budget);
             }
         }
         log::info!("Time passed for the application of all mixes: {:?} (should be near the time budget which is {:?})", instant.elapsed(), workload.time_budget);
+        Ok(())
+    }
+
+    /// Compresses both halves of a mix at the same time, each one in its own temporary container, then appends them to the result in order.
+    fn apply_halves_concurrently((metric_a, metric_b): (&AlgorithmMetrics, &AlgorithmMetrics), workload: &mut Workload, workload_partition: usize) -> Result<(), MixError> {
+        let mut halves = Vec::with_capacity(2);
+        for (metric, first_half) in [(metric_a, true), (metric_b, false)] {
+            let half = Workload::new(format!("{} ({} half)", workload.name, if first_half { "first" } else { "second" }), workload.reopen_data()?, workload.time_budget, Some(tempfile()?))?;
+            halves.push((metric, first_half, half));
+        }
+        let results: Vec<Result<File, MixError>> = thread::scope(|scope| {
+            let handles: Vec<_> = halves
+                .into_iter()
+                .map(|(metric, first_half, mut half)| scope.spawn(move || {
+                    let instant = Instant::now();
+                    metric.algorithm.execute_with_target(&mut half, workload_partition, first_half)?;
+                    log::debug!("Applying optimal mix: {} finished its half in {:?}", metric.algorithm.name(), instant.elapsed());
+                    Ok(half.result_file)
+                }))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("A thread compressing half of a mix panicked"))
+                .collect()
+        });
+        for result in results {
+            container::append(&workload.result_file, &result?)?;
+        }
+        Ok(())
     }
 
-    pub fn apply_optimal_mix_folder(optimal_mix: &OptimalMix, workload: &mut FolderWorkload) {
+    pub fn apply_optimal_mix_folder(optimal_mix: &OptimalMix, workload: &mut FolderWorkload) -> Result<(), MixError> {
         let instant = Instant::now();
         match optimal_mix {
             OptimalMix::Single(metrics) => {
                 log::debug!("Applying single algorithm");
-                metrics.algorithm.execute_on_folder(workload, false, None, false);
+                metrics.algorithm.execute_on_folder(workload, false, None, false)?;
             }
             OptimalMix::Normal((metric_a, metric_b), fraction) => {
-                let workload_partition = ((workload.data_files_size() as f64) * fraction).round() as usize;
-                log::debug!("Applying mix of algorithms with fraction {} and partition at index {} (data len is {})", fraction, workload_partition, workload.data_files_size());
+                let data_len = workload.data_files_size()?;
+                let workload_partition = ((data_len as f64) * fraction).round() as usize;
+                log::debug!("Applying mix of algorithms with fraction {} and partition at index {} (data len is {})", fraction, workload_partition, data_len);
                 let instant = Instant::now();
                 log::debug!("Applying optimal mix: before algorithm A {:?}", instant.elapsed());
-                metric_a.algorithm.execute_on_folder(workload, false, Some(workload_partition as u64), true);
+                metric_a.algorithm.execute_on_folder(workload, false, Some(workload_partition as u64), true)?;
                 log::debug!("Applying optimal mix: after algorithm A, before B {:?}", instant.elapsed());
-                metric_b.algorithm.execute_on_folder(workload, false, Some(workload_partition as u64), false);
+                metric_b.algorithm.execute_on_folder(workload, false, Some(workload_partition as u64), false)?;
                 log::info!("Time passed: {:?} (should be near the time budget which is {:?})", instant.elapsed(), workload.time_budget);
             }
         }
         log::info!("Time passed for the application of all mixes: {:?} (should be near the time budget which is {:?})", instant.elapsed(), workload.time_budget);
+        Ok(())
     }
 }
 
@@ -365,43 +654,46 @@ pub enum OptimalMix<'a> {
     Normal((&'a AlgorithmMetrics, &'a AlgorithmMetrics), f64),
 }
 
+impl OptimalMix<'_> {
+    /// Expected compressed size and time required of the mix, with their confidence interval if the metrics of its setups are estimated.
+    /// The half widths of the two setups are combined linearly, as if their errors were fully correlated, which is the conservative choice.
+    pub fn expected_metrics(&self) -> (ByteSize, Duration, Option<ConfidenceInterval>) {
+        match self {
+            OptimalMix::Single(metrics) => (metrics.compressed_size, metrics.time_required, metrics.confidence_interval),
+            OptimalMix::Normal((metric_a, metric_b), fraction) => {
+                let compressed_size = (metric_a.compressed_size as f64 * fraction + metric_b.compressed_size as f64 * (1. - fraction)) as ByteSize;
+                let time_required = metric_a.time_required.mul_f64(*fraction) + metric_b.time_required.mul_f64(1. - fraction);
+                let confidence_interval = match (metric_a.confidence_interval, metric_b.confidence_interval) {
+                    (None, None) => None,
+                    (a, b) => {
+                        let a = a.unwrap_or(ConfidenceInterval { compressed_size: 0, time_required: Duration::ZERO, samples: u64::MAX });
+                        let b = b.unwrap_or(ConfidenceInterval { compressed_size: 0, time_required: Duration::ZERO, samples: u64::MAX });
+                        Some(ConfidenceInterval {
+                            compressed_size: (a.compressed_size as f64 * fraction + b.compressed_size as f64 * (1. - fraction)) as ByteSize,
+                            time_required: a.time_required.mul_f64(*fraction) + b.time_required.mul_f64(1. - fraction),
+                            samples: a.samples.min(b.samples),
+                        })
+                    }
+                };
+                (compressed_size, time_required, confidence_interval)
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
-    use std::io::Write;
+    use std::io::{Seek, Write};
     use std::time::Duration;
     use tempfile::tempfile;
-    use crate::algorithms::{Algorithm, AlgorithmMetrics, BlockInfo, ByteSize};
-    use crate::mixing_policy::MixingPolicy;
-    use crate::workload::{FolderWorkload, Workload};
-
-    #[derive(Debug)]
-    struct MockAlgorithm {
-        pub compressed_size: ByteSize,
-        pub time_required: Duration,
-    }
-
-    impl Algorithm for MockAlgorithm {
-        fn name(&self) -> String {
-            "Mock".to_string()
-        }
-
-        fn compressed_size(&self) -> ByteSize {
-            self.compressed_size
-        }
-
-        fn time_required(&self) -> Duration {
-            self.time_required
-        }
-
-        fn execute(&self, _: &mut Workload) {  }
-
-        fn execute_on_tmp(&self, _: &mut Workload, _: Option<BlockInfo>) -> File { tempfile().unwrap() }
-
-        fn execute_with_target(&self, _: &mut Workload, _: usize, _: bool) {}
-
-        fn execute_on_folder(&self, _: &mut FolderWorkload, _: bool, _: Option<u64>, _: bool) -> u64 {0}
-    }
+    use crate::algorithms::{AlgorithmMetrics, ByteSize};
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::algorithms::mock::MockAlgorithm;
+    use crate::algorithms::xz2::{Xz2, Xz2CompressionLevel};
+    use crate::container::{AlgorithmId, Container, decompress};
+    use crate::cost::CostModel;
+    use crate::mixing_policy::{ExecutionMode, MixingPolicy, MixingPolicyMultipleWorkloads, OptimalMix};
+    use crate::workload::Workload;
 
     #[test]
     fn paper_polygonal_chain() {
@@ -409,23 +701,23 @@ mod tests {
         let mut tmp = tempfile().unwrap();
         tmp.write_all("test".as_bytes()).unwrap();
         let algorithm_metrics = vec![
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 1_000_000, time_required: Duration::from_secs(2) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 800_000 as ByteSize, time_required: Duration::from_secs(4) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 600_000 as ByteSize, time_required: Duration::from_secs(6) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 580_000 as ByteSize, time_required: Duration::from_secs(7) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 400_000 as ByteSize, time_required: Duration::from_secs(8) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 300_000 as ByteSize, time_required: Duration::from_secs(10) })),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 1_000_000, Duration::from_secs(2)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 800_000 as ByteSize, Duration::from_secs(4)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 600_000 as ByteSize, Duration::from_secs(6)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 580_000 as ByteSize, Duration::from_secs(7)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 400_000 as ByteSize, Duration::from_secs(8)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 300_000 as ByteSize, Duration::from_secs(10)),
         ];
         let algorithm_metrics = algorithm_metrics.iter().collect();
         let mixing_policy = MixingPolicy::new(algorithm_metrics);
 
         // Fetched on https://ch.mathworks.com/help/matlab/ref/convhull.html by using time_required as x and compressed_size as y, according to the paper plots
         let expected_algorithm_metrics = vec![
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 1_000_000, time_required: Duration::from_secs(2) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 800_000, time_required: Duration::from_secs(4) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 600_000 as ByteSize, time_required: Duration::from_secs(6) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 400_000 as ByteSize, time_required: Duration::from_secs(8) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 300_000 as ByteSize, time_required: Duration::from_secs(10) })),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 1_000_000, Duration::from_secs(2)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 800_000, Duration::from_secs(4)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 600_000 as ByteSize, Duration::from_secs(6)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 400_000 as ByteSize, Duration::from_secs(8)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 300_000 as ByteSize, Duration::from_secs(10)),
         ];
         let expected_algorithm_metrics: Vec<_> = expected_algorithm_metrics.iter().collect();
         let obtained_algorithm_metrics = mixing_policy.lower_convex_hull.iter().map(|el| el.0).collect::<Vec<&AlgorithmMetrics>>();
@@ -437,18 +729,182 @@ mod tests {
         let _ = env_logger::try_init();
         let mut tmp = tempfile().unwrap();
         tmp.write_all("test".as_bytes()).unwrap();
-        let workload = Workload::new(String::from("test"), tmp, Duration::from_secs(7), None);
+        let workload = Workload::new(String::from("test"), tmp, Duration::from_secs(7), None).unwrap();
         let algorithm_metrics = vec![
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 1_000_000, time_required: Duration::from_secs(2) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 800_000 as ByteSize, time_required: Duration::from_secs(4) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 600_000 as ByteSize, time_required: Duration::from_secs(6) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 580_000 as ByteSize, time_required: Duration::from_secs(7) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 400_000 as ByteSize, time_required: Duration::from_secs(8) })),
-            AlgorithmMetrics::new(Box::new(MockAlgorithm { compressed_size: 300_000 as ByteSize, time_required: Duration::from_secs(10) })),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 1_000_000, Duration::from_secs(2)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 800_000 as ByteSize, Duration::from_secs(4)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 600_000 as ByteSize, Duration::from_secs(6)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 580_000 as ByteSize, Duration::from_secs(7)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 400_000 as ByteSize, Duration::from_secs(8)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 300_000 as ByteSize, Duration::from_secs(10)),
         ];
         let algorithm_metrics = algorithm_metrics.iter().collect();
         let mixing_policy = MixingPolicy::new(algorithm_metrics);
         println!("LCH: {:?}", mixing_policy.lower_convex_hull);
         println!("{:?}", mixing_policy.optimal_mix(workload.time_budget));
     }
-}
\ No newline at end of file
+
+    fn paper_metrics() -> Vec<AlgorithmMetrics> {
+        vec![
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 1_000_000, Duration::from_secs(2)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 800_000 as ByteSize, Duration::from_secs(4)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 600_000 as ByteSize, Duration::from_secs(6)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 400_000 as ByteSize, Duration::from_secs(8)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 300_000 as ByteSize, Duration::from_secs(10)),
+        ]
+    }
+
+    #[test]
+    fn per_core_budget() {
+        let algorithm_metrics = paper_metrics();
+        let mixing_policy = MixingPolicy::new(algorithm_metrics.iter().collect());
+        // Two halves on 4 cores: each half must fit in the 3.5 seconds of its core
+        let budget = Duration::from_secs_f64(3.5);
+        match mixing_policy.optimal_mix_with_mode(budget, ExecutionMode::PerCore(4)) {
+            Some(OptimalMix::Normal((expensive, cheap), fraction)) => {
+                assert_eq!(expensive.time_required, Duration::from_secs(8));
+                assert_eq!(cheap.time_required, Duration::from_secs(6));
+                assert_eq!(fraction, 0.43);
+                assert!(expensive.time_required.mul_f64(fraction) <= budget);
+                assert!(cheap.time_required.mul_f64(1. - fraction) <= budget);
+            }
+            optimal_mix => panic!("Unexpected mix {:?}", optimal_mix),
+        }
+        // A single setup must fit in the budget of one core
+        assert!(matches!(mixing_policy.optimal_mix_with_mode(Duration::from_secs(10), ExecutionMode::PerCore(4)), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(10)));
+        assert!(mixing_policy.optimal_mix_with_mode(Duration::from_secs(1), ExecutionMode::PerCore(4)).is_none());
+        // The two cheapest setups, with both halves ending at the same time
+        let minimum = mixing_policy.minimum_budget(ExecutionMode::PerCore(4)).unwrap();
+        assert!((minimum.as_secs_f64() - 4. / 3.).abs() < 1e-9);
+        assert_eq!(mixing_policy.minimum_budget(ExecutionMode::PerCore(1)), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn optimal_mix_for_size() {
+        let algorithm_metrics = paper_metrics();
+        let mixing_policy = MixingPolicy::new(algorithm_metrics.iter().collect());
+        match mixing_policy.optimal_mix_for_size(500_000) {
+            Some(OptimalMix::Normal((expensive, cheap), fraction)) => {
+                assert_eq!(expensive.time_required, Duration::from_secs(8));
+                assert_eq!(cheap.time_required, Duration::from_secs(6));
+                assert_eq!(fraction, 0.5);
+            }
+            optimal_mix => panic!("Unexpected mix {:?}", optimal_mix),
+        }
+        assert!(matches!(mixing_policy.optimal_mix_for_size(2_000_000), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(2)));
+        assert!(matches!(mixing_policy.optimal_mix_for_size(400_000), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(8)));
+        assert!(mixing_policy.optimal_mix_for_size(299_999).is_none());
+    }
+
+    #[test]
+    fn optimal_mix_for_cost() {
+        let algorithm_metrics = paper_metrics();
+        let mixing_policy = MixingPolicy::new(algorithm_metrics.iter().collect());
+        // A byte stored costs a millionth of a dollar, so setups must save 75000 bytes per second: the benefits of the hull are 100000 bytes per second up to 8 seconds, then 50000
+        let cost_model = CostModel { cpu_price_per_core_second: 0.075, storage_price_per_gb_month: 1000., retention_months: 1., reads: 2., decompression_seconds_per_gb: 3. };
+        assert!(matches!(mixing_policy.optimal_mix_for_cost(&cost_model, 1_000_000_000, None), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(8)));
+        match mixing_policy.optimal_mix_for_cost(&cost_model, 1_000_000_000, Some(Duration::from_secs(7))) {
+            Some(OptimalMix::Normal((expensive, cheap), fraction)) => {
+                assert_eq!(expensive.time_required, Duration::from_secs(8));
+                assert_eq!(cheap.time_required, Duration::from_secs(6));
+                assert_eq!(fraction, 0.5);
+            }
+            optimal_mix => panic!("Unexpected mix {:?}", optimal_mix),
+        }
+        assert!(mixing_policy.optimal_mix_for_cost(&cost_model, 1_000_000_000, Some(Duration::from_secs(1))).is_none());
+        let expensive_cpu = CostModel { cpu_price_per_core_second: 0.2, ..cost_model };
+        assert!(matches!(mixing_policy.optimal_mix_for_cost(&expensive_cpu, 1_000_000_000, None), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(2)));
+
+        let breakdown = cost_model.cost(400_000, Duration::from_secs(8), 1_000_000_000, None);
+        assert!((breakdown.compression - 0.6).abs() < 1e-9);
+        assert!((breakdown.storage - 0.4).abs() < 1e-9);
+        assert!((breakdown.decompression - 0.45).abs() < 1e-9);
+        assert!((breakdown.total() - 1.45).abs() < 1e-9);
+        // A measured decompression time replaces the one per GB
+        let breakdown = cost_model.cost(400_000, Duration::from_secs(8), 1_000_000_000, Some(Duration::from_secs(1)));
+        assert!((breakdown.decompression - 0.15).abs() < 1e-9);
+    }
+
+    #[test]
+    fn optimal_mix_for_cost_with_decompression_times() {
+        // The two smallest setups take a second to decompress, which costs 0.15 dollars with two reads
+        let algorithm_metrics: Vec<_> = paper_metrics()
+            .into_iter()
+            .map(|metrics| {
+                let decompression_time = if metrics.time_required >= Duration::from_secs(8) { Duration::from_secs(1) } else { Duration::ZERO };
+                metrics.with_decompression_time(Some(decompression_time))
+            })
+            .collect();
+        let mixing_policy = MixingPolicy::new(algorithm_metrics.iter().collect());
+        let cost_model = CostModel { cpu_price_per_core_second: 0.075, storage_price_per_gb_month: 1000., retention_months: 1., reads: 2., decompression_seconds_per_gb: 3. };
+        assert!(matches!(mixing_policy.optimal_mix_for_cost(&cost_model, 1_000_000_000, None), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(6)));
+        assert!(matches!(mixing_policy.optimal_mix_for_cost(&cost_model, 1_000_000_000, Some(Duration::from_secs(7))), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(6)));
+        // Without reads, the decompression is free and the setup of 8 seconds is the cheapest again
+        let no_reads = CostModel { reads: 0., ..cost_model };
+        assert!(matches!(mixing_policy.optimal_mix_for_cost(&no_reads, 1_000_000_000, None), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(8)));
+
+        let optimal_mix = OptimalMix::Normal((&algorithm_metrics[3], &algorithm_metrics[2]), 0.5);
+        let breakdown = cost_model.mix_cost(&optimal_mix, 1_000_000_000);
+        assert!((breakdown.compression - 0.525).abs() < 1e-9);
+        assert!((breakdown.storage - 0.5).abs() < 1e-9);
+        assert!((breakdown.decompression - 0.075).abs() < 1e-9);
+    }
+
+    #[test]
+    fn wall_clock_concurrent_mix() {
+        let algorithm_metrics = paper_metrics();
+        let mixing_policy = MixingPolicy::new(algorithm_metrics.iter().collect());
+        // Half of the data compressed by the 10s setup and the other half by the 8s setup both end within 5s, and no other pair gets a smaller size
+        match mixing_policy.optimal_mix_with_mode(Duration::from_secs(5), ExecutionMode::WallClock(2)) {
+            Some(OptimalMix::Normal((expensive, cheap), fraction)) => {
+                assert_eq!(expensive.time_required, Duration::from_secs(10));
+                assert_eq!(cheap.time_required, Duration::from_secs(8));
+                assert_eq!(fraction, 0.5);
+            }
+            optimal_mix => panic!("Unexpected mix {:?}", optimal_mix),
+        }
+        assert!(matches!(mixing_policy.optimal_mix_with_mode(Duration::from_secs(10), ExecutionMode::WallClock(2)), Some(OptimalMix::Single(metrics)) if metrics.time_required == Duration::from_secs(10)));
+        // 2s and 4s setups split to end at the same time
+        let minimum = mixing_policy.minimum_budget(ExecutionMode::WallClock(2)).unwrap();
+        assert!((minimum.as_secs_f64() - 4. / 3.).abs() < 1e-9);
+        assert!(mixing_policy.optimal_mix_with_mode(Duration::from_secs_f64(1.3), ExecutionMode::WallClock(2)).is_none());
+        assert!(mixing_policy.optimal_mix_with_mode(Duration::from_secs_f64(1.4), ExecutionMode::WallClock(2)).is_some());
+    }
+
+    #[test]
+    fn concurrent_halves_make_one_container() {
+        let data = "Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura".repeat(500);
+        let mut tmp = tempfile().unwrap();
+        tmp.write_all(data.as_bytes()).unwrap();
+        tmp.rewind().unwrap();
+        let mut workload = Workload::new(String::from("concurrent"), tmp, Duration::from_secs(1), Some(tempfile().unwrap())).unwrap();
+        let expensive = AlgorithmMetrics::new(Box::new(Xz2::new(Xz2CompressionLevel(9))), 0, Duration::from_secs(2));
+        let cheap = AlgorithmMetrics::new(Box::new(Gzip::new(GzipCompressionLevel(1))), 0, Duration::from_secs(1));
+        MixingPolicy::apply_optimal_mix(&OptimalMix::Normal((&expensive, &cheap), 0.3), &mut workload, ExecutionMode::WallClock(2), None, None).unwrap();
+
+        let container = Container::read(&workload.result_file).unwrap();
+        assert_eq!(container.segments.iter().map(|segment| segment.algorithm).collect::<Vec<_>>(), vec![AlgorithmId::Xz2, AlgorithmId::Gzip]);
+        let mut output = Vec::new();
+        decompress(&workload.result_file, &mut output).unwrap();
+        assert_eq!(output, data.as_bytes());
+    }
+
+    #[test]
+    fn combined_hull_of_multiple_workloads() {
+        let first = paper_metrics();
+        let second = vec![
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 500, Duration::from_secs(1)),
+            AlgorithmMetrics::new(Box::new(MockAlgorithm), 300, Duration::from_secs(3)),
+        ];
+        let mixing_policy = MixingPolicyMultipleWorkloads::new(vec![first.iter().collect(), second.iter().collect()]);
+        // The benefit of each combination is relative to the previous one, starting from the cheapest setups
+        let (initial, rest) = mixing_policy.lower_convex_hull.split_first().unwrap();
+        assert_eq!(initial.1, 0.);
+        assert!(rest.iter().all(|combination| combination.1 > 0.));
+        assert!(rest.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        let mixes = mixing_policy.mix_with_total_time_budget(Duration::from_secs(4), &[]).unwrap();
+        assert_eq!(mixes.len(), 2);
+        assert!(mixing_policy.mix_with_total_time_budget(Duration::from_millis(2999), &[]).is_none());
+    }
+}
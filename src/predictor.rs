@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{read_to_string, write, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use crate::algorithms::ByteSize;
+use crate::error::MixError;
+
+/// Bytes read from a workload to compute its features, as evenly spaced chunks.
+const SAMPLE_CHUNKS: u64 = 128;
+const CHUNK_SIZE: u64 = 1 << 16;
+
+/// Bits of the hashed order 2 context.
+const ORDER2_CONTEXT_BITS: u32 = 12;
+
+/// Bits of the hash table used to find repeated 4 byte sequences.
+const MATCH_TABLE_BITS: u32 = 16;
+
+/// Regularization of the least squares fit, so that a few calibration documents still give a usable model.
+const RIDGE: f64 = 1e-3;
+
+/// First line of a model file.
+const MODEL_HEADER: &str = "mix_compression predictor v1";
+
+/// Cheap statistics of a workload, used to predict the metrics of each setup without running it.
+/// Entropies are in bits per byte (between 0 and 8).
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Features {
+    /// Order 0 entropy of the bytes
+    pub entropy: f64,
+    /// Entropy of a byte given the previous one
+    pub order1_entropy: f64,
+    /// Entropy of a byte given the (hashed) previous two
+    pub order2_entropy: f64,
+    /// Fraction of positions where the next 4 bytes already appeared in the same chunk, a hint of how much a LZ77 match finder can save
+    pub match_ratio: f64,
+    /// Entropy of the differences between neighbouring pixels, for image workloads
+    pub gradient_entropy: Option<f64>,
+    /// Size of the workload
+    pub size: ByteSize,
+}
+
+impl Features {
+    /// Computes the features of a workload from evenly spaced chunks of it, restoring the cursor at the start of the file.
+    pub fn from_file(mut file: &File) -> Result<Features, MixError> {
+        let size = file.metadata()?.len();
+        let chunks = if size <= SAMPLE_CHUNKS * CHUNK_SIZE { 1 } else { SAMPLE_CHUNKS };
+        let chunk_size = if chunks == 1 { size } else { CHUNK_SIZE };
+        let stride = if chunks == 1 { 0 } else { (size - chunk_size) / (chunks - 1) };
+
+        let mut order0 = vec![0u64; 256];
+        let mut order1 = vec![0u64; 256 * 256];
+        let mut order2 = vec![0u64; (1 << ORDER2_CONTEXT_BITS) * 256];
+        let mut match_table = vec![0u32; 1 << MATCH_TABLE_BITS];
+        let (mut matches, mut positions) = (0u64, 0u64);
+        let mut buffer = vec![0; chunk_size as usize];
+        for chunk in 0..chunks {
+            file.seek(SeekFrom::Start(chunk * stride))?;
+            file.read_exact(&mut buffer)?;
+            match_table.fill(0);
+            for (index, byte) in buffer.iter().enumerate() {
+                let byte = *byte as usize;
+                order0[byte] += 1;
+                let previous = if index > 0 { buffer[index - 1] as usize } else { 0 };
+                let before_previous = if index > 1 { buffer[index - 2] as usize } else { 0 };
+                order1[previous * 256 + byte] += 1;
+                let context = ((before_previous * 256 + previous) * 2654435761) >> (32 - ORDER2_CONTEXT_BITS) & ((1 << ORDER2_CONTEXT_BITS) - 1);
+                order2[context * 256 + byte] += 1;
+                if index >= 3 {
+                    let sequence = u32::from_le_bytes([buffer[index - 3], buffer[index - 2], buffer[index - 1], buffer[index]]);
+                    let slot = (sequence.wrapping_mul(2654435761) >> (32 - MATCH_TABLE_BITS)) as usize;
+                    let candidate = match_table[slot] as usize;
+                    if candidate != 0 && buffer[candidate - 3..=candidate] == buffer[index - 3..=index] {
+                        matches += 1;
+                    }
+                    match_table[slot] = index as u32;
+                    positions += 1;
+                }
+            }
+        }
+        file.seek(SeekFrom::Start(0))?;
+
+        let features = Features {
+            entropy: conditional_entropy(&order0),
+            order1_entropy: conditional_entropy(&order1),
+            order2_entropy: conditional_entropy(&order2),
+            match_ratio: if positions == 0 { 0. } else { matches as f64 / positions as f64 },
+            gradient_entropy: gradient_entropy(file)?,
+            size,
+        };
+        log::debug!("Workload features: {:?}", features);
+        Ok(features)
+    }
+
+    /// The inputs of the linear models: a constant term, the entropies scaled to 0..1 and the match ratio.
+    fn inputs(&self) -> Vec<f64> {
+        vec![
+            1.,
+            self.entropy / 8.,
+            self.order1_entropy / 8.,
+            self.order2_entropy / 8.,
+            self.match_ratio,
+            self.gradient_entropy.unwrap_or(0.) / 8.,
+        ]
+    }
+}
+
+/// Entropy of the bytes given their context, from counts laid out as 256 consecutive bytes per context.
+fn conditional_entropy(counts: &[u64]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.;
+    }
+    let mut bits = 0.;
+    for context in counts.chunks(256) {
+        let context_total: u64 = context.iter().sum();
+        for count in context.iter().filter(|count| **count > 0) {
+            bits += *count as f64 * (context_total as f64 / *count as f64).log2();
+        }
+    }
+    bits / total as f64
+}
+
+#[cfg(feature = "image")]
+fn gradient_entropy(mut file: &File) -> Result<Option<f64>, MixError> {
+    let mut head = [0; 16];
+    let read = file.read(&mut head)?;
+    file.seek(SeekFrom::Start(0))?;
+    if image::guess_format(&head[..read]).is_err() {
+        return Ok(None);
+    }
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    file.seek(SeekFrom::Start(0))?;
+    let image = image::load_from_memory(&buffer)?;
+    let bytes_per_pixel = image.color().bytes_per_pixel() as usize;
+    let pixels = image.as_bytes();
+    let mut counts = vec![0u64; 256];
+    for index in bytes_per_pixel..pixels.len() {
+        counts[pixels[index].wrapping_sub(pixels[index - bytes_per_pixel]) as usize] += 1;
+    }
+    Ok(Some(conditional_entropy(&counts)))
+}
+
+#[cfg(not(feature = "image"))]
+fn gradient_entropy(_file: &File) -> Result<Option<f64>, MixError> {
+    Ok(None)
+}
+
+/// Linear models of a setup, predicting the compression ratio and the seconds required per byte from the features of a workload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetupModel {
+    pub ratio: Vec<f64>,
+    pub seconds_per_byte: Vec<f64>,
+}
+
+/// Predicts the metrics of each setup from the features of a workload, with a model calibrated on measured metrics and stored on disk.
+///
+/// Predictions are much faster than estimates on very large workloads, since no setup runs at all, but only as good as the calibration documents are similar to the workload.
+/// Times are only meaningful on the machine the model was calibrated on.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PredictorModel {
+    pub setups: HashMap<String, SetupModel>,
+}
+
+/// Features of a calibration document, with the measured compressed size and time required of a setup on it.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub features: Features,
+    pub algorithm_name: String,
+    pub compressed_size: ByteSize,
+    pub time_required: Duration,
+}
+
+impl PredictorModel {
+    /// Fits the models of each setup with ridge regression on the observations.
+    pub fn calibrate(observations: &[Observation]) -> PredictorModel {
+        let mut by_setup: HashMap<&str, Vec<&Observation>> = HashMap::new();
+        for observation in observations {
+            by_setup.entry(&observation.algorithm_name).or_default().push(observation);
+        }
+        let setups = by_setup
+            .into_iter()
+            .map(|(name, observations)| {
+                let inputs: Vec<_> = observations.iter().map(|observation| observation.features.inputs()).collect();
+                let size = |observation: &&Observation| observation.features.size.max(1) as f64;
+                let ratios: Vec<_> = observations.iter().map(|observation| observation.compressed_size as f64 / size(observation)).collect();
+                let seconds_per_byte: Vec<_> = observations.iter().map(|observation| observation.time_required.as_secs_f64() / size(observation)).collect();
+                log::debug!("Calibrating the predictor of {} on {} documents", name, observations.len());
+                (name.to_string(), SetupModel { ratio: ridge_regression(&inputs, &ratios), seconds_per_byte: ridge_regression(&inputs, &seconds_per_byte) })
+            })
+            .collect();
+        PredictorModel { setups }
+    }
+
+    /// Predicted compressed size and time required of a setup on a workload, if the setup was calibrated.
+    pub fn predict(&self, features: &Features, algorithm_name: &str) -> Option<(ByteSize, Duration)> {
+        let model = self.setups.get(algorithm_name)?;
+        let inputs = features.inputs();
+        let evaluate = |weights: &[f64]| weights.iter().zip(&inputs).map(|(weight, input)| weight * input).sum::<f64>().max(0.);
+        let compressed_size = (evaluate(&model.ratio) * features.size as f64).round() as ByteSize;
+        let time_required = Duration::from_secs_f64(evaluate(&model.seconds_per_byte) * features.size as f64);
+        Some((compressed_size, time_required))
+    }
+
+    /// Reads a model written by [PredictorModel::save].
+    pub fn load(path: &Path) -> Result<PredictorModel, MixError> {
+        let content = read_to_string(path)?;
+        let mut lines = content.lines();
+        if lines.next() != Some(MODEL_HEADER) {
+            return Err(MixError::Decode(format!("{} is not a predictor model", path.display())));
+        }
+        let mut setups = HashMap::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let invalid_line = || MixError::Decode(format!("Invalid predictor model line: \"{}\"", line));
+            let fields: Vec<_> = line.split(';').collect();
+            if fields.len() != 3 {
+                return Err(invalid_line());
+            }
+            let weights = |field: &str| field.split(' ').map(|weight| weight.parse::<f64>()).collect::<Result<Vec<_>, _>>().map_err(|_| invalid_line());
+            setups.insert(fields[0].to_string(), SetupModel { ratio: weights(fields[1])?, seconds_per_byte: weights(fields[2])? });
+        }
+        Ok(PredictorModel { setups })
+    }
+
+    /// Writes the model as text, with one `algorithm;ratio weights;seconds per byte weights` line per setup.
+    pub fn save(&self, path: &Path) -> Result<(), MixError> {
+        let mut content = format!("{}\n", MODEL_HEADER);
+        let mut names: Vec<_> = self.setups.keys().collect();
+        names.sort();
+        let join = |weights: &[f64]| weights.iter().map(|weight| weight.to_string()).collect::<Vec<_>>().join(" ");
+        for name in names {
+            let model = &self.setups[name];
+            let _ = writeln!(content, "{};{};{}", name, join(&model.ratio), join(&model.seconds_per_byte));
+        }
+        write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Least squares weights of `inputs · weights = targets`, with a small ridge penalty on every weight but the constant term.
+fn ridge_regression(inputs: &[Vec<f64>], targets: &[f64]) -> Vec<f64> {
+    let dimension = inputs.first().map_or(0, |row| row.len());
+    // Normal equations (XᵀX + λI) w = Xᵀy, augmented with the right hand side
+    let mut system = vec![vec![0.; dimension + 1]; dimension];
+    for (row, target) in inputs.iter().zip(targets) {
+        for i in 0..dimension {
+            for j in 0..dimension {
+                system[i][j] += row[i] * row[j];
+            }
+            system[i][dimension] += row[i] * target;
+        }
+    }
+    for (i, equation) in system.iter_mut().enumerate().skip(1) {
+        equation[i] += RIDGE;
+    }
+    // Gaussian elimination with partial pivoting
+    for column in 0..dimension {
+        let pivot = (column..dimension).max_by(|a, b| system[*a][column].abs().total_cmp(&system[*b][column].abs())).unwrap();
+        system.swap(column, pivot);
+        if system[column][column].abs() < f64::EPSILON {
+            continue;
+        }
+        let pivot_row = system[column].clone();
+        for (_, equation) in system.iter_mut().enumerate().filter(|(row, _)| *row != column) {
+            let factor = equation[column] / pivot_row[column];
+            for (coefficient, pivot_coefficient) in equation.iter_mut().zip(&pivot_row).skip(column) {
+                *coefficient -= factor * pivot_coefficient;
+            }
+        }
+    }
+    (0..dimension)
+        .map(|i| if system[i][i].abs() < f64::EPSILON { 0. } else { system[i][dimension] / system[i][i] })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+    use tempfile::{tempdir, tempfile};
+    use crate::predictor::{Features, Observation, PredictorModel};
+
+    #[test]
+    fn entropy_of_constant_and_random_data() {
+        let mut constant = tempfile().unwrap();
+        constant.write_all(&[7; 10_000]).unwrap();
+        let features = Features::from_file(&constant).unwrap();
+        assert_eq!(features.entropy, 0.);
+        assert!(features.match_ratio > 0.99);
+
+        let mut random = tempfile().unwrap();
+        random.write_all(&(0..100_000).map(|_| rand::random::<u8>()).collect::<Vec<_>>()).unwrap();
+        let features = Features::from_file(&random).unwrap();
+        assert!(features.entropy > 7.9);
+        assert!(features.order1_entropy < features.entropy);
+        assert!(features.match_ratio < 0.01);
+    }
+
+    #[test]
+    fn calibrated_model_round_trip() {
+        // Compressed sizes proportional to the entropy, as an ideal entropy coder would do
+        let observations: Vec<_> = [1., 2., 4., 6., 8.]
+            .iter()
+            .map(|entropy| {
+                let features = Features { entropy: *entropy, order1_entropy: *entropy, order2_entropy: *entropy, size: 8000, ..Features::default() };
+                Observation { features, algorithm_name: String::from("Entropy"), compressed_size: (*entropy * 1000.) as u64, time_required: Duration::from_millis(80) }
+            })
+            .collect();
+        let model = PredictorModel::calibrate(&observations);
+        let features = Features { entropy: 3., order1_entropy: 3., order2_entropy: 3., size: 8000, ..Features::default() };
+        let (compressed_size, time_required) = model.predict(&features, "Entropy").unwrap();
+        assert!((compressed_size as f64 - 3000.).abs() < 50.);
+        assert!((time_required.as_secs_f64() - 0.08).abs() < 0.005);
+        assert!(model.predict(&features, "Other").is_none());
+
+        let folder = tempdir().unwrap();
+        let path = folder.path().join("model");
+        model.save(&path).unwrap();
+        assert_eq!(PredictorModel::load(&path).unwrap(), model);
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::algorithms::{AdaptiveEstimateMetadata, Algorithm, AlgorithmMetrics, BlockInfo, ByteSize, ConfidenceInterval, EstimateMetadata};
+use crate::cache::{content_hash, folder_content_hash, MetricsCache};
+use crate::calibration::MachineProfile;
+use crate::error::MixError;
+use crate::estimation;
+use crate::estimation::{Extrapolation, Outputs, Profile, DECOMPRESSION_BLOCKS};
+use crate::predictor::{Features, PredictorModel};
+use crate::sampling::{FileSampleSet, SampleSet, Sampling};
+use crate::timing::Timing;
+use crate::workload::{FolderWorkload, Workload};
+
+/// Metrics supplied by the user (compressed size, time required and optionally decompression time), keyed by workload name and algorithm name.
+type MetricsTable = HashMap<(String, String), (ByteSize, Duration, Option<Duration>)>;
+
+/// Where the metrics (compressed size and time required) of each setup come from.
+#[derive(Debug, Clone)]
+pub enum MetricsSource {
+    /// Compress the whole workload with every setup
+    Measure,
+    /// Compress some random blocks of the workload with every setup, and extrapolate the metrics of the whole workload
+    Estimate(EstimateMetadata),
+    /// Compress random blocks of the workload until the confidence intervals of the extrapolated metrics are narrow enough
+    AdaptiveEstimate(AdaptiveEstimateMetadata),
+    /// Metrics supplied by the user, keyed by workload name and algorithm name
+    Table(MetricsTable),
+    /// Predict the metrics from cheap features of the workload, without running any setup, with a model calibrated beforehand
+    Predict(PredictorModel),
+}
+
+impl MetricsSource {
+    /// Reads a metrics table from a csv file, with one `workload,algorithm,compressed size (bytes),time required (sec)` line per setup,
+    /// optionally followed by a `decompression time (sec)` column.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn table_from_file(path: &Path) -> Result<MetricsSource, MixError> {
+        let mut table = HashMap::new();
+        for (index, line) in read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<_> = line.split(',').map(|field| field.trim()).collect();
+            let invalid_line = || MixError::Decode(format!("Invalid metrics table line {}: \"{}\"", index + 1, line));
+            if fields.len() != 4 && fields.len() != 5 {
+                return Err(invalid_line());
+            }
+            let parse_secs = |field: &str| field.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()).ok_or_else(invalid_line);
+            let compressed_size = fields[2].parse::<ByteSize>().map_err(|_| invalid_line())?;
+            let time_required = parse_secs(fields[3])?;
+            let decompression_time = fields.get(4).map(|field| parse_secs(field)).transpose()?;
+            table.insert((fields[0].to_string(), fields[1].to_string()), (compressed_size, time_required, decompression_time));
+        }
+        Ok(MetricsSource::Table(table))
+    }
+
+    /// Describes how the metrics are profiled, to tell apart cache entries of the same setup.
+    pub fn cache_parameters(&self) -> String {
+        match self {
+            MetricsSource::Measure => String::from("measure"),
+            MetricsSource::Estimate(metadata) => format!("estimate_{}_{}", metadata.block_number, metadata.block_ratio),
+            MetricsSource::AdaptiveEstimate(metadata) => {
+                let time_cap = metadata.time_cap.map(|time_cap| time_cap.as_secs_f64().to_string()).unwrap_or_else(|| String::from("none"));
+                format!("adaptive_{}_{}_{}_{}", metadata.block_ratio, metadata.tolerance, metadata.max_blocks, time_cap)
+            }
+            MetricsSource::Table(_) => String::from("table"),
+            MetricsSource::Predict(_) => String::from("predict"),
+        }
+    }
+}
+
+/// Computes the metrics of a list of setups on a workload, without requiring the setups to know how they are profiled.
+/// Measured and estimated metrics are reused from the cache, if any.
+///
+/// Estimates compress the same blocks with every setup of a workload (see [SampleSet]), drawn once according to the sampling strategy and seed.
+#[derive(Debug)]
+pub struct Profiler {
+    pub source: MetricsSource,
+    pub cache: Option<MetricsCache>,
+    pub sampling: Sampling,
+    /// Seed of the block sampling. If none, the content hash of the workload is used when caching (so that cached and new estimates are on the same blocks),
+    /// otherwise a random seed is drawn for each workload
+    pub seed: Option<u64>,
+    pub extrapolation: Extrapolation,
+    /// How measured and estimated times are taken, recorded in the metrics of each setup
+    pub timing: Timing,
+    /// Profiles of the calibrated machines by fingerprint. If this machine is calibrated, cached metrics of the other ones are reused once rescaled
+    pub machines: HashMap<u64, MachineProfile>,
+    /// Whether the decompression time of measured and estimated setups is timed too. Tables supply it in their own column
+    pub decompression: bool,
+}
+
+impl Profiler {
+    pub fn new(source: MetricsSource) -> Profiler {
+        Profiler { source, cache: None, sampling: Sampling::default(), seed: None, extrapolation: Extrapolation::default(), timing: Timing::default(), machines: HashMap::new(), decompression: false }
+    }
+
+    pub fn with_cache(mut self, cache: MetricsCache) -> Profiler {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: Sampling, seed: Option<u64>) -> Profiler {
+        self.sampling = sampling;
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_extrapolation(mut self, extrapolation: Extrapolation) -> Profiler {
+        self.extrapolation = extrapolation;
+        self
+    }
+
+    pub fn with_timing(mut self, timing: Timing) -> Profiler {
+        self.timing = timing;
+        self
+    }
+
+    pub fn with_machines(mut self, machines: HashMap<u64, MachineProfile>) -> Profiler {
+        self.machines = machines;
+        self
+    }
+
+    pub fn with_decompression(mut self, decompression: bool) -> Profiler {
+        self.decompression = decompression;
+        self
+    }
+
+    pub fn profile(&self, workload: &mut Workload, algorithms: Vec<Box<dyn Algorithm>>) -> Result<Vec<AlgorithmMetrics>, MixError> {
+        let content_hash = if self.uses_cache() { Some(content_hash(&workload.data)?) } else { None };
+        let seed = self.seed(content_hash);
+        // The blocks are drawn once, so that every setup is estimated on the same data
+        let workload_size = workload.data.metadata()?.len();
+        let sample_set = match &self.source {
+            MetricsSource::Estimate(metadata) => Some(SampleSet::new(workload_size, metadata.block_ratio, metadata.block_number, self.sampling, seed)),
+            MetricsSource::AdaptiveEstimate(metadata) => Some(SampleSet::new(workload_size, metadata.block_ratio, metadata.max_blocks, self.sampling, seed)),
+            _ => None,
+        };
+        let features = match &self.source {
+            MetricsSource::Predict(_) => Some(Features::from_file(&workload.data)?),
+            _ => None,
+        };
+        if let Some(sample_set) = &sample_set {
+            log::info!("Sampled {} blocks of workload \"{}\" with {:?} sampling and seed {}, shared by every setup", sample_set.blocks.len(), workload.name, self.sampling, seed);
+        }
+        let mut metrics = Vec::with_capacity(algorithms.len());
+        for algorithm in algorithms {
+            log::info!("Calculating compressed size and time required for algorithm {:?} (workload \"{}\") (source: {:?})", algorithm, workload.name, self.source);
+            let timing = self.timing.resolve(algorithm.as_ref());
+            // The outputs of the profiling runs are decompressed to time the decompression, instead of compressing the data again
+            let mut outputs = (self.decompression && algorithm.segment_algorithm().is_some()).then(Outputs::new);
+            let (compressed_size, time_required, confidence_interval) = self.cached(content_hash, algorithm.as_ref(), || match (&self.source, &sample_set) {
+                (MetricsSource::Measure, _) => Profiler::measure(workload, algorithm.as_ref(), timing, outputs.as_mut()),
+                (MetricsSource::Estimate(metadata), Some(sample_set)) => estimation::estimate(workload, algorithm.as_ref(), *metadata, sample_set, self.extrapolation, timing, outputs.as_mut()),
+                (MetricsSource::AdaptiveEstimate(metadata), Some(sample_set)) => estimation::adaptive_estimate(workload, algorithm.as_ref(), *metadata, sample_set, self.extrapolation, timing, outputs.as_mut()),
+                (MetricsSource::Table(table), _) => Profiler::lookup(table, &workload.name, algorithm.as_ref()),
+                (MetricsSource::Predict(model), _) => Profiler::predict(model, features.as_ref().expect("Features are computed for every prediction"), &workload.name, algorithm.as_ref()),
+                (_, None) => unreachable!("Blocks are sampled for every estimate"),
+            })?;
+            log::info!("Compressed size and time required calculated for algorithm {:?}:\nCompressed size: {:?};\nTime required: {:?};\nConfidence interval: {:?}", algorithm, compressed_size, time_required, confidence_interval);
+            let decompression_time = match &self.source {
+                MetricsSource::Table(table) => table.get(&(workload.name.clone(), algorithm.name())).and_then(|(_, _, decompression_time)| *decompression_time),
+                MetricsSource::Measure | MetricsSource::Estimate(_) | MetricsSource::AdaptiveEstimate(_) if self.decompression => {
+                    let blocks = sample_set.as_ref().map(|sample_set| &sample_set.blocks[..sample_set.blocks.len().min(DECOMPRESSION_BLOCKS)]);
+                    let decompression_time = self.cached_decompression_time(content_hash, algorithm.as_ref(), || Profiler::measure_decompression(workload, algorithm.as_ref(), timing, outputs, blocks))?;
+                    log::info!("Decompression time calculated for algorithm {:?}: {:?}", algorithm, decompression_time);
+                    decompression_time
+                }
+                _ => None,
+            };
+            metrics.push(AlgorithmMetrics::new(algorithm, compressed_size, time_required).with_confidence_interval(confidence_interval).with_timing(self.measures().then_some(timing))
+                .with_decompression_time(decompression_time));
+        }
+        Ok(metrics)
+    }
+
+    /// Estimates compress subsets of the files of the folder, see [estimation::FolderEstimator].
+    pub fn profile_folder(&self, workload: &mut FolderWorkload, algorithms: Vec<Box<dyn Algorithm>>) -> Result<Vec<AlgorithmMetrics>, MixError> {
+        let content_hash = if self.uses_cache() { Some(folder_content_hash(workload)?) } else { None };
+        let seed = self.seed(content_hash);
+        let sample_set = match &self.source {
+            MetricsSource::Estimate(metadata) => Some(FileSampleSet::new(workload, metadata.block_ratio, metadata.block_number, self.sampling, seed)?),
+            MetricsSource::AdaptiveEstimate(metadata) => Some(FileSampleSet::new(workload, metadata.block_ratio, metadata.max_blocks, self.sampling, seed)?),
+            _ => None,
+        };
+        if self.decompression {
+            log::warn!("The decompression time of folder workloads isn't measured");
+        }
+        if matches!(self.source, MetricsSource::Measure) && self.timing != Timing::default() {
+            log::warn!("Folder workloads are measured with a single wall time run, ignoring the {:?} timing", self.timing);
+        }
+        if let Some(sample_set) = &sample_set {
+            log::info!("Sampled {} file subsets of folder workload \"{}\" with {:?} sampling and seed {}, shared by every setup", sample_set.subsets.len(), workload.name, self.sampling, seed);
+            if self.extrapolation != Extrapolation::Linear {
+                log::warn!("Folder estimates are always scaled linearly by the bytes of the folder, ignoring the {:?} extrapolation", self.extrapolation);
+            }
+        }
+        let mut metrics = Vec::with_capacity(algorithms.len());
+        for algorithm in algorithms {
+            log::info!("Calculating compressed size and time required for algorithm {:?} (workload \"{}\") (source: {:?})", algorithm, workload.name, self.source);
+            // Whole folders are only timed as a single wall time run
+            let timing = match self.source {
+                MetricsSource::Measure => Timing::default(),
+                _ => self.timing.resolve(algorithm.as_ref()),
+            };
+            let (compressed_size, time_required, confidence_interval) = self.cached(content_hash, algorithm.as_ref(), || match (&self.source, &sample_set) {
+                (MetricsSource::Measure, _) => {
+                    let current_unix = Instant::now();
+                    let result = algorithm.execute_on_folder(workload, true, None, false)?;
+                    Ok((result, current_unix.elapsed(), None))
+                }
+                (MetricsSource::Estimate(_), Some(sample_set)) => estimation::estimate_folder(algorithm.as_ref(), sample_set, timing),
+                (MetricsSource::AdaptiveEstimate(metadata), Some(sample_set)) => estimation::adaptive_estimate_folder(algorithm.as_ref(), *metadata, sample_set, timing),
+                (MetricsSource::Table(table), _) => Profiler::lookup(table, &workload.name, algorithm.as_ref()),
+                (MetricsSource::Predict(_), _) => Err(MixError::UnsupportedMode(String::from("Metrics can't be predicted for folder workloads yet, measure or estimate them instead"))),
+                (_, None) => unreachable!("File subsets are sampled for every estimate"),
+            })?;
+            log::info!("Compressed size and time required calculated for algorithm {:?}:\nCompressed size: {:?};\nTime required: {:?};\nConfidence interval: {:?}", algorithm, compressed_size, time_required, confidence_interval);
+            metrics.push(AlgorithmMetrics::new(algorithm, compressed_size, time_required).with_confidence_interval(confidence_interval).with_timing(self.measures().then_some(timing)));
+        }
+        Ok(metrics)
+    }
+
+    fn seed(&self, content_hash: Option<u64>) -> u64 {
+        self.seed.or(content_hash).unwrap_or_else(rand::random)
+    }
+
+    /// Whether the metrics are measured on the workload (or on blocks of it), rather than supplied or predicted.
+    fn measures(&self) -> bool {
+        !matches!(self.source, MetricsSource::Table(_) | MetricsSource::Predict(_))
+    }
+
+    /// User supplied tables and predictions are never cached, since they are already at hand or cheaper than hashing the workload.
+    fn uses_cache(&self) -> bool {
+        self.cache.is_some() && self.measures()
+    }
+
+    /// Estimates also depend on how the blocks are sampled and extrapolated, and every measurement on how it is timed.
+    fn cache_parameters(&self) -> String {
+        let parameters = match (&self.source, self.seed) {
+            (MetricsSource::Estimate(_) | MetricsSource::AdaptiveEstimate(_), Some(seed)) => format!("{}_{:?}_{}_{:?}", self.source.cache_parameters(), self.sampling, seed, self.extrapolation),
+            (MetricsSource::Estimate(_) | MetricsSource::AdaptiveEstimate(_), None) => format!("{}_{:?}_{:?}", self.source.cache_parameters(), self.sampling, self.extrapolation),
+            _ => self.source.cache_parameters(),
+        };
+        format!("{}{}", parameters, self.timing.cache_parameters())
+    }
+
+    /// Returns the cached metrics of a setup, or computes and caches them.
+    fn cached(&self, content_hash: Option<u64>, algorithm: &dyn Algorithm, compute: impl FnOnce() -> Result<Profile, MixError>) -> Result<Profile, MixError> {
+        let (cache, content_hash) = match (&self.cache, content_hash) {
+            (Some(cache), Some(content_hash)) => (cache, content_hash),
+            _ => return compute(),
+        };
+        let key = cache.key(content_hash, &algorithm.name(), &self.cache_parameters());
+        if let Some(metrics) = cache.get(&key) {
+            log::info!("Using cached metrics for algorithm {:?} (key {})", algorithm, key);
+            return Ok(metrics);
+        }
+        if let Some(metrics) = self.cached_on_other_machines(cache, content_hash, algorithm) {
+            return Ok(metrics);
+        }
+        let (compressed_size, time_required, confidence_interval) = compute()?;
+        cache.insert(&key, compressed_size, time_required, confidence_interval)?;
+        Ok((compressed_size, time_required, confidence_interval))
+    }
+
+    /// Returns the cached decompression time of a setup, or measures and caches it.
+    fn cached_decompression_time(&self, content_hash: Option<u64>, algorithm: &dyn Algorithm, compute: impl FnOnce() -> Result<Option<Duration>, MixError>) -> Result<Option<Duration>, MixError> {
+        let (cache, content_hash) = match (&self.cache, content_hash) {
+            (Some(cache), Some(content_hash)) => (cache, content_hash),
+            _ => return compute(),
+        };
+        let key = cache.key(content_hash, &algorithm.name(), &self.cache_parameters());
+        if let Some(decompression_time) = cache.get_decompression_time(&key) {
+            log::info!("Using cached decompression time for algorithm {:?} (key {})", algorithm, key);
+            return Ok(decompression_time);
+        }
+        let decompression_time = compute()?;
+        cache.insert_decompression_time(&key, decompression_time)?;
+        Ok(decompression_time)
+    }
+
+    /// Metrics of a setup cached by another calibrated machine, rescaled to this one.
+    fn cached_on_other_machines(&self, cache: &MetricsCache, content_hash: u64, algorithm: &dyn Algorithm) -> Option<Profile> {
+        let target = self.machines.get(&cache.fingerprint)?;
+        self.machines.values().filter(|machine| machine.fingerprint != cache.fingerprint).find_map(|machine| {
+            let key = cache.key_on_machine(content_hash, &algorithm.name(), &self.cache_parameters(), machine.fingerprint);
+            let (compressed_size, time_required, confidence_interval) = cache.get(&key)?;
+            let rescaled_time = machine.time_on(target, &algorithm.name(), time_required)?;
+            let confidence_interval = confidence_interval.map(|confidence_interval| ConfidenceInterval {
+                time_required: machine.time_on(target, &algorithm.name(), confidence_interval.time_required).unwrap_or(confidence_interval.time_required),
+                ..confidence_interval
+            });
+            log::info!("Using cached metrics of machine {:016x} for algorithm {:?}, rescaled from {:?} to {:?}", machine.fingerprint, algorithm, time_required, rescaled_time);
+            Some((compressed_size, rescaled_time, confidence_interval))
+        })
+    }
+
+    fn measure(workload: &mut Workload, algorithm: &dyn Algorithm, timing: Timing, outputs: Option<&mut Outputs>) -> Result<Profile, MixError> {
+        let (compressed_size, time_required, output) = timing.measure_with_output(workload, algorithm, None)?;
+        if let (Some(outputs), Some(output)) = (outputs, output) {
+            outputs.push((workload.data.metadata()?.len(), output));
+        }
+        Ok((compressed_size, time_required, None))
+    }
+
+    /// Times the decompression of the outputs of the profiling runs of a setup as described by the timing. If they weren't kept (metrics reused from the cache, or compressed in memory),
+    /// the workload (or some blocks of it) is compressed once more, untimed.
+    /// The decompression time of blocks is scaled linearly by the bytes of the workload. Setups that can't be decoded have no decompression time.
+    fn measure_decompression(workload: &mut Workload, algorithm: &dyn Algorithm, timing: Timing, outputs: Option<Outputs>, blocks: Option<&[BlockInfo]>) -> Result<Option<Duration>, MixError> {
+        let Some(segment_algorithm) = algorithm.segment_algorithm() else {
+            log::warn!("The decompression time of algorithm {} can't be measured: its output can't be decoded", algorithm.name());
+            return Ok(None);
+        };
+        let workload_size = workload.data.metadata()?.len();
+        let outputs = match outputs {
+            Some(outputs) if !outputs.is_empty() => outputs,
+            _ => {
+                let blocks: Vec<Option<BlockInfo>> = match blocks {
+                    Some(blocks) => blocks.iter().copied().map(Some).collect(),
+                    None => vec![None],
+                };
+                let mut outputs = Outputs::with_capacity(blocks.len());
+                for block_info in blocks {
+                    outputs.push((block_info.map_or(workload_size, |block_info| block_info.block_size), algorithm.execute_on_tmp(workload, block_info)?));
+                }
+                outputs
+            }
+        };
+        let mut elapsed = Duration::ZERO;
+        for (_, output) in &outputs {
+            match timing.measure_decompression(segment_algorithm, output) {
+                Ok(time) => elapsed += time,
+                Err(MixError::UnsupportedMode(message)) => {
+                    log::warn!("The decompression time of algorithm {} can't be measured: {}", algorithm.name(), message);
+                    return Ok(None);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        let sampled: u64 = outputs.iter().map(|(size, _)| size).sum();
+        if sampled == 0 || sampled == workload_size {
+            return Ok(Some(elapsed));
+        }
+        Ok(Some(elapsed.mul_f64(workload_size as f64 / sampled as f64)))
+    }
+
+    fn lookup(table: &MetricsTable, workload_name: &str, algorithm: &dyn Algorithm) -> Result<Profile, MixError> {
+        table
+            .get(&(workload_name.to_string(), algorithm.name()))
+            .map(|(compressed_size, time_required, _)| (*compressed_size, *time_required, None))
+            .ok_or_else(|| MixError::UnsupportedMode(format!("The metrics table has no entry for algorithm {} on workload \"{}\"", algorithm.name(), workload_name)))
+    }
+
+    fn predict(model: &PredictorModel, features: &Features, workload_name: &str, algorithm: &dyn Algorithm) -> Result<Profile, MixError> {
+        model
+            .predict(features, &algorithm.name())
+            .map(|(compressed_size, time_required)| (compressed_size, time_required, None))
+            .ok_or_else(|| MixError::UnsupportedMode(format!("The predictor model wasn't calibrated for algorithm {} (workload \"{}\")", algorithm.name(), workload_name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::{Seek, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tempfile::{tempdir, tempfile};
+    use crate::algorithms::{AdaptiveEstimateMetadata, Algorithm, BlockInfo, EstimateMetadata};
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::cache::{content_hash, MetricsCache};
+    use crate::calibration::MachineProfile;
+    use crate::error::MixError;
+    use crate::profiler::{MetricsSource, Profiler};
+    use crate::timing::{Timing, TimingMode};
+    use crate::workload::{FolderWorkload, Workload};
+
+    /// Records the blocks it is asked to compress.
+    #[derive(Debug)]
+    struct RecordingAlgorithm(Arc<Mutex<Vec<BlockInfo>>>);
+
+    impl Algorithm for RecordingAlgorithm {
+        fn name(&self) -> String {
+            "Recording".to_string()
+        }
+
+        fn execute(&self, _: &mut Workload) -> Result<(), MixError> { Ok(()) }
+
+        fn execute_on_tmp(&self, _: &mut Workload, block_info: Option<BlockInfo>) -> Result<File, MixError> {
+            self.0.lock().unwrap().extend(block_info);
+            Ok(tempfile()?)
+        }
+
+        fn execute_with_target(&self, _: &mut Workload, _: usize, _: bool) -> Result<(), MixError> { Ok(()) }
+
+        fn execute_on_folder(&self, _: &mut FolderWorkload, _: bool, _: Option<u64>, _: bool) -> Result<u64, MixError> { Ok(0) }
+    }
+
+    fn workload() -> Workload {
+        let mut data = tempfile().unwrap();
+        data.write_all("Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura".repeat(50).as_bytes()).unwrap();
+        data.rewind().unwrap();
+        Workload::new(String::from("profiler"), data, Duration::from_secs(1), Some(tempfile().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn measure_without_touching_the_result() {
+        let mut workload = workload();
+        let algorithms: Vec<Box<dyn Algorithm>> = vec![Box::new(Gzip::new(GzipCompressionLevel(1))), Box::new(Gzip::new(GzipCompressionLevel(9)))];
+        let metrics = Profiler::new(MetricsSource::Measure).profile(&mut workload, algorithms).unwrap();
+        assert_eq!(metrics.len(), 2);
+        assert!(metrics.iter().all(|metric| metric.compressed_size > 0));
+        assert_eq!(workload.result_file.metadata().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn table_lookup() {
+        let mut table = HashMap::new();
+        table.insert((String::from("profiler"), String::from("Gzip_1")), (1000, Duration::from_millis(10), Some(Duration::from_millis(2))));
+        let profiler = Profiler::new(MetricsSource::Table(table));
+        let mut workload = workload();
+
+        let metrics = profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(1)))]).unwrap();
+        assert_eq!(metrics[0].compressed_size, 1000);
+        assert_eq!(metrics[0].time_required, Duration::from_millis(10));
+        assert_eq!(metrics[0].decompression_time, Some(Duration::from_millis(2)));
+
+        assert!(profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(9)))]).is_err());
+    }
+
+    #[test]
+    fn reuse_cached_metrics() {
+        let folder = tempdir().unwrap();
+        let mut workload = workload();
+        let profiler = Profiler::new(MetricsSource::Measure).with_cache(MetricsCache::new(folder.path()).unwrap());
+        let measured = profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(6)))]).unwrap();
+
+        let cache = profiler.cache.as_ref().unwrap();
+        let key = cache.key(content_hash(&workload.data).unwrap(), "Gzip_6", "measure");
+        assert_eq!(cache.get(&key), Some((measured[0].compressed_size, measured[0].time_required, None)));
+
+        // A fake entry proves the second run doesn't measure the setup again
+        cache.insert(&key, 1, Duration::from_nanos(1), None).unwrap();
+        let cached = profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(6)))]).unwrap();
+        assert_eq!(cached[0].compressed_size, 1);
+    }
+
+    #[test]
+    fn measure_and_cache_decompression_time() {
+        let folder = tempdir().unwrap();
+        let mut workload = workload();
+        let profiler = Profiler::new(MetricsSource::Measure).with_cache(MetricsCache::new(folder.path()).unwrap()).with_decompression(true);
+        let measured = profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(6)))]).unwrap();
+        assert!(measured[0].decompression_time.is_some());
+        assert_eq!(workload.result_file.metadata().unwrap().len(), 0);
+
+        let cache = profiler.cache.as_ref().unwrap();
+        let key = cache.key(content_hash(&workload.data).unwrap(), "Gzip_6", "measure");
+        assert_eq!(cache.get_decompression_time(&key), Some(measured[0].decompression_time));
+        cache.insert_decompression_time(&key, Some(Duration::from_nanos(1))).unwrap();
+        let cached = profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(6)))]).unwrap();
+        assert_eq!(cached[0].decompression_time, Some(Duration::from_nanos(1)));
+    }
+
+    #[test]
+    fn estimate_decompression_time_on_the_sampled_blocks() {
+        let mut workload = workload();
+        let timing = Timing { mode: TimingMode::Wall, warm_up: 1, repetitions: 3 };
+        let profiler = Profiler::new(MetricsSource::Estimate(EstimateMetadata { block_number: 4, block_ratio: 0.1 })).with_timing(timing).with_decompression(true);
+        let algorithms: Vec<Box<dyn Algorithm>> = vec![Box::new(Gzip::new(GzipCompressionLevel(6))), Box::new(RecordingAlgorithm(Arc::new(Mutex::new(Vec::new()))))];
+        let estimated = profiler.profile(&mut workload, algorithms).unwrap();
+        assert!(estimated[0].decompression_time.is_some());
+        // The output of a setup without segments can't be decoded
+        assert_eq!(estimated[1].decompression_time, None);
+    }
+
+    #[test]
+    fn reuse_metrics_cached_on_another_machine() {
+        let folder = tempdir().unwrap();
+        let mut workload = workload();
+        let cache = MetricsCache::new(folder.path()).unwrap();
+        let key = cache.key_on_machine(content_hash(&workload.data).unwrap(), "Gzip_6", "measure", 1);
+        cache.insert(&key, 1, Duration::from_secs(4), None).unwrap();
+        // This machine runs Gzip_6 twice as fast as machine 1
+        let throughputs = |throughput: f64| HashMap::from([(String::from("Gzip_6"), throughput)]);
+        let machines = HashMap::from([
+            (1, MachineProfile { fingerprint: 1, throughputs: throughputs(50.) }),
+            (cache.fingerprint, MachineProfile { fingerprint: cache.fingerprint, throughputs: throughputs(100.) }),
+        ]);
+        let profiler = Profiler::new(MetricsSource::Measure).with_cache(cache).with_machines(machines);
+        let metrics = profiler.profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(6)))]).unwrap();
+        assert_eq!(metrics[0].compressed_size, 1);
+        assert_eq!(metrics[0].time_required, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn adaptive_estimate_stops_within_the_tolerance() {
+        let mut workload = workload();
+        // Every block of a repeated sentence compresses to about the same size, so few blocks are needed for the size;
+        // timings are noisier, hence the loose tolerance and the block limit
+        let metadata = AdaptiveEstimateMetadata { block_ratio: 0.1, tolerance: 0.5, max_blocks: 50, time_cap: None };
+        let metrics = Profiler::new(MetricsSource::AdaptiveEstimate(metadata)).profile(&mut workload, vec![Box::new(Gzip::new(GzipCompressionLevel(6)))]).unwrap();
+        let confidence_interval = metrics[0].confidence_interval.unwrap();
+        assert!(confidence_interval.samples >= 5 && confidence_interval.samples <= 50);
+        assert!(confidence_interval.samples == 50 || confidence_interval.compressed_size as f64 <= 0.5 * metrics[0].compressed_size as f64);
+    }
+
+    #[test]
+    fn setups_share_the_same_blocks() {
+        let mut workload = workload();
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let algorithms: Vec<Box<dyn Algorithm>> = vec![Box::new(RecordingAlgorithm(first.clone())), Box::new(RecordingAlgorithm(second.clone()))];
+        Profiler::new(MetricsSource::Estimate(EstimateMetadata { block_number: 8, block_ratio: 0.05 })).profile(&mut workload, algorithms).unwrap();
+        assert_eq!(first.lock().unwrap().len(), 8);
+        assert_eq!(*first.lock().unwrap(), *second.lock().unwrap());
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::{Rng, SeedableRng};
+use crate::algorithms::BlockInfo;
+use crate::error::MixError;
+use crate::workload::FolderWorkload;
+
+/// How the blocks used to estimate metrics are drawn from a workload.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Sampling {
+    /// Each block ends at a uniformly random position, so blocks can overlap and oversample a region of the workload
+    Random,
+    /// The workload is split into as many strata as blocks, and each block is drawn at a random position within its own stratum.
+    /// Blocks never overlap (unless they are bigger than a stratum) and are spread over the whole workload.
+    #[default]
+    Stratified,
+}
+
+/// Draws the blocks of an estimate, the same way for every setup.
+///
+/// Two samplers built with the same parameters and seed return the same blocks, so that every setup of a workload is profiled on the same data.
+#[derive(Debug)]
+pub struct BlockSampler {
+    workload_size: u64,
+    block_size: u64,
+    sampling: Sampling,
+    /// Strata not sampled yet, in random order
+    strata: Vec<u64>,
+    stratum_size: u64,
+    rng: StdRng,
+}
+
+impl BlockSampler {
+    /// Prepares the sampling of up to `blocks` blocks of `block_ratio` of the workload.
+    /// With stratified sampling, strata are visited in random order, so that an estimate stopping early still covers the workload evenly.
+    pub fn new(workload_size: u64, block_ratio: f64, blocks: u64, sampling: Sampling, seed: u64) -> BlockSampler {
+        let block_size = ((workload_size as f64 * block_ratio).round() as u64).clamp(1, workload_size.max(1));
+        let blocks = blocks.max(1);
+        let stratum_size = workload_size / blocks;
+        if sampling == Sampling::Stratified && block_size > stratum_size {
+            log::warn!("Blocks of {} bytes are bigger than the {} strata of {} bytes, so they will overlap. Use a smaller block ratio or fewer blocks.", block_size, blocks, stratum_size);
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut strata: Vec<_> = (0..blocks).collect();
+        strata.shuffle(&mut rng);
+        BlockSampler { workload_size, block_size, sampling, strata, stratum_size, rng }
+    }
+
+    /// The next block to compress. Stratified sampling falls back to random blocks once every stratum has been sampled.
+    pub fn next_block(&mut self) -> BlockInfo {
+        let stratum = match self.sampling {
+            Sampling::Stratified => self.strata.pop(),
+            Sampling::Random => None,
+        };
+        let block_end_index = match stratum {
+            Some(stratum) => {
+                let stratum_start = stratum * self.stratum_size;
+                // The block fits in the stratum if possible, otherwise it starts at the stratum and spills over the next one
+                let latest_start = (stratum_start + self.stratum_size.saturating_sub(self.block_size)).min(self.workload_size - self.block_size);
+                let start = self.rng.gen_range(stratum_start.min(latest_start)..=latest_start);
+                start + self.block_size
+            }
+            None => self.rng.gen_range(self.block_size..=self.workload_size),
+        };
+        BlockInfo { block_size: self.block_size, block_end_index }
+    }
+}
+
+/// Blocks of a workload drawn once and compressed by every setup, so that setups are compared on the same data (paired measurements).
+/// Differences between setups then only depend on the setups, not on which blocks each one happened to draw.
+#[derive(Debug, Clone)]
+pub struct SampleSet {
+    pub blocks: Vec<BlockInfo>,
+}
+
+impl SampleSet {
+    pub fn new(workload_size: u64, block_ratio: f64, blocks: u64, sampling: Sampling, seed: u64) -> SampleSet {
+        let mut sampler = BlockSampler::new(workload_size, block_ratio, blocks, sampling, seed);
+        SampleSet { blocks: (0..blocks).map(|_| sampler.next_block()).collect() }
+    }
+}
+
+/// Subsets of the files of a folder workload drawn once and compressed by every setup, as [SampleSet] for folders.
+#[derive(Debug, Clone)]
+pub struct FileSampleSet {
+    pub folder_name: String,
+    /// Files of the folder with their size, sorted by size
+    pub files: Vec<(u64, PathBuf)>,
+    pub total_size: u64,
+    /// Indexes of the files of each subset
+    pub subsets: Vec<Vec<usize>>,
+}
+
+impl FileSampleSet {
+    /// Draws `subsets` subsets of `files_ratio` of the files of the folder (at least one file each).
+    /// With stratified sampling, files are split in as many strata (by size) as the files of a subset, and one file is drawn from each stratum.
+    pub fn new(workload: &FolderWorkload, files_ratio: f64, subsets: u64, sampling: Sampling, seed: u64) -> Result<FileSampleSet, MixError> {
+        let mut files = Vec::new();
+        for path in workload.get_data_folder()? {
+            let path = path?;
+            files.push((path.metadata()?.len(), path.path()));
+        }
+        // read_dir doesn't guarantee any consistent order, which would make seeded estimates differ
+        files.sort();
+        if files.is_empty() {
+            return Err(MixError::UnsupportedMode(format!("Folder workload \"{}\" has no files to estimate metrics on", workload.name)));
+        }
+        let total_size = files.iter().map(|(size, _)| size).sum();
+        let count = files.len();
+        let subset_size = ((count as f64 * files_ratio).round() as usize).clamp(1, count);
+        log::debug!("Folder \"{}\": {} files, {} bytes, {} files per subset", workload.name, count, total_size, subset_size);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let subsets = (0..subsets)
+            .map(|_| match sampling {
+                Sampling::Random => index::sample(&mut rng, count, subset_size).into_vec(),
+                Sampling::Stratified => (0..subset_size)
+                    .map(|stratum| rng.gen_range(stratum * count / subset_size..(stratum + 1) * count / subset_size))
+                    .collect(),
+            })
+            .collect();
+        Ok(FileSampleSet { folder_name: workload.name.clone(), files, total_size, subsets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampling::{BlockSampler, Sampling};
+
+    #[test]
+    fn stratified_blocks_dont_overlap() {
+        let mut sampler = BlockSampler::new(10_000, 0.01, 20, Sampling::Stratified, 7);
+        let mut blocks: Vec<_> = (0..20).map(|_| sampler.next_block()).collect();
+        blocks.sort_by_key(|block| block.block_end_index);
+        for (index, block) in blocks.iter().enumerate() {
+            assert_eq!(block.block_size, 100);
+            // Each block stays within its own stratum of 500 bytes
+            let start = block.block_end_index - block.block_size;
+            assert!(start >= index as u64 * 500 && block.block_end_index <= (index as u64 + 1) * 500);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_blocks() {
+        for sampling in [Sampling::Random, Sampling::Stratified] {
+            let mut first = BlockSampler::new(1_000_000, 0.001, 10, sampling, 42);
+            let mut second = BlockSampler::new(1_000_000, 0.001, 10, sampling, 42);
+            for _ in 0..10 {
+                let block = first.next_block();
+                assert!(block.block_end_index >= block.block_size && block.block_end_index <= 1_000_000);
+                assert_eq!(block.block_end_index, second.next_block().block_end_index);
+            }
+        }
+    }
+}
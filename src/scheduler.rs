@@ -0,0 +1,183 @@
+use std::time::Duration;
+use crate::algorithms::AlgorithmMetrics;
+use crate::mixing_policy::{MetricsWithBenefit, OptimalMix};
+
+/// Which part of a workload a job compresses.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Part {
+    Whole,
+    /// The first half of a mix, with the fraction of the workload it covers
+    FirstHalf(f64),
+    /// The second half of a mix, with the fraction of the workload covered by the first half
+    SecondHalf(f64),
+}
+
+/// A part of a workload compressed with a single setup, by a single worker.
+#[derive(Debug, Copy, Clone)]
+pub struct Job<'a> {
+    /// Index of the workload
+    pub workload: usize,
+    pub metrics: &'a AlgorithmMetrics,
+    pub part: Part,
+    /// Expected time required by the job, proportional to the part of the workload it covers
+    pub time_required: Duration,
+}
+
+/// Jobs assigned to each worker, along with the predicted makespan (the load of the busiest worker).
+#[derive(Debug)]
+pub struct Schedule<'a> {
+    pub workers: Vec<Vec<Job<'a>>>,
+    pub predicted_makespan: Duration,
+}
+
+impl Schedule<'_> {
+    pub fn worker_load(&self, worker: usize) -> Duration {
+        self.workers[worker].iter().map(|job| job.time_required).sum()
+    }
+}
+
+/// Splits the optimal mix of each workload into jobs: a whole workload for single setups, and two halves for proper mixes.
+pub fn jobs<'a>(optimal_mixes: &[OptimalMix<'a>]) -> Vec<Job<'a>> {
+    let mut jobs = Vec::with_capacity(optimal_mixes.len() * 2);
+    for (workload, optimal_mix) in optimal_mixes.iter().enumerate() {
+        match optimal_mix {
+            OptimalMix::Single(metrics) => {
+                jobs.push(Job { workload, metrics, part: Part::Whole, time_required: metrics.time_required });
+            }
+            OptimalMix::Normal((metric_a, metric_b), fraction) => {
+                jobs.push(Job { workload, metrics: metric_a, part: Part::FirstHalf(*fraction), time_required: metric_a.time_required.mul_f64(*fraction) });
+                jobs.push(Job { workload, metrics: metric_b, part: Part::SecondHalf(*fraction), time_required: metric_b.time_required.mul_f64(1. - fraction) });
+            }
+        }
+    }
+    jobs
+}
+
+/// Packs the jobs on the workers with the longest processing time first rule: the longest job left is assigned to the least loaded worker.
+/// The resulting makespan is at most 4/3 of the optimal one.
+pub fn pack(mut jobs: Vec<Job>, workers: usize) -> Schedule {
+    let workers = workers.max(1);
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.time_required));
+    let mut schedule = vec![Vec::new(); workers];
+    let mut loads = vec![Duration::ZERO; workers];
+    for job in jobs {
+        let (worker, _) = loads.iter().enumerate().min_by_key(|(_, load)| **load).unwrap();
+        loads[worker] += job.time_required;
+        schedule[worker].push(job);
+    }
+    Schedule { workers: schedule, predicted_makespan: loads.into_iter().max().unwrap() }
+}
+
+/// Chooses the setups of each workload so that the jobs packed on `workers` workers end before the deadline, with the smallest total size.
+///
+/// Every workload starts from its cheapest useful setup. The setups are then upgraded greedily, by highest benefit first, as long as the packed jobs still meet the deadline.
+/// Once no whole upgrade fits, the remaining slack is spent on a proper mix for the workload saving the most bytes.
+/// Can result in a none if even the cheapest setups can't meet the deadline.
+pub fn mixes_within_deadline<'a>(lower_convex_hull_per_workload: &[Vec<MetricsWithBenefit<'a>>], workers: usize, deadline: Duration) -> Option<Vec<OptimalMix<'a>>> {
+    let makespan = |mixes: &[OptimalMix]| pack(jobs(mixes), workers).predicted_makespan;
+    let mut current = vec![0; lower_convex_hull_per_workload.len()];
+    let mixes_of = |current: &[usize]| -> Vec<OptimalMix<'a>> {
+        current
+            .iter()
+            .zip(lower_convex_hull_per_workload)
+            .map(|(index, lch)| OptimalMix::Single(lch[*index].0))
+            .collect()
+    };
+    if makespan(&mixes_of(&current)) > deadline {
+        return None;
+    }
+
+    // Workloads whose next useful setup doesn't fit anymore
+    let mut exhausted = vec![false; current.len()];
+    loop {
+        let mut candidates: Vec<_> = (0..current.len())
+            .filter(|workload| !exhausted[*workload] && current[*workload] + 1 < lower_convex_hull_per_workload[*workload].len())
+            .collect();
+        candidates.sort_by(|a, b| {
+            let benefit = |workload: &usize| lower_convex_hull_per_workload[*workload][current[*workload] + 1].1;
+            benefit(b).total_cmp(&benefit(a))
+        });
+        let mut upgraded = false;
+        for workload in candidates {
+            current[workload] += 1;
+            if makespan(&mixes_of(&current)) <= deadline {
+                log::debug!("Scheduling: workload #{} upgraded to {}", workload, lower_convex_hull_per_workload[workload][current[workload]].0.algorithm.name());
+                upgraded = true;
+                break;
+            }
+            current[workload] -= 1;
+            exhausted[workload] = true;
+        }
+        if !upgraded {
+            break;
+        }
+    }
+
+    // Spend the remaining slack on a mix between the current setup and the next one of a single workload
+    let mut optimal_mixes = mixes_of(&current);
+    let mut best_mix = None;
+    let mut best_saving = 0.;
+    for (workload, lch) in lower_convex_hull_per_workload.iter().enumerate() {
+        let Some(expensive) = lch.get(current[workload] + 1) else { continue };
+        let cheap = lch[current[workload]];
+        for percentage in (1..100).rev() {
+            let fraction = percentage as f64 / 100.;
+            let mut mixes = mixes_of(&current);
+            mixes[workload] = OptimalMix::Normal((expensive.0, cheap.0), fraction);
+            if makespan(&mixes) <= deadline {
+                let saving = fraction * (cheap.0.compressed_size - expensive.0.compressed_size) as f64;
+                if saving > best_saving {
+                    best_saving = saving;
+                    best_mix = Some((workload, OptimalMix::Normal((expensive.0, cheap.0), fraction)));
+                }
+                break;
+            }
+        }
+    }
+    if let Some((workload, mix)) = best_mix {
+        log::debug!("Scheduling: workload #{} mixed as {:?}", workload, mix);
+        optimal_mixes[workload] = mix;
+    }
+    Some(optimal_mixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::algorithms::mock::metrics;
+    use crate::mixing_policy::{MixingPolicy, OptimalMix};
+    use crate::scheduler::{jobs, mixes_within_deadline, pack};
+
+    #[test]
+    fn longest_jobs_first() {
+        let metrics = metrics(&[(10, 5000), (10, 4000), (10, 3000), (10, 3000), (10, 3000)]);
+        let mixes: Vec<_> = metrics.iter().map(OptimalMix::Single).collect();
+        let schedule = pack(jobs(&mixes), 2);
+        // LPT gives 5+3 | 4+3+3
+        assert_eq!(schedule.predicted_makespan, Duration::from_secs(10));
+        assert_eq!(schedule.workers.iter().map(|worker| worker.len()).sum::<usize>(), 5);
+        assert_eq!(schedule.worker_load(0).max(schedule.worker_load(1)), schedule.predicted_makespan);
+    }
+
+    #[test]
+    fn setups_fit_the_deadline_on_every_worker() {
+        let first = metrics(&[(1000, 1000), (600, 3000), (400, 6000)]);
+        let second = metrics(&[(2000, 2000), (1500, 4000), (1200, 8000)]);
+        let lchs = vec![
+            MixingPolicy::new(first.iter().collect()).lower_convex_hull,
+            MixingPolicy::new(second.iter().collect()).lower_convex_hull,
+        ];
+
+        // Each workload gets its own worker, so each one can use up to the deadline
+        let mixes = mixes_within_deadline(&lchs, 2, Duration::from_secs(6)).unwrap();
+        let schedule = pack(jobs(&mixes), 2);
+        assert!(schedule.predicted_makespan <= Duration::from_secs(6));
+        assert!(matches!(mixes[0], OptimalMix::Single(metrics) if metrics.compressed_size == 400));
+
+        // A single worker runs everything serially
+        let serial = mixes_within_deadline(&lchs, 1, Duration::from_secs(6)).unwrap();
+        assert!(pack(jobs(&serial), 1).predicted_makespan <= Duration::from_secs(6));
+
+        assert!(mixes_within_deadline(&lchs, 2, Duration::from_millis(1500)).is_none());
+    }
+}
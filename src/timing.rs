@@ -0,0 +1,236 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+use crate::algorithms::{Algorithm, BlockInfo, ByteSize};
+use crate::container;
+use crate::container::AlgorithmId;
+use crate::error::MixError;
+use crate::workload::Workload;
+
+/// What the time required by a setup measures, and so what the time budget means.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TimingMode {
+    /// Elapsed time of a run, including reading the workload and writing the result to a temporary file.
+    /// It is what a mix actually takes, but it depends on the disks and on the load of the machine
+    #[default]
+    Wall,
+    /// CPU time (user and system) used by the process during a run, from `getrusage`. Not affected by other processes or I/O waits,
+    /// but it counts the time of every worker thread (e.g. multithreaded zstd setups), so it can exceed the wall time
+    Cpu,
+    /// Elapsed time of the compression alone: the data is read in memory beforehand and the result is only counted, never written.
+    /// Setups that don't support it (see [Algorithm::compress_in_memory]) are timed as [TimingMode::Wall]
+    InMemory,
+}
+
+/// How the time required by each setup is measured.
+///
+/// Each measurement (of the whole workload or of a block) runs the setup `warm_up` times without timing it, to fill the page cache and the caches of the cpu,
+/// then `repetitions` times, and keeps the median time. Decompressions are timed the same way (see [Timing::measure_decompression]).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Timing {
+    pub mode: TimingMode,
+    pub warm_up: u32,
+    pub repetitions: u32,
+}
+
+impl Default for Timing {
+    /// A single wall time run, as metrics were always measured.
+    fn default() -> Self {
+        Timing { mode: TimingMode::Wall, warm_up: 0, repetitions: 1 }
+    }
+}
+
+impl Timing {
+    /// The timing actually used for a setup: in memory timing falls back to wall time for setups that can't compress in memory,
+    /// and cpu time falls back to wall time where `getrusage` isn't available.
+    pub fn resolve(self, algorithm: &dyn Algorithm) -> Timing {
+        let supported = match self.mode {
+            TimingMode::Wall => true,
+            TimingMode::Cpu => cpu_time().is_some(),
+            TimingMode::InMemory => algorithm.compress_in_memory(&[]).is_ok(),
+        };
+        if supported {
+            self
+        } else {
+            log::warn!("{:?} timing isn't available for algorithm {}, falling back to wall time", self.mode, algorithm.name());
+            Timing { mode: TimingMode::Wall, ..self }
+        }
+    }
+
+    /// Compresses a block of the workload (the whole workload if none) as described by the timing, and returns the compressed size and the median time required.
+    /// The timing should be [resolved](Timing::resolve) for the setup beforehand.
+    pub fn measure(&self, workload: &mut Workload, algorithm: &dyn Algorithm, block_info: Option<BlockInfo>) -> Result<(ByteSize, Duration), MixError> {
+        let (compressed_size, time, _) = self.measure_with_output(workload, algorithm, block_info)?;
+        Ok((compressed_size, time))
+    }
+
+    /// As [Timing::measure], also returning the compressed data written by the last run (see [Algorithm::execute_on_tmp]), none when compressed in memory.
+    pub fn measure_with_output(&self, workload: &mut Workload, algorithm: &dyn Algorithm, block_info: Option<BlockInfo>) -> Result<(ByteSize, Duration, Option<File>), MixError> {
+        let data = match self.mode {
+            TimingMode::InMemory => Some(read_block(workload, block_info)?),
+            _ => None,
+        };
+        for _ in 0..self.warm_up {
+            self.run(workload, algorithm, block_info, data.as_deref())?;
+        }
+        let mut compressed_size = 0;
+        let mut output = None;
+        let mut times = Vec::with_capacity(self.repetitions.max(1) as usize);
+        for _ in 0..self.repetitions.max(1) {
+            let (size, time, file) = self.run(workload, algorithm, block_info, data.as_deref())?;
+            compressed_size = size;
+            output = file;
+            times.push(time);
+        }
+        Ok((compressed_size, median(times), output))
+    }
+
+    /// Decompresses the output of a setup (see [Algorithm::segment_algorithm]) as described by the timing, and returns the median time required.
+    /// In memory timing reads the compressed data beforehand, the other modes read it from the file. The decompressed data is always discarded.
+    pub fn measure_decompression(&self, algorithm: AlgorithmId, mut compressed: &File) -> Result<Duration, MixError> {
+        let data = match self.mode {
+            TimingMode::InMemory => {
+                let mut data = Vec::new();
+                compressed.rewind()?;
+                compressed.read_to_end(&mut data)?;
+                Some(data)
+            }
+            _ => None,
+        };
+        for _ in 0..self.warm_up {
+            self.decompress(algorithm, compressed, data.as_deref())?;
+        }
+        let mut times = Vec::with_capacity(self.repetitions.max(1) as usize);
+        for _ in 0..self.repetitions.max(1) {
+            times.push(self.decompress(algorithm, compressed, data.as_deref())?);
+        }
+        Ok(median(times))
+    }
+
+    fn run(&self, workload: &mut Workload, algorithm: &dyn Algorithm, block_info: Option<BlockInfo>, data: Option<&[u8]>) -> Result<(ByteSize, Duration, Option<File>), MixError> {
+        match (self.mode, data) {
+            (TimingMode::InMemory, Some(data)) => {
+                let current_unix = Instant::now();
+                let compressed_size = algorithm.compress_in_memory(data)?;
+                Ok((compressed_size, current_unix.elapsed(), None))
+            }
+            (TimingMode::Cpu, _) => {
+                let start = cpu_time().ok_or_else(cpu_time_unavailable)?;
+                let output = algorithm.execute_on_tmp(workload, block_info)?;
+                let time = cpu_time().ok_or_else(cpu_time_unavailable)?.saturating_sub(start);
+                Ok((output.metadata()?.len(), time, Some(output)))
+            }
+            _ => {
+                let current_unix = Instant::now();
+                let output = algorithm.execute_on_tmp(workload, block_info)?;
+                let time = current_unix.elapsed();
+                Ok((output.metadata()?.len(), time, Some(output)))
+            }
+        }
+    }
+
+    fn decompress(&self, algorithm: AlgorithmId, mut compressed: &File, data: Option<&[u8]>) -> Result<Duration, MixError> {
+        match (self.mode, data) {
+            (TimingMode::InMemory, Some(data)) => {
+                let current_unix = Instant::now();
+                container::decode_payload(algorithm, data, &mut io::sink())?;
+                Ok(current_unix.elapsed())
+            }
+            (TimingMode::Cpu, _) => {
+                compressed.rewind()?;
+                let start = cpu_time().ok_or_else(cpu_time_unavailable)?;
+                container::decode_payload(algorithm, compressed, &mut io::sink())?;
+                Ok(cpu_time().ok_or_else(cpu_time_unavailable)?.saturating_sub(start))
+            }
+            _ => {
+                compressed.rewind()?;
+                let current_unix = Instant::now();
+                container::decode_payload(algorithm, compressed, &mut io::sink())?;
+                Ok(current_unix.elapsed())
+            }
+        }
+    }
+
+    /// Describes the timing, to tell apart cache entries measured differently. Empty for the default timing, so that entries cached before timings were configurable stay valid.
+    pub fn cache_parameters(&self) -> String {
+        if *self == Timing::default() {
+            String::new()
+        } else {
+            format!("_{:?}_{}_{}", self.mode, self.warm_up, self.repetitions)
+        }
+    }
+}
+
+fn median(mut times: Vec<Duration>) -> Duration {
+    times.sort();
+    times[times.len() / 2]
+}
+
+fn cpu_time_unavailable() -> MixError {
+    MixError::UnsupportedMode(String::from("CPU time isn't available on this platform"))
+}
+
+/// Reads a block of the workload (the whole workload if none) in memory, restoring the cursor at the start of the file.
+fn read_block(workload: &mut Workload, block_info: Option<BlockInfo>) -> Result<Vec<u8>, MixError> {
+    let workload_size = workload.data.metadata()?.len();
+    let block_info = block_info.unwrap_or(BlockInfo { block_size: workload_size, block_end_index: workload_size });
+    let mut data = vec![0; block_info.block_size as usize];
+    workload.data.seek(SeekFrom::Start(block_info.block_end_index - block_info.block_size))?;
+    workload.data.read_exact(&mut data)?;
+    workload.data.rewind()?;
+    Ok(data)
+}
+
+/// User and system time used by the process so far.
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only fills the given struct, which is fully initialized (zeroed) beforehand
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: the struct was zeroed, and filled by getrusage
+    let usage = unsafe { usage.assume_init() };
+    let duration = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    Some(duration(usage.ru_utime) + duration(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Seek, Write};
+    use std::time::Duration;
+    use tempfile::tempfile;
+    use crate::algorithms::{Algorithm, BlockInfo};
+    use crate::algorithms::gzip::{Gzip, GzipCompressionLevel};
+    use crate::container::AlgorithmId;
+    use crate::timing::{Timing, TimingMode};
+    use crate::workload::Workload;
+
+    #[test]
+    fn every_mode_measures_the_same_size() {
+        let mut data = tempfile().unwrap();
+        data.write_all("Tanto gentile e tanto onesta pare la donna mia quand'ella altrui saluta".repeat(500).as_bytes()).unwrap();
+        data.rewind().unwrap();
+        let mut workload = Workload::new(String::from("timing"), data, Duration::ZERO, Some(tempfile().unwrap())).unwrap();
+        let algorithm = Gzip::new(GzipCompressionLevel(6));
+        let block_info = Some(BlockInfo { block_size: 1000, block_end_index: 3000 });
+        let compressed = algorithm.execute_on_tmp(&mut workload, block_info).unwrap();
+        let expected = compressed.metadata().unwrap().len();
+        for mode in [TimingMode::Wall, TimingMode::Cpu, TimingMode::InMemory] {
+            let timing = Timing { mode, warm_up: 1, repetitions: 3 }.resolve(&algorithm);
+            assert_eq!(timing.mode, mode);
+            let (compressed_size, _, output) = timing.measure_with_output(&mut workload, &algorithm, block_info).unwrap();
+            assert_eq!(compressed_size, expected);
+            // Only runs writing their result keep it
+            assert_eq!(output.is_some(), mode != TimingMode::InMemory);
+            timing.measure_decompression(AlgorithmId::Gzip, output.as_ref().unwrap_or(&compressed)).unwrap();
+        }
+        assert_eq!(Timing::default().cache_parameters(), "");
+    }
+}
@@ -1,4 +1,7 @@
-use std::fs::{create_dir, create_dir_all, File, read_dir, ReadDir};
+use std::fs::{create_dir, create_dir_all, File, OpenOptions, read_dir, ReadDir};
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 /// Defines the structure of a workload, containing the data to be compressed, the time budget and the algorithms to use.
@@ -7,17 +10,44 @@ pub struct Workload {
     pub name: String,
     pub data: File,
     pub time_budget: Duration,
-    pub result_file: File
+    pub result_file: File,
+    /// Path of the data, if it was opened from one (see [Workload::open])
+    pub data_path: Option<PathBuf>,
 }
 
 impl Workload {
-    pub fn new(name: String, data: File, time_budget: Duration, result_file: Option<File>) -> Self {
-        let result_file = result_file.unwrap_or_else(|| File::create(format!("results/{}.zip", name))
-            .expect(format!("Couldn't create result file for workload \"{}\"", name).as_str()));
-        Self { name, data, time_budget, result_file }
+    /// Creates the workload, along with its result file in `results/` if none is given.
+    pub fn new(name: String, data: File, time_budget: Duration, result_file: Option<File>) -> io::Result<Self> {
+        let result_file = match result_file {
+            Some(result_file) => result_file,
+            // The result file is also read back, to append segments to its mixed container
+            None => OpenOptions::new().read(true).write(true).create(true).truncate(true).open(format!("results/{}.zip", name))
+                .map_err(|e| io::Error::new(e.kind(), format!("Couldn't create result file for workload \"{}\": {}", name, e)))?,
+        };
+        Ok(Self { name, data, time_budget, result_file, data_path: None })
+    }
+
+    /// Opens the data at the given path, which is kept to reopen the data cheaply.
+    pub fn open(name: String, path: impl AsRef<Path>, time_budget: Duration, result_file: Option<File>) -> io::Result<Self> {
+        let data = File::open(path.as_ref())?;
+        Ok(Self { data_path: Some(path.as_ref().to_path_buf()), ..Self::new(name, data, time_budget, result_file)? })
     }
-}
 
+    /// Opens a new handle on the data, with its own cursor (unlike `File::try_clone`), so that different parts of the workload can be read concurrently.
+    /// Data without a path (e.g. a temporary file) is copied to a new temporary file.
+    pub fn reopen_data(&self) -> io::Result<File> {
+        if let Some(path) = &self.data_path {
+            return File::open(path);
+        }
+        let mut data = &self.data;
+        let mut copy = tempfile::tempfile()?;
+        data.seek(SeekFrom::Start(0))?;
+        io::copy(&mut data, &mut copy)?;
+        data.seek(SeekFrom::Start(0))?;
+        copy.seek(SeekFrom::Start(0))?;
+        Ok(copy)
+    }
+}
 
 #[derive(Debug)]
 pub struct FolderWorkload {
@@ -26,29 +56,29 @@ pub struct FolderWorkload {
 }
 
 impl FolderWorkload {
-    pub fn new(name: String, time_budget: Duration) -> Self {
+    /// Creates the workload, along with its result folder in `results/`.
+    pub fn new(name: String, time_budget: Duration) -> io::Result<Self> {
         create_dir(format!("results/{}", name))
-            .expect(format!("Couldn't create result folder for workload \"{}\"", name).as_str());
-        Self { name, time_budget }
+            .map_err(|e| io::Error::new(e.kind(), format!("Couldn't create result folder for workload \"{}\": {}", name, e)))?;
+        Ok(Self { name, time_budget })
     }
 
-    pub fn get_data_folder(&self) -> ReadDir {
+    pub fn get_data_folder(&self) -> io::Result<ReadDir> {
         read_dir(format!("data/{}", self.name))
-            .expect(format!("Couldn't read data folder for workload \"{}\"", self.name).as_str())
     }
 
-    pub fn data_files_count(&self) -> usize {
-        let data_folder = self.get_data_folder();
-        data_folder.count()
+    pub fn data_files_count(&self) -> io::Result<usize> {
+        let data_folder = self.get_data_folder()?;
+        Ok(data_folder.count())
     }
 
-    pub fn data_files_size(&self) -> u64 {
-        let data_folder = self.get_data_folder();
+    pub fn data_files_size(&self) -> io::Result<u64> {
+        let data_folder = self.get_data_folder()?;
         let mut size = 0;
         for path in data_folder {
-            size += path.unwrap().metadata().unwrap().len();
+            size += path?.metadata()?.len();
         }
-        size
+        Ok(size)
     }
 
     pub fn get_results_folder(&self) {